@@ -0,0 +1,171 @@
+use std::collections::HashMap;
+use crate::backend::bytecode::{Builtin, Bytecode, Opcode, Width};
+use crate::ir::instruction::{Instruction, Label, LocalId, Operand};
+use crate::ir::Program;
+use crate::parser::r#type::ValueType;
+use crate::tokenizer::token::Operator;
+
+/// Lowers the IR to bytecode, expects a program without phis. Locals whose address is taken and aggregates
+/// live in the memory after the data, all other locals get a slot of their own.
+pub struct BytecodeGenerator<'a> {
+    program: &'a Program,
+    /// Addresses of the data and of the locals living in memory.
+    data_addresses: Vec<u64>,
+    local_addresses: HashMap<LocalId, u64>,
+    code: Vec<Opcode>,
+    /// Jumps waiting for the position of their label.
+    fixups: Vec<(usize, Label)>,
+    labels: HashMap<Label, u32>,
+}
+
+impl<'a> BytecodeGenerator<'a> {
+    pub fn new(program: &'a Program) -> Self {
+        BytecodeGenerator { program, data_addresses: vec![], local_addresses: HashMap::new(), code: vec![], fixups: vec![], labels: HashMap::new() }
+    }
+
+    pub fn generate(mut self) -> Bytecode {
+        let mut data = vec![];
+        for bytes in &self.program.data {
+            self.data_addresses.push(data.len() as u64);
+            data.extend(bytes);
+        }
+
+        let registers = self.program.register_locals(&self.program.instructions);
+        let mut memory_size = data.len() as u64;
+        for (local, value) in self.program.locals.iter().enumerate() {
+            if !registers.contains(&local) {
+                let size = value.type_.size_of();
+                let alignment = size.clamp(1, 8);
+                memory_size = memory_size.div_ceil(alignment) * alignment;
+                self.local_addresses.insert(local, memory_size);
+                memory_size += size;
+            }
+        }
+
+        for instruction in &self.program.instructions {
+            self.generate_instruction(instruction);
+        }
+        // Falling off the end of the program exits successfully
+        self.code.push(Opcode::Push(0));
+        self.code.push(Opcode::Call(Builtin::Exit));
+
+        for (position, label) in std::mem::take(&mut self.fixups) {
+            let target = self.labels[&label];
+            match &mut self.code[position] {
+                Opcode::Jump(jump) | Opcode::JumpIfTrue(jump) => *jump = target,
+                _ => unreachable!(),
+            }
+        }
+        Bytecode { local_count: self.program.locals.len() as u32, memory_size, data, code: self.code }
+    }
+
+    fn generate_instruction(&mut self, instruction: &Instruction) {
+        match instruction {
+            Instruction::Move { destination, source } => {
+                self.push(source);
+                self.store(*destination);
+            }
+            Instruction::Binary { destination, lhs, operator, rhs } => {
+                let width = self.width(&self.program.locals[*destination].type_);
+                self.push(lhs);
+                self.push(rhs);
+                self.code.push(match operator {
+                    Operator::Plus => Opcode::Add(width),
+                    Operator::Minus => Opcode::Sub(width),
+                    Operator::Times => Opcode::Mul(width),
+                    Operator::Divide => Opcode::Div(width),
+                    Operator::Equal => Opcode::Equal,
+                    Operator::NotEqual => Opcode::NotEqual,
+                    Operator::Less => Opcode::Less,
+                    Operator::Greater => Opcode::Greater,
+                    Operator::LessEqual => Opcode::LessEqual,
+                    Operator::GreaterEqual => Opcode::GreaterEqual,
+                    _ => unreachable!("unsupported binary operator {:?}", operator),
+                });
+                self.store(*destination);
+            }
+            Instruction::Cast { destination, value, to, .. } => {
+                self.push(value);
+                self.code.push(Opcode::Cast(self.width(to)));
+                self.store(*destination);
+            }
+            Instruction::AddressOf { destination, local } => {
+                self.code.push(Opcode::Push(self.local_addresses[local]));
+                self.store(*destination);
+            }
+            Instruction::DataAddress { destination, data } => {
+                self.code.push(Opcode::Push(self.data_addresses[*data]));
+                self.store(*destination);
+            }
+            Instruction::Load { destination, address } => {
+                self.push(address);
+                self.code.push(Opcode::Load(self.width(&self.program.locals[*destination].type_)));
+                self.store(*destination);
+            }
+            Instruction::Store { address, value } => {
+                self.push(value);
+                self.push(address);
+                self.code.push(Opcode::Store(self.width(&self.program.operand_type(value))));
+            }
+            Instruction::Label { label } => {
+                self.labels.insert(*label, self.code.len() as u32);
+            }
+            Instruction::Jump { label } => self.jump(Opcode::Jump(0), *label),
+            Instruction::Branch { condition, if_true, if_false } => {
+                self.push(condition);
+                self.jump(Opcode::JumpIfTrue(0), *if_true);
+                self.jump(Opcode::Jump(0), *if_false);
+            }
+            Instruction::Exit { value } => {
+                self.push(value);
+                self.code.push(Opcode::Call(Builtin::Exit));
+            }
+            Instruction::Print { value } => {
+                self.push(value);
+                self.code.push(Opcode::Call(match self.program.operand_type(value) {
+                    ValueType::Bool => Builtin::PrintBool,
+                    _ => Builtin::PrintInteger,
+                }));
+            }
+            Instruction::PrintString { address, len } => {
+                self.push(address);
+                self.push(len);
+                self.code.push(Opcode::Call(Builtin::Write));
+            }
+            Instruction::Phi { .. } => unreachable!("phis are removed before generating bytecode"),
+        }
+    }
+
+    fn jump(&mut self, opcode: Opcode, label: Label) {
+        self.fixups.push((self.code.len(), label));
+        self.code.push(opcode);
+    }
+
+    fn push(&mut self, operand: &Operand) {
+        match operand {
+            Operand::Constant { value, .. } => self.code.push(Opcode::Push(*value)),
+            Operand::Local(local) => match self.local_addresses.get(local) {
+                Some(address) => {
+                    self.code.push(Opcode::Push(*address));
+                    self.code.push(Opcode::Load(self.width(&self.program.locals[*local].type_)));
+                }
+                None => self.code.push(Opcode::LoadLocal(*local as u32)),
+            },
+        }
+    }
+
+    /// Pops the value on top of the stack into the local.
+    fn store(&mut self, local: LocalId) {
+        match self.local_addresses.get(&local) {
+            Some(address) => {
+                self.code.push(Opcode::Push(*address));
+                self.code.push(Opcode::Store(self.width(&self.program.locals[local].type_)));
+            }
+            None => self.code.push(Opcode::StoreLocal(local as u32)),
+        }
+    }
+
+    fn width(&self, type_: &ValueType) -> Width {
+        Width::from_size(type_.size_of())
+    }
+}
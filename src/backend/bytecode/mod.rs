@@ -0,0 +1,284 @@
+use std::fmt::{Display, Formatter};
+use anyhow::{bail, Context};
+
+pub mod generator;
+pub mod vm;
+
+pub const MAGIC: &[u8; 4] = b"HEBC";
+/// Bumped whenever the encoding changes, older files are rejected instead of misread.
+pub const VERSION: u16 = 1;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Width {
+    W8,
+    W16,
+    W32,
+    W64,
+}
+
+impl Width {
+    pub fn from_size(size: u64) -> Width {
+        match size {
+            1 => Width::W8,
+            2 => Width::W16,
+            4 => Width::W32,
+            _ => Width::W64,
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        match self {
+            Width::W8 => 1,
+            Width::W16 => 2,
+            Width::W32 => 4,
+            Width::W64 => 8,
+        }
+    }
+
+    pub fn wrap(&self, value: u64) -> u64 {
+        match self {
+            Width::W64 => value,
+            width => value & ((1 << (width.size() * 8)) - 1),
+        }
+    }
+
+    fn encode(&self) -> u8 {
+        *self as u8
+    }
+
+    fn decode(byte: u8) -> anyhow::Result<Width> {
+        Ok(match byte {
+            0 => Width::W8,
+            1 => Width::W16,
+            2 => Width::W32,
+            3 => Width::W64,
+            _ => bail!("Unknown width {}!", byte),
+        })
+    }
+}
+
+/// Functions provided by the virtual machine, their arguments are popped from the stack.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Builtin {
+    /// Pops the exit code and stops the program.
+    Exit,
+    PrintInteger,
+    PrintBool,
+    /// Pops the length and then the address of the bytes to write.
+    Write,
+}
+
+/// Values on the stack and in the local slots are zero extended to 64 bits, only memory is accessed per width.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Opcode {
+    Push(u64),
+    LoadLocal(u32),
+    StoreLocal(u32),
+    /// Pops an address and pushes the value stored there.
+    Load(Width),
+    /// Pops an address and then the value to store there.
+    Store(Width),
+    Add(Width),
+    Sub(Width),
+    Mul(Width),
+    Div(Width),
+    Equal,
+    NotEqual,
+    Less,
+    Greater,
+    LessEqual,
+    GreaterEqual,
+    /// Truncates the value to the width.
+    Cast(Width),
+    Jump(u32),
+    /// Pops a bool and jumps if it is true.
+    JumpIfTrue(u32),
+    Call(Builtin),
+}
+
+/// A compiled program, the memory starts out with the data and is zeroed after it.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Bytecode {
+    pub local_count: u32,
+    pub memory_size: u64,
+    pub data: Vec<u8>,
+    pub code: Vec<Opcode>,
+}
+
+impl Builtin {
+    fn decode(byte: u8) -> anyhow::Result<Builtin> {
+        Ok(match byte {
+            0 => Builtin::Exit,
+            1 => Builtin::PrintInteger,
+            2 => Builtin::PrintBool,
+            3 => Builtin::Write,
+            _ => bail!("Unknown builtin {}!", byte),
+        })
+    }
+}
+
+impl Bytecode {
+    /// The file format: magic, version, local count, memory size, data and code, integers in little endian.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend(VERSION.to_le_bytes());
+        bytes.extend(self.local_count.to_le_bytes());
+        bytes.extend(self.memory_size.to_le_bytes());
+        bytes.extend((self.data.len() as u32).to_le_bytes());
+        bytes.extend(&self.data);
+        bytes.extend((self.code.len() as u32).to_le_bytes());
+        for opcode in &self.code {
+            match opcode {
+                Opcode::Push(value) => {
+                    bytes.push(0);
+                    bytes.extend(value.to_le_bytes());
+                }
+                Opcode::LoadLocal(slot) => {
+                    bytes.push(1);
+                    bytes.extend(slot.to_le_bytes());
+                }
+                Opcode::StoreLocal(slot) => {
+                    bytes.push(2);
+                    bytes.extend(slot.to_le_bytes());
+                }
+                Opcode::Load(width) => bytes.extend([3, width.encode()]),
+                Opcode::Store(width) => bytes.extend([4, width.encode()]),
+                Opcode::Add(width) => bytes.extend([5, width.encode()]),
+                Opcode::Sub(width) => bytes.extend([6, width.encode()]),
+                Opcode::Mul(width) => bytes.extend([7, width.encode()]),
+                Opcode::Div(width) => bytes.extend([8, width.encode()]),
+                Opcode::Equal => bytes.push(9),
+                Opcode::NotEqual => bytes.push(10),
+                Opcode::Less => bytes.push(11),
+                Opcode::Greater => bytes.push(12),
+                Opcode::LessEqual => bytes.push(13),
+                Opcode::GreaterEqual => bytes.push(14),
+                Opcode::Cast(width) => bytes.extend([15, width.encode()]),
+                Opcode::Jump(target) => {
+                    bytes.push(16);
+                    bytes.extend(target.to_le_bytes());
+                }
+                Opcode::JumpIfTrue(target) => {
+                    bytes.push(17);
+                    bytes.extend(target.to_le_bytes());
+                }
+                Opcode::Call(builtin) => bytes.extend([18, *builtin as u8]),
+            }
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> anyhow::Result<Bytecode> {
+        let mut reader = Reader { bytes, position: 0 };
+        if reader.take(4)? != MAGIC { bail!("Not a bytecode file, the magic header is missing!") }
+        let version = u16::from_le_bytes(reader.array()?);
+        if version != VERSION { bail!("Unsupported bytecode version {}, expected {}!", version, VERSION) }
+
+        let local_count = u32::from_le_bytes(reader.array()?);
+        let memory_size = u64::from_le_bytes(reader.array()?);
+        let data_len = u32::from_le_bytes(reader.array()?) as usize;
+        let data = reader.take(data_len)?.to_vec();
+        if data.len() as u64 > memory_size { bail!("The data doesnt fit into the memory!") }
+
+        let code_len = u32::from_le_bytes(reader.array()?);
+        let mut code = vec![];
+        for _ in 0..code_len {
+            let opcode = match reader.take(1)?[0] {
+                0 => Opcode::Push(u64::from_le_bytes(reader.array()?)),
+                1 => Opcode::LoadLocal(u32::from_le_bytes(reader.array()?)),
+                2 => Opcode::StoreLocal(u32::from_le_bytes(reader.array()?)),
+                3 => Opcode::Load(Width::decode(reader.take(1)?[0])?),
+                4 => Opcode::Store(Width::decode(reader.take(1)?[0])?),
+                5 => Opcode::Add(Width::decode(reader.take(1)?[0])?),
+                6 => Opcode::Sub(Width::decode(reader.take(1)?[0])?),
+                7 => Opcode::Mul(Width::decode(reader.take(1)?[0])?),
+                8 => Opcode::Div(Width::decode(reader.take(1)?[0])?),
+                9 => Opcode::Equal,
+                10 => Opcode::NotEqual,
+                11 => Opcode::Less,
+                12 => Opcode::Greater,
+                13 => Opcode::LessEqual,
+                14 => Opcode::GreaterEqual,
+                15 => Opcode::Cast(Width::decode(reader.take(1)?[0])?),
+                16 => Opcode::Jump(u32::from_le_bytes(reader.array()?)),
+                17 => Opcode::JumpIfTrue(u32::from_le_bytes(reader.array()?)),
+                18 => Opcode::Call(Builtin::decode(reader.take(1)?[0])?),
+                byte => bail!("Unknown opcode {} at byte {}!", byte, reader.position - 1),
+            };
+            code.push(opcode);
+        }
+        if reader.position != bytes.len() { bail!("Unexpected bytes after the end of the code!") }
+        Ok(Bytecode { local_count, memory_size, data, code })
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> anyhow::Result<&'a [u8]> {
+        let bytes = self.bytes.get(self.position..self.position + len).context("The bytecode file ends unexpectedly!")?;
+        self.position += len;
+        Ok(bytes)
+    }
+
+    fn array<const N: usize>(&mut self) -> anyhow::Result<[u8; N]> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+}
+
+impl Display for Bytecode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "; version {}, {} locals, {} bytes of memory", VERSION, self.local_count, self.memory_size)?;
+        for (index, opcode) in self.code.iter().enumerate() {
+            writeln!(f, "{:>5}: {:?}", index, opcode)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn every_opcode() -> Bytecode {
+        let code = vec![
+            Opcode::Push(u64::MAX), Opcode::LoadLocal(1), Opcode::StoreLocal(2),
+            Opcode::Load(Width::W8), Opcode::Store(Width::W16), Opcode::Add(Width::W32), Opcode::Sub(Width::W64),
+            Opcode::Mul(Width::W8), Opcode::Div(Width::W16),
+            Opcode::Equal, Opcode::NotEqual, Opcode::Less, Opcode::Greater, Opcode::LessEqual, Opcode::GreaterEqual,
+            Opcode::Cast(Width::W32), Opcode::Jump(7), Opcode::JumpIfTrue(u32::MAX),
+            Opcode::Call(Builtin::Exit), Opcode::Call(Builtin::PrintInteger), Opcode::Call(Builtin::PrintBool), Opcode::Call(Builtin::Write),
+        ];
+        Bytecode { local_count: 3, memory_size: 16, data: b"hello".to_vec(), code }
+    }
+
+    #[test]
+    fn bytes_round_trip() {
+        let bytecode = every_opcode();
+        assert_eq!(Bytecode::from_bytes(&bytecode.to_bytes()).unwrap(), bytecode);
+        assert_eq!(Bytecode::from_bytes(&Bytecode::default().to_bytes()).unwrap(), Bytecode::default());
+    }
+
+    #[test]
+    fn other_versions_are_rejected() {
+        let mut bytes = every_opcode().to_bytes();
+        bytes[MAGIC.len()..MAGIC.len() + 2].copy_from_slice(&(VERSION + 1).to_le_bytes());
+        let error = Bytecode::from_bytes(&bytes).unwrap_err();
+        assert!(error.to_string().contains("Unsupported bytecode version"), "{}", error);
+    }
+
+    #[test]
+    fn malformed_files_are_rejected() {
+        let bytes = every_opcode().to_bytes();
+        assert!(Bytecode::from_bytes(&bytes[1..]).is_err());
+        assert!(Bytecode::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        assert!(Bytecode::from_bytes(&[bytes.as_slice(), &[0]].concat()).is_err());
+
+        let mut unknown_opcode = Bytecode { code: vec![Opcode::Equal], ..Bytecode::default() }.to_bytes();
+        *unknown_opcode.last_mut().unwrap() = 255;
+        assert!(Bytecode::from_bytes(&unknown_opcode).is_err());
+    }
+}
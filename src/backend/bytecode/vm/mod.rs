@@ -0,0 +1,97 @@
+use std::io::Write;
+use anyhow::{bail, Context};
+use crate::backend::bytecode::{Builtin, Bytecode, Opcode, Width};
+
+/// Runs bytecode, everything the program prints goes to the writer.
+pub struct Vm<'a> {
+    bytecode: &'a Bytecode,
+    stack: Vec<u64>,
+    locals: Vec<u64>,
+    memory: Vec<u8>,
+}
+
+impl<'a> Vm<'a> {
+    pub fn new(bytecode: &'a Bytecode) -> Self {
+        let mut memory = bytecode.data.clone();
+        memory.resize(bytecode.memory_size as usize, 0);
+        Vm { bytecode, stack: vec![], locals: vec![0; bytecode.local_count as usize], memory }
+    }
+
+    /// Runs the program until it exits and returns its exit code.
+    pub fn run(&mut self, output: &mut dyn Write) -> anyhow::Result<u64> {
+        let mut position = 0;
+        loop {
+            let opcode = *self.bytecode.code.get(position).context("Jumped past the end of the code!")?;
+            position += 1;
+            match opcode {
+                Opcode::Push(value) => self.stack.push(value),
+                Opcode::LoadLocal(slot) => {
+                    let value = *self.locals.get(slot as usize).context("Unknown local slot!")?;
+                    self.stack.push(value);
+                }
+                Opcode::StoreLocal(slot) => {
+                    let value = self.pop()?;
+                    *self.locals.get_mut(slot as usize).context("Unknown local slot!")? = value;
+                }
+                Opcode::Load(width) => {
+                    let address = self.pop()?;
+                    let bytes = self.memory(address, width)?;
+                    let mut value = [0; 8];
+                    value[..width.size()].copy_from_slice(bytes);
+                    self.stack.push(u64::from_le_bytes(value));
+                }
+                Opcode::Store(width) => {
+                    let address = self.pop()?;
+                    let value = self.pop()?;
+                    self.memory(address, width)?.copy_from_slice(&value.to_le_bytes()[..width.size()]);
+                }
+                Opcode::Add(width) => self.binary(|lhs, rhs| Ok(width.wrap(lhs.wrapping_add(rhs))))?,
+                Opcode::Sub(width) => self.binary(|lhs, rhs| Ok(width.wrap(lhs.wrapping_sub(rhs))))?,
+                Opcode::Mul(width) => self.binary(|lhs, rhs| Ok(width.wrap(lhs.wrapping_mul(rhs))))?,
+                Opcode::Div(_) => self.binary(|lhs, rhs| lhs.checked_div(rhs).context("Division by zero!"))?,
+                Opcode::Equal => self.binary(|lhs, rhs| Ok((lhs == rhs) as u64))?,
+                Opcode::NotEqual => self.binary(|lhs, rhs| Ok((lhs != rhs) as u64))?,
+                Opcode::Less => self.binary(|lhs, rhs| Ok((lhs < rhs) as u64))?,
+                Opcode::Greater => self.binary(|lhs, rhs| Ok((lhs > rhs) as u64))?,
+                Opcode::LessEqual => self.binary(|lhs, rhs| Ok((lhs <= rhs) as u64))?,
+                Opcode::GreaterEqual => self.binary(|lhs, rhs| Ok((lhs >= rhs) as u64))?,
+                Opcode::Cast(width) => {
+                    let value = self.pop()?;
+                    self.stack.push(width.wrap(value));
+                }
+                Opcode::Jump(target) => position = target as usize,
+                Opcode::JumpIfTrue(target) => if self.pop()? != 0 { position = target as usize },
+                Opcode::Call(Builtin::Exit) => return self.pop(),
+                Opcode::Call(Builtin::PrintInteger) => write!(output, "{}", self.pop()?)?,
+                Opcode::Call(Builtin::PrintBool) => write!(output, "{}", self.pop()? != 0)?,
+                Opcode::Call(Builtin::Write) => {
+                    let len = self.pop()?;
+                    let address = self.pop()?;
+                    let bytes = address.checked_add(len)
+                        .and_then(|end| self.memory.get(address as usize..end as usize))
+                        .context("Writing memory out of bounds!")?;
+                    output.write_all(bytes)?;
+                }
+            }
+        }
+    }
+
+    fn pop(&mut self) -> anyhow::Result<u64> {
+        self.stack.pop().context("Popped from an empty stack!")
+    }
+
+    fn binary(&mut self, operation: impl Fn(u64, u64) -> anyhow::Result<u64>) -> anyhow::Result<()> {
+        let rhs = self.pop()?;
+        let lhs = self.pop()?;
+        self.stack.push(operation(lhs, rhs)?);
+        Ok(())
+    }
+
+    fn memory(&mut self, address: u64, width: Width) -> anyhow::Result<&mut [u8]> {
+        let start = address as usize;
+        match self.memory.get_mut(start..start.saturating_add(width.size())) {
+            Some(bytes) => Ok(bytes),
+            None => bail!("Accessing memory out of bounds at address {}!", address),
+        }
+    }
+}
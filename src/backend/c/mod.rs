@@ -0,0 +1,428 @@
+use std::collections::HashMap;
+use crate::ir::generator::{BOUNDS_CHECK_EXIT_CODE, DIVISION_BY_ZERO_EXIT_CODE};
+use crate::parser::expr::Expression;
+use crate::parser::r#type::ValueType;
+use crate::parser::stmt::Statement;
+use crate::tokenizer::token::{Literal, literal_to_char, literal_to_string, Operator, string_literal_bytes};
+
+/// Types and helpers every translated program starts with, slices and strings become `he_slice`.
+const PRELUDE: &str = r#"#include <inttypes.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+typedef struct { void *pointer; uint64_t len; } he_slice;
+
+static inline uint64_t he_check(uint64_t index, uint64_t len) {
+    if (index >= len) exit(BOUNDS_CHECK_EXIT_CODE);
+    return index;
+}
+
+static inline he_slice he_subslice(he_slice value, uint64_t start, uint64_t end, uint64_t size, bool checked) {
+    if (checked && (end > value.len || start > end)) exit(BOUNDS_CHECK_EXIT_CODE);
+    return (he_slice) { (char *) value.pointer + start * size, end - start };
+}
+
+static inline uint64_t he_divisor(uint64_t divisor) {
+    if (divisor == 0) exit(DIVISION_BY_ZERO_EXIT_CODE);
+    return divisor;
+}
+
+static inline uint32_t he_char(uint64_t value) {
+    return value < 0xD800 || (value > 0xDFFF && value <= 0x10FFFF) ? (uint32_t) value : 0xFFFD;
+}
+
+static inline bool he_str_equal(he_slice lhs, he_slice rhs) {
+    return lhs.len == rhs.len && memcmp(lhs.pointer, rhs.pointer, lhs.len) == 0;
+}
+
+static inline void he_print_char(uint32_t value) {
+    unsigned char bytes[4];
+    size_t len = value < 0x80 ? 1 : value < 0x800 ? 2 : value < 0x10000 ? 3 : 4;
+    static const unsigned char prefixes[] = { 0x00, 0xC0, 0xE0, 0xF0 };
+    for (size_t index = len - 1; index > 0; index--) {
+        bytes[index] = 0x80 | (value & 0x3F);
+        value >>= 6;
+    }
+    bytes[0] = prefixes[len - 1] | value;
+    fwrite(bytes, 1, len, stdout);
+}
+
+static inline void he_print_chars(he_slice chars) {
+    for (uint64_t index = 0; index < chars.len; index++) he_print_char(((uint32_t *) chars.pointer)[index]);
+}
+
+static inline void he_print_str(he_slice str) {
+    fwrite(str.pointer, 1, str.len, stdout);
+}
+"#;
+
+/// Translates resolved statements into a C99 program.
+pub struct CGenerator {
+    lines: Vec<String>,
+    /// The C name each variable currently refers to, shadowed variables get a new name.
+    variables: HashMap<Literal, String>,
+    name_counts: HashMap<String, usize>,
+    temporary_count: usize,
+    bounds_checks: bool,
+}
+
+impl Default for CGenerator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CGenerator {
+    pub fn new() -> Self {
+        Self {
+            lines: vec![],
+            variables: HashMap::new(),
+            name_counts: HashMap::new(),
+            temporary_count: 0,
+            bounds_checks: false,
+        }
+    }
+
+    pub fn with_bounds_checks(mut self, bounds_checks: bool) -> Self {
+        self.bounds_checks = bounds_checks;
+        self
+    }
+
+    pub fn generate(mut self, statements: &[Statement]) -> String {
+        for statement in statements {
+            self.generate_statement(statement);
+        }
+        let mut source = PRELUDE.replace("BOUNDS_CHECK_EXIT_CODE", &BOUNDS_CHECK_EXIT_CODE.to_string())
+            .replace("DIVISION_BY_ZERO_EXIT_CODE", &DIVISION_BY_ZERO_EXIT_CODE.to_string());
+        source.push_str("\nint main(void) {\n");
+        for line in &self.lines {
+            source.push_str("    ");
+            source.push_str(line);
+            source.push('\n');
+        }
+        source.push_str("    return 0;\n}\n");
+        source
+    }
+
+    fn generate_statement(&mut self, statement: &Statement) {
+        match statement {
+            Statement::Let { identifier, type_, expression, .. } => {
+                let type_ = type_.as_ref().expect("Let statements are typed by the semantic analysis!");
+                let base = literal_to_string(identifier);
+                let count = self.name_counts.entry(base.clone()).or_insert(0);
+                let name = format!("{}_{}", base, count);
+                *count += 1;
+
+                match expression {
+                    Some(expression) if matches!(type_, ValueType::Array { .. }) => {
+                        self.lines.push(format!("{};", declare(type_, &name)));
+                        self.generate_aggregate(&name, expression);
+                    }
+                    Some(expression) => {
+                        let value = self.generate_expression(expression);
+                        self.lines.push(format!("{} = {};", declare(type_, &name), value));
+                    }
+                    None => self.lines.push(format!("{};", declare(type_, &name))),
+                }
+                // Registered afterwards so the initializer still sees a shadowed variable
+                self.variables.insert(identifier.clone(), name);
+            }
+            Statement::Assign { assignee, operator, expression } => {
+                let target = match assignee {
+                    Expression::Reference { reference } => self.generate_place(reference),
+                    _ => format!("(*{})", self.generate_expression(assignee)),
+                };
+                if let ValueType::Array { .. } = expression.get_type() {
+                    self.generate_aggregate(&target, expression);
+                    return;
+                }
+                let value = match operator {
+                    Some(_) => self.generate_compound_value(expression, &target),
+                    None => self.generate_expression(expression),
+                };
+                self.lines.push(format!("{} = {};", target, value));
+            }
+            Statement::Const { .. } => {}
+            Statement::Exit { expression, .. } => {
+                let value = self.generate_expression(expression);
+                self.lines.push(format!("exit((int) {});", value));
+            }
+            Statement::Print { expression, .. } => match expression.get_type() {
+                ValueType::Array { len, .. } => {
+                    let place = self.generate_place(expression);
+                    self.lines.push(format!("he_print_chars((he_slice) {{ {}, {} }});", place, len));
+                    self.lines.push("fputs(\"\\n\", stdout);".to_string());
+                }
+                type_ @ (ValueType::Slice { .. } | ValueType::Str | ValueType::Char) => {
+                    let value = self.generate_expression(expression);
+                    let function = match type_ {
+                        ValueType::Char => "he_print_char",
+                        ValueType::Str => "he_print_str",
+                        _ => "he_print_chars",
+                    };
+                    self.lines.push(format!("{}({});", function, value));
+                    self.lines.push("fputs(\"\\n\", stdout);".to_string());
+                }
+                ValueType::Bool => {
+                    let value = self.generate_expression(expression);
+                    self.lines.push(format!("fputs({} ? \"true\\n\" : \"false\\n\", stdout);", value));
+                }
+                _ => {
+                    let value = self.generate_expression(expression);
+                    self.lines.push(format!("printf(\"%\" PRIu64 \"\\n\", (uint64_t) {});", value));
+                }
+            },
+            Statement::Expression { expression } => {
+                let value = self.generate_expression(expression);
+                self.lines.push(format!("(void) {};", value));
+            }
+        }
+    }
+
+    /// The C expression for the value, arrays are the place holding them.
+    fn generate_expression(&mut self, expression: &Expression) -> String {
+        match expression {
+            Expression::NumberLiteral { value, internal_type, .. } => match internal_type {
+                ValueType::U64 => format!("UINT64_C({})", literal_to_string(value)),
+                _ => format!("{}u", literal_to_string(value)),
+            },
+            Expression::CharLiteral { value, .. } => format!("{}u", literal_to_char(value).expect("Char literals are validated by the tokenizer!")),
+            Expression::BoolLiteral { value, .. } => value.to_string(),
+            Expression::StringLiteral { value, .. } => {
+                let bytes = string_literal_bytes(value);
+                format!("(he_slice) {{ \"{}\", {} }}", escape(&bytes), bytes.len())
+            }
+            Expression::IdentifierLiteral { value, .. } => self.variables[value].clone(),
+            Expression::Operation { lhs, operator, rhs, type_ } => {
+                let lhs_type = lhs.get_type();
+                let lhs = self.generate_expression(lhs);
+                self.generate_operation(&lhs_type, lhs, operator, rhs, type_.as_ref().unwrap())
+            }
+            Expression::Logical { lhs, operator, rhs } => {
+                let lhs = self.generate_expression(lhs);
+                let rhs = self.generate_expression(rhs);
+                let operator = match operator {
+                    Operator::LogicalAnd => "&&",
+                    Operator::LogicalOr => "||",
+                    _ => unreachable!(),
+                };
+                format!("({} {} {})", lhs, operator, rhs)
+            }
+            Expression::Array { .. } |
+            Expression::Repeat { .. } => self.generate_place(expression),
+            Expression::Reference { reference } => format!("&{}", self.generate_place(reference)),
+            Expression::Deref { value } => format!("(*{})", self.generate_expression(value)),
+            // Accessing an array yields the address of the element, accessing a pointer the element itself
+            Expression::Access { value, .. } if matches!(value.get_type(), ValueType::Array { .. }) => format!("&{}", self.generate_place(expression)),
+            Expression::Access { .. } => self.generate_place(expression),
+            Expression::Length { value } => match value.get_type().known_len() {
+                Some(len) => format!("UINT64_C({})", len),
+                None => format!("{}.len", self.generate_expression(value)),
+            },
+            Expression::Slice { value, start, end } => {
+                let content_type = match expression.get_type() {
+                    ValueType::Slice { content_type, .. } => *content_type,
+                    _ => ValueType::U8,
+                };
+                let base = self.generate_slice(value);
+                let slice = self.new_temporary();
+                self.lines.push(format!("he_slice {} = {};", slice, base));
+                let start = match start {
+                    Some(start) => self.generate_expression(start),
+                    None => "0".to_string(),
+                };
+                let end = match end {
+                    Some(end) => self.generate_expression(end),
+                    None => format!("{}.len", slice),
+                };
+                format!("he_subslice({}, {}, {}, {}, {})", slice, start, end, content_type.size_of(), self.bounds_checks)
+            }
+            Expression::Cast { value, to } => {
+                let from = value.get_type();
+                if let ValueType::Slice { .. } = to { return self.generate_slice(value); }
+                let value = self.generate_expression(value);
+                cast(value, &from, to)
+            }
+        }
+    }
+
+    /// The C expression naming the memory that holds the value of the expression.
+    fn generate_place(&mut self, expression: &Expression) -> String {
+        match expression {
+            Expression::IdentifierLiteral { value, .. } => self.variables[value].clone(),
+            Expression::Array { .. } |
+            Expression::Repeat { .. } => {
+                let temporary = self.new_temporary();
+                self.lines.push(format!("{};", declare(&expression.get_type(), &temporary)));
+                self.generate_aggregate(&temporary, expression);
+                temporary
+            }
+            Expression::Access { value, index } => {
+                let (elements, len) = match value.get_type() {
+                    ValueType::Array { len, .. } => (self.generate_place(value), Some(len.to_string())),
+                    ValueType::Pointer { points_to, .. } => match *points_to {
+                        ValueType::Array { len, .. } => (format!("(*{})", self.generate_expression(value)), Some(len.to_string())),
+                        _ => (self.generate_expression(value), None),
+                    },
+                    ValueType::Slice { content_type, .. } => {
+                        let slice = self.generate_expression(value);
+                        (format!("(({}) {}.pointer)", declare(&content_type, "*"), slice), Some(format!("{}.len", slice)))
+                    }
+                    ValueType::Str => {
+                        let str = self.generate_expression(value);
+                        (format!("((uint8_t *) {}.pointer)", str), Some(format!("{}.len", str)))
+                    }
+                    _ => unreachable!(),
+                };
+                let index = self.generate_expression(index);
+                match len.filter(|_| self.bounds_checks) {
+                    Some(len) => format!("{}[he_check({}, {})]", elements, index, len),
+                    None => format!("{}[{}]", elements, index),
+                }
+            }
+            Expression::Deref { value } => format!("(*{})", self.generate_expression(value)),
+            Expression::Cast { value, .. } => self.generate_place(value),
+            _ => unreachable!("Tried to take the address of a temporary value!"),
+        }
+    }
+
+    /// The `he_slice` for a slice, a string or a pointer to an array.
+    fn generate_slice(&mut self, expression: &Expression) -> String {
+        match expression.get_type() {
+            ValueType::Array { len, .. } => format!("(he_slice) {{ {}, {} }}", self.generate_place(expression), len),
+            ValueType::Pointer { points_to, .. } => match *points_to {
+                ValueType::Array { len, .. } => format!("(he_slice) {{ {}, {} }}", self.generate_expression(expression), len),
+                _ => unreachable!("Only pointers to arrays can be turned into slices!"),
+            },
+            _ => self.generate_expression(expression),
+        }
+    }
+
+    /// Writes the array value of the expression into the place.
+    fn generate_aggregate(&mut self, place: &str, expression: &Expression) {
+        match expression {
+            Expression::Array { content } => {
+                for (index, element) in content.iter().enumerate() {
+                    let element_place = format!("{}[{}]", place, index);
+                    if let ValueType::Array { .. } = element.get_type() {
+                        self.generate_aggregate(&element_place, element);
+                    } else {
+                        let value = self.generate_expression(element);
+                        self.lines.push(format!("{} = {};", element_place, value));
+                    }
+                }
+            }
+            // The value is evaluated once and copied into every element
+            Expression::Repeat { value, len } => {
+                let index = self.new_temporary();
+                let element = match value.get_type() {
+                    ValueType::Array { .. } => {
+                        let source = self.generate_place(value);
+                        format!("memmove(&{}[{}], &{}, sizeof {});", place, index, source, source)
+                    }
+                    type_ => {
+                        let source = self.new_temporary();
+                        let value = self.generate_expression(value);
+                        self.lines.push(format!("{} = {};", declare(&type_, &source), value));
+                        format!("{}[{}] = {};", place, index, source)
+                    }
+                };
+                self.lines.push(format!("for (uint64_t {} = 0; {} < {}; {}++) {}", index, index, len, index, element));
+            }
+            _ => {
+                let source = self.generate_place(expression);
+                self.lines.push(format!("memmove(&{}, &{}, sizeof {});", place, source, place));
+            }
+        }
+    }
+
+    /// The new value of a compound assignment, the dereferenced assignee at the bottom of the left side is the target.
+    fn generate_compound_value(&mut self, expression: &Expression, target: &str) -> String {
+        match expression {
+            Expression::Deref { .. } => target.to_string(),
+            Expression::Cast { value, to } => {
+                let from = value.get_type();
+                let value = self.generate_compound_value(value, target);
+                cast(value, &from, to)
+            }
+            Expression::Operation { lhs, operator, rhs, type_ } => {
+                let lhs_type = lhs.get_type();
+                let lhs = self.generate_compound_value(lhs, target);
+                self.generate_operation(&lhs_type, lhs, operator, rhs, type_.as_ref().unwrap())
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    /// Arithmetic is done on 64 bits and truncated, so narrow operands arent promoted to a signed `int`.
+    fn generate_operation(&mut self, lhs_type: &ValueType, lhs: String, operator: &Operator, rhs: &Expression, type_: &ValueType) -> String {
+        let mut rhs = self.generate_expression(rhs);
+        if operator == &Operator::Divide { rhs = format!("he_divisor({})", rhs); }
+        let symbol = match operator {
+            Operator::Plus => "+",
+            Operator::Minus => "-",
+            Operator::Times => "*",
+            Operator::Divide => "/",
+            Operator::Equal => "==",
+            Operator::NotEqual => "!=",
+            Operator::Less => "<",
+            Operator::Greater => ">",
+            Operator::LessEqual => "<=",
+            Operator::GreaterEqual => ">=",
+            _ => unreachable!("unsupported binary operator {:?}", operator),
+        };
+        match (lhs_type, operator) {
+            (ValueType::Str, Operator::Equal) => format!("he_str_equal({}, {})", lhs, rhs),
+            (ValueType::Str, _) => format!("!he_str_equal({}, {})", lhs, rhs),
+            _ if type_ == &ValueType::Bool => format!("({} {} {})", lhs, symbol, rhs),
+            _ => format!("({}) ((uint64_t) {} {} {})", declare(type_, ""), lhs, symbol, rhs),
+        }
+    }
+
+    fn new_temporary(&mut self) -> String {
+        self.temporary_count += 1;
+        format!("he_t{}", self.temporary_count - 1)
+    }
+}
+
+/// The C declaration of a variable with the name, an empty name gives the type name used in casts.
+fn declare(type_: &ValueType, name: &str) -> String {
+    let declaration = match type_ {
+        ValueType::U64 => format!("uint64_t {}", name),
+        ValueType::U32 |
+        ValueType::Char => format!("uint32_t {}", name),
+        ValueType::U16 => format!("uint16_t {}", name),
+        ValueType::U8 => format!("uint8_t {}", name),
+        ValueType::Bool => format!("bool {}", name),
+        ValueType::Slice { .. } |
+        ValueType::Str => format!("he_slice {}", name),
+        // Pointers to arrays need parentheses, otherwise they would be arrays of pointers
+        ValueType::Pointer { points_to, .. } => match points_to.as_ref() {
+            ValueType::Array { .. } => declare(points_to, &format!("(*{})", name)),
+            _ => declare(points_to, &format!("*{}", name)),
+        },
+        ValueType::Array { content_type, len } => declare(content_type, &format!("{}[{}]", name, len)),
+    };
+    declaration.trim_end().to_string()
+}
+
+fn cast(value: String, from: &ValueType, to: &ValueType) -> String {
+    // Pointers only differ in their mutability which doesnt exist at runtime
+    if from == to || (from.is_pointer() && to.is_pointer()) { return value; }
+    if to == &ValueType::Char && from != &ValueType::U8 { return format!("he_char({})", value); }
+    format!("({}) {}", declare(to, ""), value)
+}
+
+/// Octal escapes never run into the following character, unlike hexadecimal ones.
+fn escape(bytes: &[u8]) -> String {
+    bytes.iter()
+        .map(|byte| match byte {
+            b'"' | b'\\' | b'?' => format!("\\{}", *byte as char),
+            0x20..=0x7E => (*byte as char).to_string(),
+            _ => format!("\\{:03o}", byte),
+        })
+        .collect()
+}
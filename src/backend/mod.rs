@@ -0,0 +1,4 @@
+pub mod x86_64;
+pub mod bytecode;
+pub mod c;
+pub mod wasm;
@@ -0,0 +1,207 @@
+use crate::backend::wasm::{Module, ValType, WasmInstruction};
+
+const MAGIC: &[u8; 4] = b"\0asm";
+const VERSION: u32 = 1;
+const EMPTY_BLOCK_TYPE: u8 = 0x40;
+
+/// Encodes the module into the binary format.
+pub fn encode(module: &Module) -> Vec<u8> {
+    let mut types: Vec<(Vec<ValType>, Vec<ValType>)> = vec![];
+    let mut type_index = |params: &Vec<ValType>, results: &Vec<ValType>| -> u32 {
+        let signature = (params.clone(), results.clone());
+        match types.iter().position(|type_| type_ == &signature) {
+            Some(index) => index as u32,
+            None => {
+                types.push(signature);
+                types.len() as u32 - 1
+            }
+        }
+    };
+    let import_types: Vec<u32> = module.imports.iter().map(|import| type_index(&import.params, &import.results)).collect();
+    let function_types: Vec<u32> = module.functions.iter().map(|function| type_index(&function.params, &function.results)).collect();
+
+    let mut bytes = MAGIC.to_vec();
+    bytes.extend(VERSION.to_le_bytes());
+
+    section(&mut bytes, 1, types.len(), |section| {
+        for (params, results) in &types {
+            section.push(0x60);
+            vector(section, params.iter().map(|type_| value_type(*type_)).collect());
+            vector(section, results.iter().map(|type_| value_type(*type_)).collect());
+        }
+    });
+    section(&mut bytes, 2, module.imports.len(), |section| {
+        for (import, type_) in module.imports.iter().zip(&import_types) {
+            name(section, import.module);
+            name(section, import.name);
+            section.push(0x00);
+            unsigned(section, *type_ as u64);
+        }
+    });
+    section(&mut bytes, 3, module.functions.len(), |section| {
+        for type_ in &function_types {
+            unsigned(section, *type_ as u64);
+        }
+    });
+    section(&mut bytes, 5, 1, |section| {
+        section.push(0x00);
+        unsigned(section, module.memory_pages as u64);
+    });
+    let exported: Vec<(usize, &str)> = module.functions.iter().enumerate()
+        .filter(|(_, function)| function.exported)
+        .map(|(index, function)| (module.imports.len() + index, function.name))
+        .collect();
+    section(&mut bytes, 7, exported.len() + 1, |section| {
+        for (index, function) in &exported {
+            name(section, function);
+            section.push(0x00);
+            unsigned(section, *index as u64);
+        }
+        name(section, "memory");
+        section.push(0x02);
+        unsigned(section, 0);
+    });
+    section(&mut bytes, 10, module.functions.len(), |section| {
+        for function in &module.functions {
+            let mut body = vec![];
+            // Runs of locals with the same type are declared together
+            let mut groups: Vec<(u32, ValType)> = vec![];
+            for local in &function.locals {
+                match groups.last_mut() {
+                    Some((count, type_)) if type_ == local => *count += 1,
+                    _ => groups.push((1, *local)),
+                }
+            }
+            unsigned(&mut body, groups.len() as u64);
+            for (count, type_) in groups {
+                unsigned(&mut body, count as u64);
+                body.push(value_type(type_));
+            }
+            for instruction in &function.body {
+                encode_instruction(&mut body, instruction);
+            }
+            body.push(0x0b);
+            unsigned(section, body.len() as u64);
+            section.extend(body);
+        }
+    });
+    section(&mut bytes, 11, module.data.len(), |section| {
+        for (address, data) in &module.data {
+            section.push(0x00);
+            section.push(0x41);
+            signed(section, *address as i64);
+            section.push(0x0b);
+            unsigned(section, data.len() as u64);
+            section.extend(data);
+        }
+    });
+    bytes
+}
+
+fn encode_instruction(bytes: &mut Vec<u8>, instruction: &WasmInstruction) {
+    match instruction {
+        WasmInstruction::LocalGet(local) => {
+            bytes.push(0x20);
+            unsigned(bytes, *local as u64);
+        }
+        WasmInstruction::LocalSet(local) => {
+            bytes.push(0x21);
+            unsigned(bytes, *local as u64);
+        }
+        WasmInstruction::I32Const(value) => {
+            bytes.push(0x41);
+            signed(bytes, *value as i64);
+        }
+        WasmInstruction::I64Const(value) => {
+            bytes.push(0x42);
+            signed(bytes, *value);
+        }
+        WasmInstruction::Numeric(op) => bytes.push(op.opcode()),
+        WasmInstruction::Memory { op, offset } => {
+            bytes.push(op.opcode());
+            unsigned(bytes, op.alignment() as u64);
+            unsigned(bytes, *offset as u64);
+        }
+        WasmInstruction::Block => bytes.extend([0x02, EMPTY_BLOCK_TYPE]),
+        WasmInstruction::Loop => bytes.extend([0x03, EMPTY_BLOCK_TYPE]),
+        WasmInstruction::If => bytes.extend([0x04, EMPTY_BLOCK_TYPE]),
+        WasmInstruction::Else => bytes.push(0x05),
+        WasmInstruction::End => bytes.push(0x0b),
+        WasmInstruction::Br(depth) => {
+            bytes.push(0x0c);
+            unsigned(bytes, *depth as u64);
+        }
+        WasmInstruction::BrIf(depth) => {
+            bytes.push(0x0d);
+            unsigned(bytes, *depth as u64);
+        }
+        WasmInstruction::BrTable { labels, default } => {
+            bytes.push(0x0e);
+            unsigned(bytes, labels.len() as u64);
+            for label in labels {
+                unsigned(bytes, *label as u64);
+            }
+            unsigned(bytes, *default as u64);
+        }
+        WasmInstruction::Call(function) => {
+            bytes.push(0x10);
+            unsigned(bytes, *function as u64);
+        }
+        WasmInstruction::Drop => bytes.push(0x1a),
+        WasmInstruction::Select => bytes.push(0x1b),
+        WasmInstruction::Unreachable => bytes.push(0x00),
+    }
+}
+
+/// Sections start with their id and their size, the contents here always begin with the number of entries.
+fn section(bytes: &mut Vec<u8>, id: u8, count: usize, contents: impl FnOnce(&mut Vec<u8>)) {
+    if count == 0 { return; }
+    let mut section = vec![];
+    unsigned(&mut section, count as u64);
+    contents(&mut section);
+    bytes.push(id);
+    unsigned(bytes, section.len() as u64);
+    bytes.extend(section);
+}
+
+fn vector(bytes: &mut Vec<u8>, elements: Vec<u8>) {
+    unsigned(bytes, elements.len() as u64);
+    bytes.extend(elements);
+}
+
+fn name(bytes: &mut Vec<u8>, name: &str) {
+    vector(bytes, name.as_bytes().to_vec());
+}
+
+fn value_type(type_: ValType) -> u8 {
+    match type_ {
+        ValType::I32 => 0x7f,
+        ValType::I64 => 0x7e,
+    }
+}
+
+/// LEB128, seven bits per byte with the highest bit marking that more follow.
+fn unsigned(bytes: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            bytes.push(byte);
+            return;
+        }
+        bytes.push(byte | 0x80);
+    }
+}
+
+fn signed(bytes: &mut Vec<u8>, mut value: i64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        let done = (value == 0 && byte & 0x40 == 0) || (value == -1 && byte & 0x40 != 0);
+        if done {
+            bytes.push(byte);
+            return;
+        }
+        bytes.push(byte | 0x80);
+    }
+}
@@ -0,0 +1,357 @@
+use std::collections::HashMap;
+use crate::backend::wasm::{Function, Import, MemoryOp, Module, NumericOp, ValType, WasmInstruction};
+use crate::ir::cfg::ControlFlowGraph;
+use crate::ir::instruction::{Instruction, Label, LocalId, Operand};
+use crate::ir::Program;
+use crate::parser::r#type::ValueType;
+use crate::tokenizer::token::Operator;
+
+const WASI: &str = "wasi_snapshot_preview1";
+const FD_WRITE: u32 = 0;
+const PROC_EXIT: u32 = 1;
+const WRITE: u32 = 2;
+const PRINT_INTEGER: u32 = 3;
+const PRINT_BOOL: u32 = 4;
+
+/// The `fd_write` arguments live at the start of the memory: the buffer, its length and the number of bytes written.
+const IOVEC_ADDRESS: i32 = 0;
+const WRITTEN_ADDRESS: i32 = 8;
+/// Digits are written backwards from the end of this buffer.
+const DIGITS_END: i32 = 40;
+const TRUE_ADDRESS: i32 = 48;
+const FALSE_ADDRESS: i32 = 52;
+const DATA_START: u32 = 64;
+const PAGE_SIZE: u32 = 65536;
+
+/// Lowers the IR to a WASI module, expects a program without phis. The unstructured jumps of the IR become
+/// a loop around one block per basic block, jumping sets the block to continue with and restarts the loop.
+pub struct WasmGenerator<'a> {
+    program: &'a Program,
+    /// Addresses of the locals that live in memory, the aggregates and those whose address is taken.
+    addresses: HashMap<LocalId, u32>,
+    data_addresses: Vec<u32>,
+    body: Vec<WasmInstruction>,
+}
+
+impl<'a> WasmGenerator<'a> {
+    pub fn new(program: &'a Program) -> Self {
+        WasmGenerator { program, addresses: HashMap::new(), data_addresses: vec![], body: vec![] }
+    }
+
+    pub fn generate(mut self) -> Module {
+        let mut data = vec![(TRUE_ADDRESS as u32, b"truefalse".to_vec())];
+        let mut end = DATA_START;
+        for bytes in &self.program.data {
+            self.data_addresses.push(end);
+            if !bytes.is_empty() { data.push((end, bytes.clone())); }
+            end += bytes.len() as u32;
+        }
+        let registers = self.program.register_locals(&self.program.instructions);
+        for (local, value) in self.program.locals.iter().enumerate() {
+            if !registers.contains(&local) {
+                let size = value.type_.size_of() as u32;
+                let alignment = size.clamp(1, 8);
+                end = end.div_ceil(alignment) * alignment;
+                self.addresses.insert(local, end);
+                end += size;
+            }
+        }
+
+        let graph = ControlFlowGraph::new(&self.program.instructions);
+        let count = graph.blocks.len() as u32;
+        if count > 0 {
+            self.body.push(WasmInstruction::Loop);
+            for _ in 0..count {
+                self.body.push(WasmInstruction::Block);
+            }
+            self.body.push(WasmInstruction::LocalGet(0));
+            self.body.push(WasmInstruction::BrTable { labels: (0..count).collect(), default: count - 1 });
+            for (id, block) in graph.blocks.iter().enumerate() {
+                self.body.push(WasmInstruction::End);
+                // Only the blocks after this one and the loop are still open
+                let depth = count - 1 - id as u32;
+                for instruction in &block.instructions {
+                    self.generate_instruction(instruction, &graph, depth);
+                }
+            }
+            self.body.push(WasmInstruction::End);
+        }
+        // Falling off the end of the program exits successfully
+        self.body.push(WasmInstruction::I32Const(0));
+        self.body.push(WasmInstruction::Call(PROC_EXIT));
+
+        let mut locals = vec![ValType::I32];
+        locals.extend(self.program.locals.iter().map(|local| value_type(&local.type_)));
+        let start = Function { name: "_start", params: vec![], results: vec![], locals, body: self.body, exported: true };
+        Module {
+            imports: vec![
+                Import { module: WASI, name: "fd_write", params: vec![ValType::I32; 4], results: vec![ValType::I32] },
+                Import { module: WASI, name: "proc_exit", params: vec![ValType::I32], results: vec![] },
+            ],
+            functions: vec![write_function(), print_integer_function(), print_bool_function(), start],
+            memory_pages: end.div_ceil(PAGE_SIZE).max(1),
+            data,
+        }
+    }
+
+    fn generate_instruction(&mut self, instruction: &Instruction, graph: &ControlFlowGraph, depth: u32) {
+        match instruction {
+            Instruction::Move { destination, source } => {
+                self.begin_write(*destination);
+                self.push(source, value_type(&self.program.locals[*destination].type_));
+                self.end_write(*destination);
+            }
+            Instruction::Binary { destination, lhs, operator, rhs } => self.generate_binary(*destination, lhs, operator, rhs),
+            Instruction::Cast { destination, value, from, to } => {
+                self.begin_write(*destination);
+                self.push(value, value_type(from));
+                self.convert(value_type(from), value_type(to));
+                if to.size_of() < from.size_of() {
+                    self.mask(to);
+                }
+                self.end_write(*destination);
+            }
+            Instruction::AddressOf { destination, local } => {
+                self.begin_write(*destination);
+                self.body.push(WasmInstruction::I32Const(self.addresses[local] as i32));
+                self.end_write(*destination);
+            }
+            Instruction::DataAddress { destination, data } => {
+                self.begin_write(*destination);
+                self.body.push(WasmInstruction::I32Const(self.data_addresses[*data] as i32));
+                self.end_write(*destination);
+            }
+            Instruction::Load { destination, address } => {
+                self.begin_write(*destination);
+                self.push(address, ValType::I32);
+                self.body.push(WasmInstruction::Memory { op: load_op(&self.program.locals[*destination].type_), offset: 0 });
+                self.end_write(*destination);
+            }
+            Instruction::Store { address, value } => {
+                let type_ = self.program.operand_type(value);
+                self.push(address, ValType::I32);
+                self.push(value, value_type(&type_));
+                self.body.push(WasmInstruction::Memory { op: store_op(&type_), offset: 0 });
+            }
+            Instruction::Label { .. } => {}
+            Instruction::Jump { label } => {
+                self.body.push(WasmInstruction::I32Const(block_index(graph, *label)));
+                self.body.push(WasmInstruction::LocalSet(0));
+                self.body.push(WasmInstruction::Br(depth));
+            }
+            Instruction::Branch { condition, if_true, if_false } => {
+                self.body.push(WasmInstruction::I32Const(block_index(graph, *if_true)));
+                self.body.push(WasmInstruction::I32Const(block_index(graph, *if_false)));
+                self.push(condition, ValType::I32);
+                self.body.push(WasmInstruction::Select);
+                self.body.push(WasmInstruction::LocalSet(0));
+                self.body.push(WasmInstruction::Br(depth));
+            }
+            Instruction::Exit { value } => {
+                self.push(value, ValType::I32);
+                self.body.push(WasmInstruction::Call(PROC_EXIT));
+                self.body.push(WasmInstruction::Unreachable);
+            }
+            Instruction::Print { value } => match self.program.operand_type(value) {
+                ValueType::Bool => {
+                    self.push(value, ValType::I32);
+                    self.body.push(WasmInstruction::Call(PRINT_BOOL));
+                }
+                _ => {
+                    self.push(value, ValType::I64);
+                    self.body.push(WasmInstruction::Call(PRINT_INTEGER));
+                }
+            },
+            Instruction::PrintString { address, len } => {
+                self.push(address, ValType::I32);
+                self.push(len, ValType::I32);
+                self.body.push(WasmInstruction::Call(WRITE));
+            }
+            Instruction::Phi { .. } => unreachable!("phis are removed before generating WebAssembly"),
+        }
+    }
+
+    fn generate_binary(&mut self, destination: LocalId, lhs: &Operand, operator: &Operator, rhs: &Operand) {
+        let comparison = matches!(operator, Operator::Equal | Operator::NotEqual | Operator::Less | Operator::Greater | Operator::LessEqual | Operator::GreaterEqual);
+        let destination_type = &self.program.locals[destination].type_;
+        let type_ = match comparison {
+            true => value_type(&self.program.operand_type(lhs)),
+            false => value_type(destination_type),
+        };
+        let op = match (type_, operator) {
+            (ValType::I32, Operator::Plus) => NumericOp::I32Add,
+            (ValType::I32, Operator::Minus) => NumericOp::I32Sub,
+            (ValType::I32, Operator::Times) => NumericOp::I32Mul,
+            (ValType::I32, Operator::Divide) => NumericOp::I32DivU,
+            (ValType::I32, Operator::Equal) => NumericOp::I32Eq,
+            (ValType::I32, Operator::NotEqual) => NumericOp::I32Ne,
+            (ValType::I32, Operator::Less) => NumericOp::I32LtU,
+            (ValType::I32, Operator::Greater) => NumericOp::I32GtU,
+            (ValType::I32, Operator::LessEqual) => NumericOp::I32LeU,
+            (ValType::I32, Operator::GreaterEqual) => NumericOp::I32GeU,
+            (ValType::I64, Operator::Plus) => NumericOp::I64Add,
+            (ValType::I64, Operator::Minus) => NumericOp::I64Sub,
+            (ValType::I64, Operator::Times) => NumericOp::I64Mul,
+            (ValType::I64, Operator::Divide) => NumericOp::I64DivU,
+            (ValType::I64, Operator::Equal) => NumericOp::I64Eq,
+            (ValType::I64, Operator::NotEqual) => NumericOp::I64Ne,
+            (ValType::I64, Operator::Less) => NumericOp::I64LtU,
+            (ValType::I64, Operator::Greater) => NumericOp::I64GtU,
+            (ValType::I64, Operator::LessEqual) => NumericOp::I64LeU,
+            (ValType::I64, Operator::GreaterEqual) => NumericOp::I64GeU,
+            _ => unreachable!("unsupported binary operator {:?}", operator),
+        };
+
+        self.begin_write(destination);
+        self.push(lhs, type_);
+        self.push(rhs, type_);
+        self.body.push(WasmInstruction::Numeric(op));
+        // u8 and u16 share the i32 with bits that have to be cleared after wrapping around
+        if matches!(operator, Operator::Plus | Operator::Minus | Operator::Times) {
+            let destination_type = self.program.locals[destination].type_.clone();
+            self.mask(&destination_type);
+        }
+        self.end_write(destination);
+    }
+
+    /// Pushes the value of the operand as the type.
+    fn push(&mut self, operand: &Operand, type_: ValType) {
+        match operand {
+            Operand::Constant { value, .. } => self.body.push(match type_ {
+                ValType::I32 => WasmInstruction::I32Const(*value as u32 as i32),
+                ValType::I64 => WasmInstruction::I64Const(*value as i64),
+            }),
+            Operand::Local(local) => {
+                let local_type = &self.program.locals[*local].type_;
+                match self.addresses.get(local) {
+                    Some(address) => {
+                        self.body.push(WasmInstruction::I32Const(*address as i32));
+                        self.body.push(WasmInstruction::Memory { op: load_op(local_type), offset: 0 });
+                    }
+                    None => self.body.push(WasmInstruction::LocalGet(*local as u32 + 1)),
+                }
+                self.convert(value_type(local_type), type_);
+            }
+        }
+    }
+
+    fn convert(&mut self, from: ValType, to: ValType) {
+        match (from, to) {
+            (ValType::I64, ValType::I32) => self.body.push(WasmInstruction::Numeric(NumericOp::I32WrapI64)),
+            (ValType::I32, ValType::I64) => self.body.push(WasmInstruction::Numeric(NumericOp::I64ExtendI32U)),
+            _ => {}
+        }
+    }
+
+    fn mask(&mut self, type_: &ValueType) {
+        let mask = match type_ {
+            ValueType::U8 => 0xFF,
+            ValueType::U16 => 0xFFFF,
+            _ => return,
+        };
+        self.body.push(WasmInstruction::I32Const(mask));
+        self.body.push(WasmInstruction::Numeric(NumericOp::I32And));
+    }
+
+    /// Locals in memory need their address below the value that gets stored.
+    fn begin_write(&mut self, local: LocalId) {
+        if let Some(address) = self.addresses.get(&local) {
+            self.body.push(WasmInstruction::I32Const(*address as i32));
+        }
+    }
+
+    fn end_write(&mut self, local: LocalId) {
+        match self.addresses.get(&local) {
+            Some(_) => self.body.push(WasmInstruction::Memory { op: store_op(&self.program.locals[local].type_), offset: 0 }),
+            None => self.body.push(WasmInstruction::LocalSet(local as u32 + 1)),
+        }
+    }
+}
+
+fn block_index(graph: &ControlFlowGraph, label: Label) -> i32 {
+    graph.block_of_label(label).expect("Jumps target existing labels!") as i32
+}
+
+/// Pointers are 32 bit addresses into the linear memory.
+fn value_type(type_: &ValueType) -> ValType {
+    match type_ {
+        ValueType::U64 => ValType::I64,
+        _ => ValType::I32,
+    }
+}
+
+fn load_op(type_: &ValueType) -> MemoryOp {
+    match type_ {
+        ValueType::U64 => MemoryOp::I64Load,
+        ValueType::U16 => MemoryOp::I32Load16U,
+        ValueType::U8 | ValueType::Bool => MemoryOp::I32Load8U,
+        _ => MemoryOp::I32Load,
+    }
+}
+
+fn store_op(type_: &ValueType) -> MemoryOp {
+    match type_ {
+        ValueType::U64 => MemoryOp::I64Store,
+        ValueType::U16 => MemoryOp::I32Store16,
+        ValueType::U8 | ValueType::Bool => MemoryOp::I32Store8,
+        _ => MemoryOp::I32Store,
+    }
+}
+
+/// `write(address, len)` writes the bytes to stdout.
+fn write_function() -> Function {
+    use WasmInstruction::*;
+    Function {
+        name: "write",
+        params: vec![ValType::I32, ValType::I32],
+        results: vec![],
+        locals: vec![],
+        body: vec![
+            I32Const(IOVEC_ADDRESS), LocalGet(0), Memory { op: MemoryOp::I32Store, offset: 0 },
+            I32Const(IOVEC_ADDRESS), LocalGet(1), Memory { op: MemoryOp::I32Store, offset: 4 },
+            I32Const(1), I32Const(IOVEC_ADDRESS), I32Const(1), I32Const(WRITTEN_ADDRESS), Call(FD_WRITE), Drop,
+        ],
+        exported: false,
+    }
+}
+
+/// `print_integer(value)` writes the value in decimal.
+fn print_integer_function() -> Function {
+    use WasmInstruction::*;
+    Function {
+        name: "print_integer",
+        params: vec![ValType::I64],
+        results: vec![],
+        locals: vec![ValType::I32],
+        body: vec![
+            I32Const(DIGITS_END), LocalSet(1),
+            Loop,
+            LocalGet(1), I32Const(1), Numeric(NumericOp::I32Sub), LocalSet(1),
+            LocalGet(1),
+            LocalGet(0), I64Const(10), Numeric(NumericOp::I64RemU), Numeric(NumericOp::I32WrapI64), I32Const(b'0' as i32), Numeric(NumericOp::I32Add),
+            Memory { op: MemoryOp::I32Store8, offset: 0 },
+            LocalGet(0), I64Const(10), Numeric(NumericOp::I64DivU), LocalSet(0),
+            LocalGet(0), I64Const(0), Numeric(NumericOp::I64Ne), BrIf(0),
+            End,
+            LocalGet(1), I32Const(DIGITS_END), LocalGet(1), Numeric(NumericOp::I32Sub), Call(WRITE),
+        ],
+        exported: false,
+    }
+}
+
+/// `print_bool(value)` writes `true` or `false`.
+fn print_bool_function() -> Function {
+    use WasmInstruction::*;
+    Function {
+        name: "print_bool",
+        params: vec![ValType::I32],
+        results: vec![],
+        locals: vec![],
+        body: vec![
+            I32Const(TRUE_ADDRESS), I32Const(FALSE_ADDRESS), LocalGet(0), Select,
+            I32Const(4), I32Const(5), LocalGet(0), Select,
+            Call(WRITE),
+        ],
+        exported: false,
+    }
+}
@@ -0,0 +1,266 @@
+use std::fmt::{Display, Formatter};
+
+pub mod encoder;
+pub mod generator;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ValType {
+    I32,
+    I64,
+}
+
+impl ValType {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ValType::I32 => "i32",
+            ValType::I64 => "i64",
+        }
+    }
+}
+
+/// Instructions without immediates, with their opcode in the binary format.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum NumericOp {
+    I32Add, I32Sub, I32Mul, I32DivU, I32RemU, I32And,
+    I64Add, I64Sub, I64Mul, I64DivU, I64RemU, I64And,
+    I32Eqz, I32Eq, I32Ne, I32LtU, I32GtU, I32LeU, I32GeU,
+    I64Eq, I64Ne, I64LtU, I64GtU, I64LeU, I64GeU,
+    I32WrapI64, I64ExtendI32U,
+}
+
+impl NumericOp {
+    pub fn opcode(&self) -> u8 {
+        match self {
+            NumericOp::I32Add => 0x6a,
+            NumericOp::I32Sub => 0x6b,
+            NumericOp::I32Mul => 0x6c,
+            NumericOp::I32DivU => 0x6e,
+            NumericOp::I32RemU => 0x70,
+            NumericOp::I32And => 0x71,
+            NumericOp::I64Add => 0x7c,
+            NumericOp::I64Sub => 0x7d,
+            NumericOp::I64Mul => 0x7e,
+            NumericOp::I64DivU => 0x80,
+            NumericOp::I64RemU => 0x82,
+            NumericOp::I64And => 0x83,
+            NumericOp::I32Eqz => 0x45,
+            NumericOp::I32Eq => 0x46,
+            NumericOp::I32Ne => 0x47,
+            NumericOp::I32LtU => 0x49,
+            NumericOp::I32GtU => 0x4b,
+            NumericOp::I32LeU => 0x4d,
+            NumericOp::I32GeU => 0x4f,
+            NumericOp::I64Eq => 0x51,
+            NumericOp::I64Ne => 0x52,
+            NumericOp::I64LtU => 0x54,
+            NumericOp::I64GtU => 0x56,
+            NumericOp::I64LeU => 0x58,
+            NumericOp::I64GeU => 0x5a,
+            NumericOp::I32WrapI64 => 0xa7,
+            NumericOp::I64ExtendI32U => 0xad,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            NumericOp::I32Add => "i32.add",
+            NumericOp::I32Sub => "i32.sub",
+            NumericOp::I32Mul => "i32.mul",
+            NumericOp::I32DivU => "i32.div_u",
+            NumericOp::I32RemU => "i32.rem_u",
+            NumericOp::I32And => "i32.and",
+            NumericOp::I64Add => "i64.add",
+            NumericOp::I64Sub => "i64.sub",
+            NumericOp::I64Mul => "i64.mul",
+            NumericOp::I64DivU => "i64.div_u",
+            NumericOp::I64RemU => "i64.rem_u",
+            NumericOp::I64And => "i64.and",
+            NumericOp::I32Eqz => "i32.eqz",
+            NumericOp::I32Eq => "i32.eq",
+            NumericOp::I32Ne => "i32.ne",
+            NumericOp::I32LtU => "i32.lt_u",
+            NumericOp::I32GtU => "i32.gt_u",
+            NumericOp::I32LeU => "i32.le_u",
+            NumericOp::I32GeU => "i32.ge_u",
+            NumericOp::I64Eq => "i64.eq",
+            NumericOp::I64Ne => "i64.ne",
+            NumericOp::I64LtU => "i64.lt_u",
+            NumericOp::I64GtU => "i64.gt_u",
+            NumericOp::I64LeU => "i64.le_u",
+            NumericOp::I64GeU => "i64.ge_u",
+            NumericOp::I32WrapI64 => "i32.wrap_i64",
+            NumericOp::I64ExtendI32U => "i64.extend_i32_u",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum MemoryOp {
+    I32Load, I64Load, I32Load8U, I32Load16U,
+    I32Store, I64Store, I32Store8, I32Store16,
+}
+
+impl MemoryOp {
+    pub fn opcode(&self) -> u8 {
+        match self {
+            MemoryOp::I32Load => 0x28,
+            MemoryOp::I64Load => 0x29,
+            MemoryOp::I32Load8U => 0x2d,
+            MemoryOp::I32Load16U => 0x2f,
+            MemoryOp::I32Store => 0x36,
+            MemoryOp::I64Store => 0x37,
+            MemoryOp::I32Store8 => 0x3a,
+            MemoryOp::I32Store16 => 0x3b,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            MemoryOp::I32Load => "i32.load",
+            MemoryOp::I64Load => "i64.load",
+            MemoryOp::I32Load8U => "i32.load8_u",
+            MemoryOp::I32Load16U => "i32.load16_u",
+            MemoryOp::I32Store => "i32.store",
+            MemoryOp::I64Store => "i64.store",
+            MemoryOp::I32Store8 => "i32.store8",
+            MemoryOp::I32Store16 => "i32.store16",
+        }
+    }
+
+    /// The log2 of the natural alignment.
+    pub fn alignment(&self) -> u32 {
+        match self {
+            MemoryOp::I32Load8U | MemoryOp::I32Store8 => 0,
+            MemoryOp::I32Load16U | MemoryOp::I32Store16 => 1,
+            MemoryOp::I32Load | MemoryOp::I32Store => 2,
+            MemoryOp::I64Load | MemoryOp::I64Store => 3,
+        }
+    }
+}
+
+/// Blocks, loops and ifs dont produce values.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum WasmInstruction {
+    LocalGet(u32),
+    LocalSet(u32),
+    I32Const(i32),
+    I64Const(i64),
+    Numeric(NumericOp),
+    Memory { op: MemoryOp, offset: u32 },
+    Block,
+    Loop,
+    If,
+    Else,
+    End,
+    Br(u32),
+    BrIf(u32),
+    BrTable { labels: Vec<u32>, default: u32 },
+    Call(u32),
+    Drop,
+    Select,
+    Unreachable,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Import {
+    pub module: &'static str,
+    pub name: &'static str,
+    pub params: Vec<ValType>,
+    pub results: Vec<ValType>,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Function {
+    pub name: &'static str,
+    pub params: Vec<ValType>,
+    pub results: Vec<ValType>,
+    /// Locals besides the parameters, which come first.
+    pub locals: Vec<ValType>,
+    pub body: Vec<WasmInstruction>,
+    pub exported: bool,
+}
+
+/// Functions are numbered with the imports first.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Module {
+    pub imports: Vec<Import>,
+    pub functions: Vec<Function>,
+    /// Pages of 64 KiB, the memory is exported as `memory`.
+    pub memory_pages: u32,
+    /// Bytes placed at an address when the module is instantiated.
+    pub data: Vec<(u32, Vec<u8>)>,
+}
+
+impl Module {
+    fn function_name(&self, index: u32) -> &'static str {
+        match self.imports.get(index as usize) {
+            Some(import) => import.name,
+            None => self.functions[index as usize - self.imports.len()].name,
+        }
+    }
+}
+
+fn signature(params: &[ValType], results: &[ValType]) -> String {
+    let mut signature = String::new();
+    if !params.is_empty() {
+        signature.push_str(&format!(" (param {})", params.iter().map(ValType::name).collect::<Vec<_>>().join(" ")));
+    }
+    if !results.is_empty() {
+        signature.push_str(&format!(" (result {})", results.iter().map(ValType::name).collect::<Vec<_>>().join(" ")));
+    }
+    signature
+}
+
+impl Display for Module {
+    /// The text format.
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "(module")?;
+        for import in &self.imports {
+            writeln!(f, "  (import \"{}\" \"{}\" (func ${}{}))", import.module, import.name, import.name, signature(&import.params, &import.results))?;
+        }
+        writeln!(f, "  (memory (export \"memory\") {})", self.memory_pages)?;
+        for function in &self.functions {
+            write!(f, "  (func ${}", function.name)?;
+            if function.exported { write!(f, " (export \"{}\")", function.name)?; }
+            writeln!(f, "{}", signature(&function.params, &function.results))?;
+            if !function.locals.is_empty() {
+                writeln!(f, "    (local {})", function.locals.iter().map(ValType::name).collect::<Vec<_>>().join(" "))?;
+            }
+            let mut depth = 2;
+            for instruction in &function.body {
+                if matches!(instruction, WasmInstruction::End | WasmInstruction::Else) { depth -= 1; }
+                write!(f, "{}", "  ".repeat(depth))?;
+                match instruction {
+                    WasmInstruction::LocalGet(local) => writeln!(f, "local.get {}", local)?,
+                    WasmInstruction::LocalSet(local) => writeln!(f, "local.set {}", local)?,
+                    WasmInstruction::I32Const(value) => writeln!(f, "i32.const {}", value)?,
+                    WasmInstruction::I64Const(value) => writeln!(f, "i64.const {}", value)?,
+                    WasmInstruction::Numeric(op) => writeln!(f, "{}", op.name())?,
+                    WasmInstruction::Memory { op, offset: 0 } => writeln!(f, "{}", op.name())?,
+                    WasmInstruction::Memory { op, offset } => writeln!(f, "{} offset={}", op.name(), offset)?,
+                    WasmInstruction::Block => writeln!(f, "block")?,
+                    WasmInstruction::Loop => writeln!(f, "loop")?,
+                    WasmInstruction::If => writeln!(f, "if")?,
+                    WasmInstruction::Else => writeln!(f, "else")?,
+                    WasmInstruction::End => writeln!(f, "end")?,
+                    WasmInstruction::Br(depth) => writeln!(f, "br {}", depth)?,
+                    WasmInstruction::BrIf(depth) => writeln!(f, "br_if {}", depth)?,
+                    WasmInstruction::BrTable { labels, default } => {
+                        writeln!(f, "br_table {} {}", labels.iter().map(u32::to_string).collect::<Vec<_>>().join(" "), default)?
+                    }
+                    WasmInstruction::Call(function) => writeln!(f, "call ${}", self.function_name(*function))?,
+                    WasmInstruction::Drop => writeln!(f, "drop")?,
+                    WasmInstruction::Select => writeln!(f, "select")?,
+                    WasmInstruction::Unreachable => writeln!(f, "unreachable")?,
+                }
+                if matches!(instruction, WasmInstruction::Block | WasmInstruction::Loop | WasmInstruction::If | WasmInstruction::Else) { depth += 1; }
+            }
+            writeln!(f, "  )")?;
+        }
+        for (address, bytes) in &self.data {
+            let escaped: String = bytes.iter().map(|byte| format!("\\{:02x}", byte)).collect();
+            writeln!(f, "  (data (i32.const {}) \"{}\")", address, escaped)?;
+        }
+        writeln!(f, ")")
+    }
+}
@@ -0,0 +1,131 @@
+use std::collections::BTreeSet;
+use crate::backend::x86_64::Register;
+use crate::ir::cfg::ControlFlowGraph;
+use crate::ir::instruction::{LocalId, Operand};
+use crate::ir::Program;
+
+/// Registers handed out to locals, the others are used as scratch registers while lowering single instructions.
+pub const ALLOCATABLE: [Register; 10] = [
+    Register::Rbx, Register::R12, Register::R13, Register::R14, Register::R15,
+    Register::Rsi, Register::Rdi, Register::R8, Register::R9, Register::R10,
+];
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Location {
+    Register(Register),
+    /// Offset below the frame pointer.
+    Stack(i32),
+}
+
+/// Where every local lives, locals that are never used dont get a location.
+#[derive(Debug, Clone)]
+pub struct Allocation {
+    pub locations: Vec<Option<Location>>,
+    /// Bytes of stack needed below the frame pointer, a multiple of 16.
+    pub frame_size: i32,
+}
+
+/// The range of instruction positions in which a local holds a value that is still needed.
+#[derive(Debug, Clone, Copy)]
+struct Interval {
+    local: LocalId,
+    start: usize,
+    end: usize,
+}
+
+/// Linear scan register allocation, see "Linear Scan Register Allocation" by Poletto and Sarkar.
+/// Aggregates and locals whose address is taken always live on the stack.
+pub fn allocate(program: &Program, graph: &ControlFlowGraph) -> Allocation {
+    let registers = graph.register_locals(program);
+
+    let mut locations: Vec<Option<Location>> = vec![None; program.locals.len()];
+    let mut frame_size = 0;
+    let mut stack_slot = |size: i32, alignment: i32| {
+        frame_size = (frame_size + size + alignment - 1) / alignment * alignment;
+        Location::Stack(frame_size)
+    };
+    for local in (0..program.locals.len()).filter(|local| !registers.contains(local)) {
+        let size = program.locals[local].type_.size_of().max(1) as i32;
+        locations[local] = Some(stack_slot(size, size.min(8)));
+    }
+
+    let mut intervals = intervals(graph, &registers);
+    intervals.sort_by_key(|interval| interval.start);
+
+    let mut free: Vec<Register> = ALLOCATABLE.iter().rev().copied().collect();
+    let mut active: Vec<(Interval, Register)> = vec![];
+    for interval in intervals {
+        active.retain(|(active, register)| {
+            let expired = active.end < interval.start;
+            if expired { free.push(*register); }
+            !expired
+        });
+        match free.pop() {
+            Some(register) => {
+                locations[interval.local] = Some(Location::Register(register));
+                active.push((interval, register));
+            }
+            None => {
+                // Spill whichever interval lives the longest, that frees a register for the most instructions
+                let (index, (longest, register)) = active.iter().enumerate().max_by_key(|(_, (active, _))| active.end).unwrap();
+                if longest.end > interval.end {
+                    let (longest, register) = (*longest, *register);
+                    locations[longest.local] = Some(stack_slot(8, 8));
+                    locations[interval.local] = Some(Location::Register(register));
+                    active[index] = (interval, register);
+                } else {
+                    locations[interval.local] = Some(stack_slot(8, 8));
+                }
+            }
+        }
+    }
+
+    Allocation { locations, frame_size: (frame_size + 15) / 16 * 16 }
+}
+
+/// Intervals from a liveness analysis over the blocks, a local live across a block boundary covers that boundary.
+fn intervals(graph: &ControlFlowGraph, registers: &BTreeSet<LocalId>) -> Vec<Interval> {
+    let locals = |operands: Vec<&Operand>| -> Vec<LocalId> {
+        operands.into_iter()
+            .filter_map(|operand| match operand {
+                Operand::Local(local) if registers.contains(local) => Some(*local),
+                _ => None,
+            })
+            .collect()
+    };
+
+    let live_in = graph.live_in(registers);
+
+    let mut ranges: Vec<Option<(usize, usize)>> = vec![];
+    let mut extend = |local: LocalId, position: usize| {
+        if ranges.len() <= local { ranges.resize(local + 1, None); }
+        ranges[local] = Some(match ranges[local] {
+            Some((start, end)) => (start.min(position), end.max(position)),
+            None => (position, position),
+        });
+    };
+    let mut position = 0;
+    for (id, block) in graph.blocks.iter().enumerate() {
+        let start = position;
+        for instruction in &block.instructions {
+            for local in locals(instruction.operands()) {
+                extend(local, position);
+            }
+            if let Some(destination) = instruction.destination().filter(|destination| registers.contains(destination)) {
+                extend(destination, position);
+            }
+            position += 1;
+        }
+        let end = position.saturating_sub(1).max(start);
+        for local in &live_in[id] {
+            extend(*local, start);
+        }
+        for local in block.successors.iter().flat_map(|successor| live_in[*successor].iter()) {
+            extend(*local, end);
+        }
+    }
+
+    ranges.into_iter().enumerate()
+        .filter_map(|(local, range)| range.map(|(start, end)| Interval { local, start, end }))
+        .collect()
+}
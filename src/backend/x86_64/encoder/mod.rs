@@ -0,0 +1,193 @@
+use std::collections::HashMap;
+use crate::backend::x86_64::{Address, ArithmeticOperation, MachineInstruction, Register, RuntimeCall};
+use crate::ir::instruction::{DataId, Label};
+
+/// A 32 bit displacement, relative to the end of the instruction, the encoder cannot fill in by itself.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Relocation {
+    Data(DataId),
+    Runtime(RuntimeCall),
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Encoding {
+    pub code: Vec<u8>,
+    /// The positions of the displacements in the code.
+    pub relocations: Vec<(usize, Relocation)>,
+}
+
+enum Operand {
+    Register(Register),
+    Memory(Address),
+}
+
+/// Encodes machine instructions into bytes, jumps to labels are resolved when finishing.
+#[derive(Debug, Default)]
+pub struct Encoder {
+    code: Vec<u8>,
+    labels: HashMap<Label, usize>,
+    jumps: Vec<(usize, Label)>,
+    relocations: Vec<(usize, Relocation)>,
+}
+
+impl Encoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn position(&self) -> usize {
+        self.code.len()
+    }
+
+    /// Appends bytes for instructions the machine instructions dont cover.
+    pub fn raw(&mut self, bytes: &[u8]) {
+        self.code.extend(bytes);
+    }
+
+    pub fn instruction(&mut self, instruction: &MachineInstruction) {
+        match instruction {
+            MachineInstruction::Enter { frame_size } => {
+                self.instruction(&MachineInstruction::Mov { destination: Register::Rbp, source: Register::Rsp });
+                if *frame_size > 0 {
+                    self.instruction(&MachineInstruction::AdjustStack { amount: -frame_size });
+                }
+            }
+            MachineInstruction::MovImmediate { destination, value } if *value <= u32::MAX as u64 => {
+                if destination.number() >= 8 { self.code.push(0x41); }
+                self.code.push(0xB8 + (destination.number() & 7));
+                self.code.extend((*value as u32).to_le_bytes());
+            }
+            MachineInstruction::MovImmediate { destination, value } => {
+                self.code.push(0x48 | (destination.number() >> 3));
+                self.code.push(0xB8 + (destination.number() & 7));
+                self.code.extend(value.to_le_bytes());
+            }
+            MachineInstruction::Mov { destination, source } => self.encode(true, &[0x89], source.number(), Operand::Register(*destination), false),
+            MachineInstruction::Load { destination, address, width } => {
+                let (wide, opcode): (bool, &[u8]) = match width {
+                    1 => (false, &[0x0F, 0xB6]),
+                    2 => (false, &[0x0F, 0xB7]),
+                    4 => (false, &[0x8B]),
+                    _ => (true, &[0x8B]),
+                };
+                self.encode(wide, opcode, destination.number(), Operand::Memory(*address), false);
+            }
+            MachineInstruction::Store { address, source, width } => {
+                if *width == 2 { self.code.push(0x66); }
+                let opcode = if *width == 1 { 0x88 } else { 0x89 };
+                self.encode(*width == 8, &[opcode], source.number(), Operand::Memory(*address), *width == 1);
+            }
+            MachineInstruction::Lea { destination, address } => self.encode(true, &[0x8D], destination.number(), Operand::Memory(*address), false),
+            MachineInstruction::Arithmetic { operation, destination, source } => match operation {
+                ArithmeticOperation::Add => self.encode(true, &[0x01], source.number(), Operand::Register(*destination), false),
+                ArithmeticOperation::Sub => self.encode(true, &[0x29], source.number(), Operand::Register(*destination), false),
+                ArithmeticOperation::Mul => self.encode(true, &[0x0F, 0xAF], destination.number(), Operand::Register(*source), false),
+            },
+            MachineInstruction::Divide { divisor } => {
+                // xor edx, edx
+                self.code.extend([0x31, 0xD2]);
+                self.encode(true, &[0xF7], 6, Operand::Register(*divisor), false);
+            }
+            MachineInstruction::ZeroExtend { register, width: width @ (1 | 2) } => {
+                let opcode = if *width == 1 { 0xB6 } else { 0xB7 };
+                self.encode(false, &[0x0F, opcode], register.number(), Operand::Register(*register), true);
+            }
+            MachineInstruction::ZeroExtend { register, .. } => self.encode(false, &[0x89], register.number(), Operand::Register(*register), false),
+            MachineInstruction::Compare { lhs, rhs } => self.encode(true, &[0x39], rhs.number(), Operand::Register(*lhs), false),
+            MachineInstruction::SetCondition { condition, destination } => {
+                self.encode(false, &[0x0F, 0x90 + condition.code()], 0, Operand::Register(*destination), true);
+                self.instruction(&MachineInstruction::ZeroExtend { register: *destination, width: 1 });
+            }
+            MachineInstruction::Test { register } => self.encode(true, &[0x85], register.number(), Operand::Register(*register), false),
+            MachineInstruction::Label { label } => {
+                self.labels.insert(*label, self.code.len());
+            }
+            MachineInstruction::Jump { label } => {
+                self.code.push(0xE9);
+                self.jump(*label);
+            }
+            MachineInstruction::JumpIfNotZero { label } => {
+                self.code.extend([0x0F, 0x85]);
+                self.jump(*label);
+            }
+            MachineInstruction::Push { register } => {
+                if register.number() >= 8 { self.code.push(0x41); }
+                self.code.push(0x50 + (register.number() & 7));
+            }
+            MachineInstruction::Pop { register } => {
+                if register.number() >= 8 { self.code.push(0x41); }
+                self.code.push(0x58 + (register.number() & 7));
+            }
+            MachineInstruction::AdjustStack { amount } => {
+                let (extension, amount) = if *amount < 0 { (5, -amount) } else { (0, *amount) };
+                self.encode(true, &[0x81], extension, Operand::Register(Register::Rsp), false);
+                self.code.extend(amount.to_le_bytes());
+            }
+            MachineInstruction::Runtime { call } => {
+                self.code.push(0xE8);
+                self.relocate(Relocation::Runtime(*call));
+            }
+        }
+    }
+
+    /// Resolves the jumps, panics on jumps to labels that were never placed.
+    pub fn finish(mut self) -> Encoding {
+        for (position, label) in &self.jumps {
+            let displacement = self.labels[label] as i32 - (*position as i32 + 4);
+            self.code[*position..*position + 4].copy_from_slice(&displacement.to_le_bytes());
+        }
+        Encoding { code: self.code, relocations: self.relocations }
+    }
+
+    fn jump(&mut self, label: Label) {
+        self.jumps.push((self.code.len(), label));
+        self.code.extend([0; 4]);
+    }
+
+    fn relocate(&mut self, relocation: Relocation) {
+        self.relocations.push((self.code.len(), relocation));
+        self.code.extend([0; 4]);
+    }
+
+    /// Encodes the prefix, opcode and operands, `reg` is either a register number or an opcode extension.
+    /// Byte registers need a prefix to mean `spl` to `dil` instead of `ah` to `bh`.
+    fn encode(&mut self, wide: bool, opcode: &[u8], reg: u8, operand: Operand, byte: bool) {
+        let base = match &operand {
+            Operand::Register(register) => register.number(),
+            Operand::Memory(Address::Base { register, .. }) => register.number(),
+            Operand::Memory(Address::Data(_)) => 0,
+        };
+        let rex = 0x40 | (wide as u8) << 3 | (reg >> 3) << 2 | base >> 3;
+        let byte_register = |number: u8| (4..8).contains(&number);
+        let operand_byte_register = matches!(operand, Operand::Register(register) if byte_register(register.number()));
+        if rex != 0x40 || (byte && (byte_register(reg) || operand_byte_register)) {
+            self.code.push(rex);
+        }
+        self.code.extend(opcode);
+        let reg = (reg & 7) << 3;
+        match operand {
+            Operand::Register(register) => self.code.push(0xC0 | reg | (register.number() & 7)),
+            Operand::Memory(Address::Base { register, offset }) => {
+                let base = register.number() & 7;
+                // rbp and r13 cant be used without a displacement
+                let mode = match offset {
+                    0 if base != 5 => 0x00,
+                    -128..=127 => 0x40,
+                    _ => 0x80,
+                };
+                self.code.push(mode | reg | base);
+                // rsp and r12 need a scale index byte
+                if base == 4 { self.code.push(0x24); }
+                match mode {
+                    0x40 => self.code.push(offset as i8 as u8),
+                    0x80 => self.code.extend(offset.to_le_bytes()),
+                    _ => {}
+                }
+            }
+            Operand::Memory(Address::Data(data)) => {
+                self.code.push(reg | 0x05);
+                self.relocate(Relocation::Data(data));
+            }
+        }
+    }
+}
@@ -0,0 +1,205 @@
+use crate::backend::x86_64::allocation::{allocate, Allocation, Location};
+use crate::backend::x86_64::{Address, ArithmeticOperation, Condition, MachineInstruction, MachineProgram, Register, RuntimeCall};
+use crate::ir::cfg::ControlFlowGraph;
+use crate::ir::instruction::{Instruction, LocalId, Operand};
+use crate::ir::Program;
+use crate::parser::r#type::ValueType;
+use crate::tokenizer::token::Operator;
+
+/// Allocatable registers the runtime is allowed to clobber, saved around every runtime call.
+const CALLER_SAVED: [Register; 5] = [Register::Rsi, Register::Rdi, Register::R8, Register::R9, Register::R10];
+
+/// Lowers the IR to x86-64, expects a program without phis.
+pub struct MachineGenerator<'a> {
+    program: &'a Program,
+    allocation: Allocation,
+    instructions: Vec<MachineInstruction>,
+}
+
+impl<'a> MachineGenerator<'a> {
+    pub fn new(program: &'a Program) -> Self {
+        let graph = ControlFlowGraph::new(&program.instructions);
+        MachineGenerator { program, allocation: allocate(program, &graph), instructions: vec![] }
+    }
+
+    pub fn generate(mut self) -> MachineProgram {
+        self.instructions.push(MachineInstruction::Enter { frame_size: self.allocation.frame_size });
+        for instruction in &self.program.instructions {
+            self.generate_instruction(instruction);
+        }
+        // Falling off the end of the program exits successfully
+        self.instructions.push(MachineInstruction::MovImmediate { destination: Register::Rdi, value: 0 });
+        self.instructions.push(MachineInstruction::Runtime { call: RuntimeCall::Exit });
+        MachineProgram { instructions: self.instructions, data: self.program.data.clone() }
+    }
+
+    fn generate_instruction(&mut self, instruction: &Instruction) {
+        match instruction {
+            Instruction::Move { destination, source } => {
+                let source = self.read(source, Register::Rax);
+                self.write(*destination, source);
+            }
+            Instruction::Binary { destination, lhs, operator, rhs } => self.generate_binary(*destination, lhs, operator, rhs),
+            Instruction::Cast { destination, value, to, .. } => {
+                self.read_into(value, Register::Rax);
+                if to.size_of() < self.width(value) {
+                    self.instructions.push(MachineInstruction::ZeroExtend { register: Register::Rax, width: to.size_of() });
+                }
+                self.write(*destination, Register::Rax);
+            }
+            Instruction::AddressOf { destination, local } => {
+                let address = self.stack_address(*local);
+                self.instructions.push(MachineInstruction::Lea { destination: Register::Rax, address });
+                self.write(*destination, Register::Rax);
+            }
+            Instruction::DataAddress { destination, data } => {
+                self.instructions.push(MachineInstruction::Lea { destination: Register::Rax, address: Address::Data(*data) });
+                self.write(*destination, Register::Rax);
+            }
+            Instruction::Load { destination, address } => {
+                let register = self.read(address, Register::Rax);
+                let width = self.program.locals[*destination].type_.size_of();
+                self.instructions.push(MachineInstruction::Load { destination: Register::Rcx, address: Address::Base { register, offset: 0 }, width });
+                self.write(*destination, Register::Rcx);
+            }
+            Instruction::Store { address, value } => {
+                let register = self.read(address, Register::Rax);
+                let source = self.read(value, Register::Rcx);
+                let width = self.width(value);
+                self.instructions.push(MachineInstruction::Store { address: Address::Base { register, offset: 0 }, source, width });
+            }
+            Instruction::Label { label } => self.instructions.push(MachineInstruction::Label { label: *label }),
+            Instruction::Jump { label } => self.instructions.push(MachineInstruction::Jump { label: *label }),
+            Instruction::Branch { condition, if_true, if_false } => {
+                let register = self.read(condition, Register::Rax);
+                self.instructions.push(MachineInstruction::Test { register });
+                self.instructions.push(MachineInstruction::JumpIfNotZero { label: *if_true });
+                self.instructions.push(MachineInstruction::Jump { label: *if_false });
+            }
+            Instruction::Exit { value } => {
+                self.read_into(value, Register::Rdi);
+                self.instructions.push(MachineInstruction::Runtime { call: RuntimeCall::Exit });
+            }
+            Instruction::Print { value } => {
+                let call = match self.program.operand_type(value) {
+                    ValueType::Bool => RuntimeCall::PrintBool,
+                    _ => RuntimeCall::PrintInteger,
+                };
+                self.read_into(value, Register::Rax);
+                self.call(call);
+            }
+            Instruction::PrintString { address, len } => {
+                self.read_into(address, Register::Rax);
+                self.read_into(len, Register::Rcx);
+                self.call(RuntimeCall::Write);
+            }
+            Instruction::Phi { .. } => unreachable!("phis are removed before generating machine code"),
+        }
+    }
+
+    fn generate_binary(&mut self, destination: LocalId, lhs: &Operand, operator: &Operator, rhs: &Operand) {
+        self.read_into(lhs, Register::Rax);
+        let rhs = self.read(rhs, Register::Rcx);
+        let condition = match operator {
+            Operator::Equal => Some(Condition::Equal),
+            Operator::NotEqual => Some(Condition::NotEqual),
+            Operator::Less => Some(Condition::Below),
+            Operator::Greater => Some(Condition::Above),
+            Operator::LessEqual => Some(Condition::BelowEqual),
+            Operator::GreaterEqual => Some(Condition::AboveEqual),
+            _ => None,
+        };
+        if let Some(condition) = condition {
+            self.instructions.push(MachineInstruction::Compare { lhs: Register::Rax, rhs });
+            self.instructions.push(MachineInstruction::SetCondition { condition, destination: Register::Rax });
+            self.write(destination, Register::Rax);
+            return;
+        }
+
+        let operation = match operator {
+            Operator::Plus => ArithmeticOperation::Add,
+            Operator::Minus => ArithmeticOperation::Sub,
+            Operator::Times => ArithmeticOperation::Mul,
+            Operator::Divide => {
+                // The divisor must stay out of rdx, which holds the upper half of the dividend
+                self.instructions.push(MachineInstruction::Divide { divisor: rhs });
+                self.write(destination, Register::Rax);
+                return;
+            }
+            _ => unreachable!("unsupported binary operator {:?}", operator),
+        };
+        self.instructions.push(MachineInstruction::Arithmetic { operation, destination: Register::Rax, source: rhs });
+        let width = self.program.locals[destination].type_.size_of();
+        if width < 8 {
+            self.instructions.push(MachineInstruction::ZeroExtend { register: Register::Rax, width });
+        }
+        self.write(destination, Register::Rax);
+    }
+
+    /// Calls into the runtime with the arguments in rax and rcx.
+    fn call(&mut self, call: RuntimeCall) {
+        for register in CALLER_SAVED {
+            self.instructions.push(MachineInstruction::Push { register });
+        }
+        // Keeps the stack 16 byte aligned
+        self.instructions.push(MachineInstruction::AdjustStack { amount: -8 });
+        self.instructions.push(MachineInstruction::Mov { destination: Register::Rdi, source: Register::Rax });
+        self.instructions.push(MachineInstruction::Mov { destination: Register::Rsi, source: Register::Rcx });
+        self.instructions.push(MachineInstruction::Runtime { call });
+        self.instructions.push(MachineInstruction::AdjustStack { amount: 8 });
+        for register in CALLER_SAVED.iter().rev() {
+            self.instructions.push(MachineInstruction::Pop { register: *register });
+        }
+    }
+
+    /// The register holding the operand, values that arent in a register are loaded into the scratch register.
+    fn read(&mut self, operand: &Operand, scratch: Register) -> Register {
+        match operand {
+            Operand::Constant { value, .. } => {
+                self.instructions.push(MachineInstruction::MovImmediate { destination: scratch, value: *value });
+                scratch
+            }
+            Operand::Local(local) => match self.location(*local) {
+                Location::Register(register) => register,
+                Location::Stack(_) => {
+                    let width = self.program.locals[*local].type_.size_of();
+                    self.instructions.push(MachineInstruction::Load { destination: scratch, address: self.stack_address(*local), width });
+                    scratch
+                }
+            },
+        }
+    }
+
+    fn read_into(&mut self, operand: &Operand, destination: Register) {
+        let source = self.read(operand, destination);
+        if source != destination {
+            self.instructions.push(MachineInstruction::Mov { destination, source });
+        }
+    }
+
+    fn write(&mut self, local: LocalId, source: Register) {
+        match self.location(local) {
+            Location::Register(register) if register == source => {}
+            Location::Register(register) => self.instructions.push(MachineInstruction::Mov { destination: register, source }),
+            Location::Stack(_) => {
+                let width = self.program.locals[local].type_.size_of();
+                self.instructions.push(MachineInstruction::Store { address: self.stack_address(local), source, width });
+            }
+        }
+    }
+
+    fn location(&self, local: LocalId) -> Location {
+        self.allocation.locations[local].expect("Every local that is used has a location!")
+    }
+
+    fn stack_address(&self, local: LocalId) -> Address {
+        match self.location(local) {
+            Location::Stack(offset) => Address::Base { register: Register::Rbp, offset: -offset },
+            Location::Register(_) => unreachable!("locals in registers have no address"),
+        }
+    }
+
+    fn width(&self, operand: &Operand) -> u64 {
+        self.program.operand_type(operand).size_of()
+    }
+}
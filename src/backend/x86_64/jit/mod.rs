@@ -0,0 +1,160 @@
+use std::ffi::c_void;
+use std::io::Write;
+use anyhow::{bail, Context};
+use crate::backend::x86_64::encoder::{Encoder, Relocation};
+use crate::backend::x86_64::{Address, MachineInstruction, MachineProgram, Register, RuntimeCall};
+
+const PAGE_SIZE: usize = 4096;
+const PROT_READ: i32 = 1;
+const PROT_WRITE: i32 = 2;
+const PROT_EXEC: i32 = 4;
+const MAP_PRIVATE: i32 = 0x02;
+const MAP_ANONYMOUS: i32 = 0x20;
+const MAP_FAILED: *mut c_void = !0 as *mut c_void;
+
+extern "C" {
+    fn mmap(address: *mut c_void, length: usize, protection: i32, flags: i32, fd: i32, offset: i64) -> *mut c_void;
+    fn mprotect(address: *mut c_void, length: usize, protection: i32) -> i32;
+    fn munmap(address: *mut c_void, length: usize) -> i32;
+}
+
+/// Registers the System V calling convention expects us to preserve for the caller.
+const CALLEE_SAVED: [Register; 6] = [Register::Rbx, Register::Rbp, Register::R12, Register::R13, Register::R14, Register::R15];
+
+/// Where the prints of a run go, errors are kept until the program returns as we cant unwind through it.
+struct Output<'a> {
+    writer: &'a mut dyn Write,
+    error: Option<std::io::Error>,
+}
+
+impl Output<'_> {
+    fn write(&mut self, bytes: &[u8]) {
+        if self.error.is_none() {
+            self.error = self.writer.write_all(bytes).err();
+        }
+    }
+}
+
+type Callback = extern "C" fn(*mut Output, u64, u64);
+
+extern "C" fn print_integer(output: *mut Output, value: u64, _: u64) {
+    unsafe { &mut *output }.write(value.to_string().as_bytes());
+}
+
+extern "C" fn print_bool(output: *mut Output, value: u64, _: u64) {
+    unsafe { &mut *output }.write((value != 0).to_string().as_bytes());
+}
+
+extern "C" fn write(output: *mut Output, address: u64, len: u64) {
+    let bytes = unsafe { std::slice::from_raw_parts(address as *const u8, len as usize) };
+    unsafe { &mut *output }.write(bytes);
+}
+
+/// Machine code loaded into executable memory of this process.
+/// The code comes first and is followed by the data, which also holds the stack pointer to return with and the output.
+pub struct Jit {
+    memory: *mut u8,
+    size: usize,
+}
+
+impl Jit {
+    pub fn new(program: &MachineProgram) -> anyhow::Result<Self> {
+        let stack_slot = program.data.len();
+        let output_slot = stack_slot + 1;
+        let mut encoder = Encoder::new();
+
+        // Called as `extern "C" fn(*mut Output) -> u64`, then falls through into the program
+        for register in CALLEE_SAVED {
+            encoder.instruction(&MachineInstruction::Push { register });
+        }
+        encoder.instruction(&MachineInstruction::AdjustStack { amount: -8 });
+        encoder.instruction(&MachineInstruction::Store { address: Address::Data(stack_slot), source: Register::Rsp, width: 8 });
+        encoder.instruction(&MachineInstruction::Store { address: Address::Data(output_slot), source: Register::Rdi, width: 8 });
+        for instruction in &program.instructions {
+            encoder.instruction(instruction);
+        }
+
+        // Exiting returns the exit code from wherever the program is
+        let exit = encoder.position();
+        encoder.instruction(&MachineInstruction::Mov { destination: Register::Rax, source: Register::Rdi });
+        encoder.instruction(&MachineInstruction::Load { destination: Register::Rsp, address: Address::Data(stack_slot), width: 8 });
+        encoder.instruction(&MachineInstruction::AdjustStack { amount: 8 });
+        for register in CALLEE_SAVED.iter().rev() {
+            encoder.instruction(&MachineInstruction::Pop { register: *register });
+        }
+        // ret
+        encoder.raw(&[0xC3]);
+
+        // The other calls go to Rust with the output as the first argument
+        let mut callbacks = vec![];
+        let functions = [
+            (RuntimeCall::PrintInteger, print_integer as Callback),
+            (RuntimeCall::PrintBool, print_bool as Callback),
+            (RuntimeCall::Write, write as Callback),
+        ];
+        for (call, function) in functions {
+            callbacks.push((call, encoder.position()));
+            encoder.instruction(&MachineInstruction::Mov { destination: Register::Rdx, source: Register::Rsi });
+            encoder.instruction(&MachineInstruction::Mov { destination: Register::Rsi, source: Register::Rdi });
+            encoder.instruction(&MachineInstruction::Load { destination: Register::Rdi, address: Address::Data(output_slot), width: 8 });
+            encoder.instruction(&MachineInstruction::MovImmediate { destination: Register::Rax, value: function as usize as u64 });
+            // jmp rax
+            encoder.raw(&[0xFF, 0xE0]);
+        }
+        let mut encoding = encoder.finish();
+
+        let code_size = encoding.code.len().next_multiple_of(PAGE_SIZE);
+        let mut data = vec![];
+        let mut data_offsets = vec![];
+        for bytes in program.data.iter().chain([&vec![0; 8], &vec![0; 8]]) {
+            data.resize(data.len().next_multiple_of(8), 0);
+            data_offsets.push(code_size + data.len());
+            data.extend(bytes);
+        }
+        let size = code_size + data.len().next_multiple_of(PAGE_SIZE);
+
+        for (position, relocation) in encoding.relocations {
+            let target = match relocation {
+                Relocation::Data(data) => data_offsets[data],
+                Relocation::Runtime(RuntimeCall::Exit) => exit,
+                Relocation::Runtime(call) => callbacks.iter().find(|(callback, _)| *callback == call).map(|(_, position)| *position).unwrap(),
+            };
+            let displacement = i32::try_from(target as i64 - (position as i64 + 4)).context("The program is too large!")?;
+            encoding.code[position..position + 4].copy_from_slice(&displacement.to_le_bytes());
+        }
+
+        let memory = unsafe { mmap(std::ptr::null_mut(), size, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS, -1, 0) };
+        if memory == MAP_FAILED {
+            bail!("Cannot map memory for the code: {}", std::io::Error::last_os_error());
+        }
+        let jit = Jit { memory: memory as *mut u8, size };
+        unsafe {
+            std::ptr::copy_nonoverlapping(encoding.code.as_ptr(), jit.memory, encoding.code.len());
+            std::ptr::copy_nonoverlapping(data.as_ptr(), jit.memory.add(code_size), data.len());
+            if mprotect(memory, code_size, PROT_READ | PROT_EXEC) != 0 {
+                bail!("Cannot make the code executable: {}", std::io::Error::last_os_error());
+            }
+        }
+        Ok(jit)
+    }
+
+    /// Runs the program until it exits and returns its exit code, everything it prints goes to the writer.
+    pub fn run(&self, writer: &mut dyn Write) -> anyhow::Result<u64> {
+        let mut output = Output { writer, error: None };
+        let entry: extern "C" fn(*mut Output) -> u64 = unsafe { std::mem::transmute(self.memory) };
+        let code = entry(&mut output);
+        if output.error.is_none() {
+            output.error = output.writer.flush().err();
+        }
+        match output.error {
+            Some(error) => Err(error).context("Cannot write the output!"),
+            None => Ok(code),
+        }
+    }
+}
+
+impl Drop for Jit {
+    fn drop(&mut self) {
+        unsafe { munmap(self.memory as *mut c_void, self.size) };
+    }
+}
@@ -0,0 +1,275 @@
+use std::fmt::{Display, Formatter};
+use crate::ir::instruction::{DataId, Label};
+
+pub mod allocation;
+pub mod encoder;
+pub mod generator;
+#[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+pub mod jit;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum Register {
+    Rax, Rcx, Rdx, Rbx, Rsp, Rbp, Rsi, Rdi,
+    R8, R9, R10, R11, R12, R13, R14, R15,
+}
+
+impl Register {
+    /// The number of the register in the instruction encoding.
+    pub fn number(&self) -> u8 {
+        *self as u8
+    }
+
+    /// The name of the lowest `width` bytes of the register.
+    pub fn name(&self, width: u64) -> &'static str {
+        const NAMES: [[&str; 4]; 16] = [
+            ["al", "ax", "eax", "rax"], ["cl", "cx", "ecx", "rcx"], ["dl", "dx", "edx", "rdx"], ["bl", "bx", "ebx", "rbx"],
+            ["spl", "sp", "esp", "rsp"], ["bpl", "bp", "ebp", "rbp"], ["sil", "si", "esi", "rsi"], ["dil", "di", "edi", "rdi"],
+            ["r8b", "r8w", "r8d", "r8"], ["r9b", "r9w", "r9d", "r9"], ["r10b", "r10w", "r10d", "r10"], ["r11b", "r11w", "r11d", "r11"],
+            ["r12b", "r12w", "r12d", "r12"], ["r13b", "r13w", "r13d", "r13"], ["r14b", "r14w", "r14d", "r14"], ["r15b", "r15w", "r15d", "r15"],
+        ];
+        let index = match width {
+            1 => 0,
+            2 => 1,
+            4 => 2,
+            _ => 3,
+        };
+        NAMES[*self as usize][index]
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Address {
+    Base { register: Register, offset: i32 },
+    /// Relative to the instruction pointer.
+    Data(DataId),
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ArithmeticOperation {
+    Add,
+    Sub,
+    Mul,
+}
+
+/// Unsigned conditions, as all of our integers are unsigned.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Condition {
+    Equal,
+    NotEqual,
+    Below,
+    Above,
+    BelowEqual,
+    AboveEqual,
+}
+
+impl Condition {
+    pub fn suffix(&self) -> &'static str {
+        match self {
+            Condition::Equal => "e",
+            Condition::NotEqual => "ne",
+            Condition::Below => "b",
+            Condition::Above => "a",
+            Condition::BelowEqual => "be",
+            Condition::AboveEqual => "ae",
+        }
+    }
+
+    /// The condition code in the `setcc` and `jcc` opcodes.
+    pub fn code(&self) -> u8 {
+        match self {
+            Condition::Equal => 0x4,
+            Condition::NotEqual => 0x5,
+            Condition::Below => 0x2,
+            Condition::Above => 0x7,
+            Condition::BelowEqual => 0x6,
+            Condition::AboveEqual => 0x3,
+        }
+    }
+}
+
+/// Services the program needs from its environment, the arguments are passed in `rdi` and `rsi`.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum RuntimeCall {
+    /// Ends the program with the exit code in `rdi`, never returns.
+    Exit,
+    PrintInteger,
+    PrintBool,
+    /// Writes `rsi` bytes starting at the address in `rdi`.
+    Write,
+}
+
+impl RuntimeCall {
+    pub fn name(&self) -> &'static str {
+        match self {
+            RuntimeCall::Exit => "he_exit",
+            RuntimeCall::PrintInteger => "he_print_integer",
+            RuntimeCall::PrintBool => "he_print_bool",
+            RuntimeCall::Write => "he_write",
+        }
+    }
+}
+
+/// Registers always hold their value zero extended to 64 bits, only memory is accessed with the width of the value.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum MachineInstruction {
+    /// Sets up the frame pointer and reserves the stack for the locals.
+    Enter { frame_size: i32 },
+    MovImmediate { destination: Register, value: u64 },
+    Mov { destination: Register, source: Register },
+    /// Zero extends the `width` bytes read.
+    Load { destination: Register, address: Address, width: u64 },
+    Store { address: Address, source: Register, width: u64 },
+    Lea { destination: Register, address: Address },
+    Arithmetic { operation: ArithmeticOperation, destination: Register, source: Register },
+    /// Divides `rax` by the divisor, the quotient ends up in `rax`.
+    Divide { divisor: Register },
+    /// Clears everything above the lowest `width` bytes.
+    ZeroExtend { register: Register, width: u64 },
+    Compare { lhs: Register, rhs: Register },
+    /// Sets the register to 1 if the condition of the last comparison holds and to 0 otherwise.
+    SetCondition { condition: Condition, destination: Register },
+    Test { register: Register },
+    Label { label: Label },
+    Jump { label: Label },
+    JumpIfNotZero { label: Label },
+    Push { register: Register },
+    Pop { register: Register },
+    /// Moves the stack pointer, a negative amount reserves stack.
+    AdjustStack { amount: i32 },
+    Runtime { call: RuntimeCall },
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct MachineProgram {
+    pub instructions: Vec<MachineInstruction>,
+    pub data: Vec<Vec<u8>>,
+}
+
+impl Display for Address {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Address::Base { register, offset: 0 } => write!(f, "[{}]", register.name(8)),
+            Address::Base { register, offset } if *offset < 0 => write!(f, "[{} - {}]", register.name(8), -(*offset as i64)),
+            Address::Base { register, offset } => write!(f, "[{} + {}]", register.name(8), offset),
+            Address::Data(data) => write!(f, "[rip + he_data{}]", data),
+        }
+    }
+}
+
+fn pointer_size(width: u64) -> &'static str {
+    match width {
+        1 => "byte",
+        2 => "word",
+        4 => "dword",
+        _ => "qword",
+    }
+}
+
+impl Display for MachineInstruction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MachineInstruction::Enter { frame_size } => {
+                write!(f, "    mov rbp, rsp")?;
+                if *frame_size > 0 { write!(f, "\n    sub rsp, {}", frame_size)?; }
+                Ok(())
+            }
+            MachineInstruction::MovImmediate { destination, value } if *value <= u32::MAX as u64 => write!(f, "    mov {}, {}", destination.name(4), value),
+            MachineInstruction::MovImmediate { destination, value } => write!(f, "    movabs {}, {}", destination.name(8), value),
+            MachineInstruction::Mov { destination, source } => write!(f, "    mov {}, {}", destination.name(8), source.name(8)),
+            MachineInstruction::Load { destination, address, width: width @ (1 | 2) } => write!(f, "    movzx {}, {} ptr {}", destination.name(4), pointer_size(*width), address),
+            MachineInstruction::Load { destination, address, width } => write!(f, "    mov {}, {} ptr {}", destination.name(*width), pointer_size(*width), address),
+            MachineInstruction::Store { address, source, width } => write!(f, "    mov {} ptr {}, {}", pointer_size(*width), address, source.name(*width)),
+            MachineInstruction::Lea { destination, address } => write!(f, "    lea {}, {}", destination.name(8), address),
+            MachineInstruction::Arithmetic { operation, destination, source } => {
+                let mnemonic = match operation {
+                    ArithmeticOperation::Add => "add",
+                    ArithmeticOperation::Sub => "sub",
+                    ArithmeticOperation::Mul => "imul",
+                };
+                write!(f, "    {} {}, {}", mnemonic, destination.name(8), source.name(8))
+            }
+            MachineInstruction::Divide { divisor } => write!(f, "    xor edx, edx\n    div {}", divisor.name(8)),
+            MachineInstruction::ZeroExtend { register, width: width @ (1 | 2) } => write!(f, "    movzx {}, {}", register.name(4), register.name(*width)),
+            MachineInstruction::ZeroExtend { register, .. } => write!(f, "    mov {}, {}", register.name(4), register.name(4)),
+            MachineInstruction::Compare { lhs, rhs } => write!(f, "    cmp {}, {}", lhs.name(8), rhs.name(8)),
+            MachineInstruction::SetCondition { condition, destination } => write!(f, "    set{} {}\n    movzx {}, {}", condition.suffix(), destination.name(1), destination.name(4), destination.name(1)),
+            MachineInstruction::Test { register } => write!(f, "    test {}, {}", register.name(8), register.name(8)),
+            MachineInstruction::Label { label } => write!(f, ".L{}:", label),
+            MachineInstruction::Jump { label } => write!(f, "    jmp .L{}", label),
+            MachineInstruction::JumpIfNotZero { label } => write!(f, "    jnz .L{}", label),
+            MachineInstruction::Push { register } => write!(f, "    push {}", register.name(8)),
+            MachineInstruction::Pop { register } => write!(f, "    pop {}", register.name(8)),
+            MachineInstruction::AdjustStack { amount } if *amount < 0 => write!(f, "    sub rsp, {}", -amount),
+            MachineInstruction::AdjustStack { amount } => write!(f, "    add rsp, {}", amount),
+            MachineInstruction::Runtime { call } => write!(f, "    call {}", call.name()),
+        }
+    }
+}
+
+/// The runtime for Linux, talking to the kernel with system calls.
+const LINUX_RUNTIME: &str = "he_exit:
+    mov eax, 60
+    syscall
+he_print_integer:
+    mov rax, rdi
+    lea rsi, [rsp - 1]
+    mov ecx, 10
+.Lhe_digit:
+    xor edx, edx
+    div rcx
+    add dl, 48
+    mov byte ptr [rsi], dl
+    dec rsi
+    test rax, rax
+    jnz .Lhe_digit
+    inc rsi
+    mov rdx, rsp
+    sub rdx, rsi
+    mov edi, 1
+    mov eax, 1
+    syscall
+    ret
+he_print_bool:
+    lea rsi, [rip + he_false]
+    mov edx, 5
+    test rdi, rdi
+    jz .Lhe_write_bool
+    lea rsi, [rip + he_true]
+    mov edx, 4
+.Lhe_write_bool:
+    mov edi, 1
+    mov eax, 1
+    syscall
+    ret
+he_write:
+    mov rdx, rsi
+    mov rsi, rdi
+    mov edi, 1
+    mov eax, 1
+    syscall
+    ret
+";
+
+impl Display for MachineProgram {
+    /// GNU assembler syntax for a static Linux executable, assemble with `as` and link with `ld`.
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, ".intel_syntax noprefix")?;
+        writeln!(f, ".section .rodata")?;
+        writeln!(f, "he_true: .ascii \"true\"")?;
+        writeln!(f, "he_false: .ascii \"false\"")?;
+        for (id, data) in self.data.iter().enumerate() {
+            let bytes: Vec<String> = data.iter().map(|byte| byte.to_string()).collect();
+            match bytes.is_empty() {
+                true => writeln!(f, "he_data{}:", id)?,
+                false => writeln!(f, "he_data{}: .byte {}", id, bytes.join(", "))?,
+            }
+        }
+        writeln!(f, ".text")?;
+        writeln!(f, ".globl _start")?;
+        writeln!(f, "_start:")?;
+        for instruction in &self.instructions {
+            writeln!(f, "{}", instruction)?;
+        }
+        write!(f, "{}", LINUX_RUNTIME)
+    }
+}
@@ -0,0 +1,102 @@
+use std::fmt::{Display, Formatter};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Default, Serialize, Deserialize)]
+pub struct Span {
+    pub line: usize,
+    pub column: usize,
+    pub len: usize,
+}
+
+impl Display for Span {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Severity::Error => f.write_str("error"),
+            Severity::Warning => f.write_str("warning"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// Stable identifier of a warning, so it can be looked up independent of the message.
+    pub code: Option<&'static str>,
+    pub message: String,
+    pub span: Option<Span>,
+    pub help: Option<String>,
+}
+
+impl Diagnostic {
+    pub fn error(message: impl Into<String>, span: Span) -> Self {
+        Self {
+            severity: Severity::Error,
+            code: None,
+            message: message.into(),
+            span: Some(span),
+            help: None,
+        }
+    }
+
+    pub fn warning(code: &'static str, message: impl Into<String>, span: Option<Span>) -> Self {
+        Self {
+            severity: Severity::Warning,
+            code: Some(code),
+            message: message.into(),
+            span,
+            help: None,
+        }
+    }
+
+    pub fn with_help(mut self, help: impl Into<String>) -> Self {
+        self.help = Some(help.into());
+        self
+    }
+
+    /// Renders the diagnostic together with the source line it points at.
+    pub fn render(&self, source: &str) -> String {
+        let mut rendered = match self.code {
+            Some(code) => format!("{}[{}]: {}\n", self.severity, code, self.message),
+            None => format!("{}: {}\n", self.severity, self.message),
+        };
+
+        if let Some(span) = self.span {
+            let gutter = " ".repeat(span.line.to_string().len());
+            rendered.push_str(&format!("{} --> {}\n", gutter, span));
+            if let Some(line) = source.lines().nth(span.line - 1) {
+                rendered.push_str(&format!("{} |\n", gutter));
+                rendered.push_str(&format!("{} | {}\n", span.line, line));
+                rendered.push_str(&format!("{} | {}{}\n", gutter, " ".repeat(span.column - 1), "^".repeat(span.len.max(1))));
+            }
+        }
+        if let Some(help) = &self.help {
+            rendered.push_str(&format!("  = help: {}\n", help));
+        }
+
+        rendered
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)?;
+        if let Some(span) = self.span {
+            write!(f, " (at {})", span)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for Diagnostic {}
@@ -0,0 +1,358 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt::Write;
+use crate::ir::instruction::{Instruction, Label, LocalId, Operand};
+use crate::ir::Program;
+
+pub type BlockId = usize;
+
+/// Instructions that always run one after another, only the last one may jump.
+#[derive(Debug, Clone, Default)]
+pub struct BasicBlock {
+    /// Starts with the label of the block if it has one.
+    pub instructions: Vec<Instruction>,
+    pub predecessors: Vec<BlockId>,
+    pub successors: Vec<BlockId>,
+}
+
+impl BasicBlock {
+    pub fn label(&self) -> Option<Label> {
+        match self.instructions.first() {
+            Some(Instruction::Label { label }) => Some(*label),
+            _ => None,
+        }
+    }
+
+    /// Blocks that dont end in a jump, branch or exit continue with the next block.
+    pub fn falls_through(&self) -> bool {
+        !self.instructions.last().is_some_and(Instruction::is_terminator)
+    }
+}
+
+/// A natural loop, the header dominates every block of the loop.
+#[derive(Debug, Clone)]
+pub struct Loop {
+    pub header: BlockId,
+    pub blocks: BTreeSet<BlockId>,
+    /// The innermost loop containing this one.
+    pub parent: Option<usize>,
+    /// Loops that arent inside another loop have a depth of 1.
+    pub depth: usize,
+}
+
+/// The basic blocks of a program in their original order, the first block is the entry.
+#[derive(Debug, Clone)]
+pub struct ControlFlowGraph {
+    pub blocks: Vec<BasicBlock>,
+    /// The immediate dominator of every block, `None` for the entry and unreachable blocks.
+    pub dominators: Vec<Option<BlockId>>,
+    pub loops: Vec<Loop>,
+}
+
+impl ControlFlowGraph {
+    pub fn new(instructions: &[Instruction]) -> Self {
+        let mut blocks: Vec<BasicBlock> = vec![BasicBlock::default()];
+        for instruction in instructions {
+            let current = blocks.last_mut().unwrap();
+            if matches!(instruction, Instruction::Label { .. }) && !current.instructions.is_empty() {
+                blocks.push(BasicBlock::default());
+            }
+            let current = blocks.last_mut().unwrap();
+            current.instructions.push(instruction.clone());
+            if !current.falls_through() {
+                blocks.push(BasicBlock::default());
+            }
+        }
+        // Nothing can jump to an empty block without a label
+        if blocks.len() > 1 && blocks.last().unwrap().instructions.is_empty() {
+            blocks.pop();
+        }
+
+        let mut graph = Self { blocks, dominators: vec![], loops: vec![] };
+        graph.recompute();
+        graph
+    }
+
+    /// Updates the edges, dominators and loops after the blocks changed.
+    pub fn recompute(&mut self) {
+        self.compute_edges();
+        self.compute_dominators();
+        self.compute_loops();
+    }
+
+    pub fn block_of_label(&self, label: Label) -> Option<BlockId> {
+        self.blocks.iter().position(|block| block.label() == Some(label))
+    }
+
+    /// The instructions of all blocks in order, ready to be turned back into a program.
+    pub fn instructions(&self) -> Vec<Instruction> {
+        self.blocks.iter()
+            .flat_map(|block| block.instructions.iter().cloned())
+            .collect()
+    }
+
+    pub fn register_locals(&self, program: &Program) -> BTreeSet<LocalId> {
+        program.register_locals(self.blocks.iter().flat_map(|block| block.instructions.iter()))
+    }
+
+    /// The tracked locals that may still be read after entering each block. Phis read their sources on the edges,
+    /// treating them as read at the start of their block only keeps more alive.
+    pub fn live_in(&self, tracked: &BTreeSet<LocalId>) -> Vec<HashSet<LocalId>> {
+        let mut live_in: Vec<HashSet<LocalId>> = vec![HashSet::new(); self.blocks.len()];
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for id in (0..self.blocks.len()).rev() {
+                let mut live = self.live_out(&live_in, id);
+                for instruction in self.blocks[id].instructions.iter().rev() {
+                    transfer(instruction, &mut live, tracked);
+                }
+                if live != live_in[id] {
+                    live_in[id] = live;
+                    changed = true;
+                }
+            }
+        }
+        live_in
+    }
+
+    pub fn live_out(&self, live_in: &[HashSet<LocalId>], block: BlockId) -> HashSet<LocalId> {
+        self.blocks[block].successors.iter()
+            .flat_map(|successor| live_in[*successor].iter().copied())
+            .collect()
+    }
+
+    pub fn dominates(&self, dominator: BlockId, mut block: BlockId) -> bool {
+        loop {
+            if block == dominator { return true; }
+            match self.dominators[block] {
+                Some(parent) => block = parent,
+                None => return false,
+            }
+        }
+    }
+
+    /// The blocks every block immediately dominates.
+    pub fn dominator_tree(&self) -> Vec<Vec<BlockId>> {
+        let mut children = vec![vec![]; self.blocks.len()];
+        for (block, dominator) in self.dominators.iter().enumerate() {
+            if let Some(dominator) = dominator {
+                children[*dominator].push(block);
+            }
+        }
+        children
+    }
+
+    /// The blocks where the dominance of every block ends, this is where definitions meet.
+    pub fn dominance_frontiers(&self) -> Vec<BTreeSet<BlockId>> {
+        let mut frontiers = vec![BTreeSet::new(); self.blocks.len()];
+        for (block, basic_block) in self.blocks.iter().enumerate() {
+            if basic_block.predecessors.len() < 2 || !self.is_reachable(block) { continue; }
+            for &predecessor in &basic_block.predecessors {
+                let mut runner = Some(predecessor);
+                while let Some(current) = runner.filter(|current| self.is_reachable(*current) && Some(*current) != self.dominators[block]) {
+                    frontiers[current].insert(block);
+                    runner = self.dominators[current];
+                }
+            }
+        }
+        frontiers
+    }
+
+    /// Gives every block a label, so that jumps and phis can refer to all of them.
+    pub fn label_blocks(&mut self, program: &mut Program) {
+        for block in self.blocks.iter_mut().filter(|block| block.label().is_none()) {
+            block.instructions.insert(0, Instruction::Label { label: program.new_label() });
+        }
+    }
+
+    pub fn is_reachable(&self, block: BlockId) -> bool {
+        block == 0 || self.dominators[block].is_some()
+    }
+
+    /// The depth of the innermost loop containing the block, 0 outside of loops.
+    pub fn loop_depth(&self, block: BlockId) -> usize {
+        self.loops.iter()
+            .filter(|loop_| loop_.blocks.contains(&block))
+            .map(|loop_| loop_.depth)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Reachable blocks ordered so that every block comes before its successors, ignoring back edges.
+    pub fn reverse_postorder(&self) -> Vec<BlockId> {
+        let mut visited = vec![false; self.blocks.len()];
+        let mut postorder = vec![];
+        // Each entry remembers how many successors were already visited
+        let mut stack = vec![(0, 0)];
+        visited[0] = true;
+        while let Some((block, next)) = stack.pop() {
+            match self.blocks[block].successors.get(next) {
+                Some(&successor) => {
+                    stack.push((block, next + 1));
+                    if !visited[successor] {
+                        visited[successor] = true;
+                        stack.push((successor, 0));
+                    }
+                }
+                None => postorder.push(block),
+            }
+        }
+        postorder.reverse();
+        postorder
+    }
+
+    fn compute_edges(&mut self) {
+        let labels: HashMap<Label, BlockId> = self.blocks.iter().enumerate()
+            .filter_map(|(id, block)| block.label().map(|label| (label, id)))
+            .collect();
+
+        for id in 0..self.blocks.len() {
+            let successors = match self.blocks[id].instructions.last() {
+                Some(Instruction::Jump { label }) => vec![labels[label]],
+                Some(Instruction::Branch { if_true, if_false, .. }) if if_true == if_false => vec![labels[if_true]],
+                Some(Instruction::Branch { if_true, if_false, .. }) => vec![labels[if_true], labels[if_false]],
+                Some(Instruction::Exit { .. }) => vec![],
+                _ if id + 1 < self.blocks.len() => vec![id + 1],
+                _ => vec![],
+            };
+            self.blocks[id].successors = successors;
+            self.blocks[id].predecessors.clear();
+        }
+        for id in 0..self.blocks.len() {
+            for successor in self.blocks[id].successors.clone() {
+                self.blocks[successor].predecessors.push(id);
+            }
+        }
+    }
+
+    /// "A Simple, Fast Dominance Algorithm" by Cooper, Harvey and Kennedy.
+    fn compute_dominators(&mut self) {
+        let order = self.reverse_postorder();
+        let mut position = vec![usize::MAX; self.blocks.len()];
+        for (index, block) in order.iter().enumerate() {
+            position[*block] = index;
+        }
+
+        let mut dominators: Vec<Option<BlockId>> = vec![None; self.blocks.len()];
+        dominators[0] = Some(0);
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &block in order.iter().skip(1) {
+                let mut processed = self.blocks[block].predecessors.iter().filter(|predecessor| dominators[**predecessor].is_some());
+                let mut dominator = *processed.next().expect("Reachable blocks have a processed predecessor!");
+                for &predecessor in processed {
+                    dominator = intersect(&dominators, &position, predecessor, dominator);
+                }
+                if dominators[block] != Some(dominator) {
+                    dominators[block] = Some(dominator);
+                    changed = true;
+                }
+            }
+        }
+        dominators[0] = None;
+        self.dominators = dominators;
+    }
+
+    fn compute_loops(&mut self) {
+        let mut loops: Vec<Loop> = vec![];
+        for block in 0..self.blocks.len() {
+            for &header in &self.blocks[block].successors {
+                if !self.is_reachable(block) || !self.dominates(header, block) { continue; }
+
+                // Everything that reaches the back edge without passing the header belongs to the loop
+                let mut blocks = BTreeSet::from([header]);
+                let mut worklist = vec![block];
+                while let Some(current) = worklist.pop() {
+                    if blocks.insert(current) {
+                        worklist.extend(self.blocks[current].predecessors.iter().filter(|predecessor| self.is_reachable(**predecessor)));
+                    }
+                }
+                match loops.iter_mut().find(|loop_| loop_.header == header) {
+                    Some(loop_) => loop_.blocks.extend(blocks),
+                    None => loops.push(Loop { header, blocks, parent: None, depth: 1 }),
+                }
+            }
+        }
+
+        // Outer loops contain more blocks, so they get their depth before their inner loops
+        loops.sort_by_key(|loop_| std::cmp::Reverse(loop_.blocks.len()));
+        for index in 0..loops.len() {
+            let parent = (0..index).rev().find(|outer| loops[*outer].blocks.contains(&loops[index].header));
+            loops[index].parent = parent;
+            loops[index].depth = parent.map_or(1, |parent| loops[parent].depth + 1);
+        }
+        self.loops = loops;
+    }
+
+    /// The graph in the DOT format of Graphviz, loop headers are highlighted and back edges dashed.
+    pub fn to_dot(&self, program: &Program) -> String {
+        let mut dot = String::new();
+        writeln!(dot, "digraph cfg {{").unwrap();
+        writeln!(dot, "    node [shape=box, fontname=\"monospace\"];").unwrap();
+
+        for (id, block) in self.blocks.iter().enumerate() {
+            let mut label = format!("B{}", id);
+            if let Some(dominator) = self.dominators[id] {
+                write!(label, " (idom B{})", dominator).unwrap();
+            }
+            if self.loop_depth(id) > 0 {
+                write!(label, " (loop depth {})", self.loop_depth(id)).unwrap();
+            }
+            if !self.is_reachable(id) {
+                label.push_str(" (unreachable)");
+            }
+            label.push_str("\\l");
+            for instruction in &block.instructions {
+                let line = match instruction {
+                    Instruction::Label { .. } => program.format_instruction(instruction),
+                    _ => format!("    {}", program.format_instruction(instruction)),
+                };
+                write!(label, "{}\\l", escape(&line)).unwrap();
+            }
+            let style = if self.loops.iter().any(|loop_| loop_.header == id) { ", style=filled, fillcolor=lightyellow" } else { "" };
+            writeln!(dot, "    B{} [label=\"{}\"{}];", id, label, style).unwrap();
+        }
+
+        for (id, block) in self.blocks.iter().enumerate() {
+            for (index, successor) in block.successors.iter().enumerate() {
+                let mut attributes = vec![];
+                if block.successors.len() == 2 {
+                    attributes.push(if index == 0 { "label=\"true\"" } else { "label=\"false\"" });
+                }
+                if self.is_reachable(id) && self.dominates(*successor, id) {
+                    attributes.push("style=dashed");
+                }
+                match attributes.is_empty() {
+                    true => writeln!(dot, "    B{} -> B{};", id, successor).unwrap(),
+                    false => writeln!(dot, "    B{} -> B{} [{}];", id, successor, attributes.join(", ")).unwrap(),
+                }
+            }
+        }
+        writeln!(dot, "}}").unwrap();
+        dot
+    }
+}
+
+fn intersect(dominators: &[Option<BlockId>], position: &[usize], mut lhs: BlockId, mut rhs: BlockId) -> BlockId {
+    while lhs != rhs {
+        while position[lhs] > position[rhs] { lhs = dominators[lhs].unwrap(); }
+        while position[rhs] > position[lhs] { rhs = dominators[rhs].unwrap(); }
+    }
+    lhs
+}
+
+fn escape(line: &str) -> String {
+    line.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Turns the tracked locals live after the instruction into those live before it.
+pub fn transfer(instruction: &Instruction, live: &mut HashSet<LocalId>, tracked: &BTreeSet<LocalId>) {
+    if let Some(destination) = instruction.destination() {
+        live.remove(&destination);
+    }
+    live.extend(instruction.operands().into_iter().filter_map(|operand| match operand {
+        Operand::Local(local) if tracked.contains(local) => Some(*local),
+        _ => None,
+    }));
+}
@@ -0,0 +1,719 @@
+use std::collections::HashMap;
+use crate::ir::instruction::{Instruction, LocalId, Operand};
+use crate::ir::Program;
+use crate::parser::expr::Expression;
+use crate::parser::r#type::ValueType;
+use crate::parser::stmt::Statement;
+use crate::tokenizer::token::{Literal, literal_to_char, literal_to_string, Operator, string_literal_bytes};
+
+/// Lowers resolved statements into three address code.
+pub struct IrGenerator {
+    program: Program,
+    variables: HashMap<Literal, LocalId>,
+    /// Exit with `BOUNDS_CHECK_EXIT_CODE` when an index only known at runtime is out of bounds.
+    bounds_checks: bool,
+}
+
+pub const BOUNDS_CHECK_EXIT_CODE: u64 = 101;
+/// Every backend exits with this code when dividing by zero, instead of trapping in its own way.
+pub const DIVISION_BY_ZERO_EXIT_CODE: u64 = 102;
+
+impl Default for IrGenerator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl IrGenerator {
+    pub fn new() -> Self {
+        Self {
+            program: Program::default(),
+            variables: HashMap::new(),
+            bounds_checks: false,
+        }
+    }
+
+    pub fn with_bounds_checks(mut self, bounds_checks: bool) -> Self {
+        self.bounds_checks = bounds_checks;
+        self
+    }
+
+    pub fn generate(mut self, statements: &[Statement]) -> Program {
+        for statement in statements {
+            self.generate_statement(statement);
+        }
+        self.program
+    }
+
+    fn generate_statement(&mut self, statement: &Statement) {
+        match statement {
+            Statement::Let { identifier, type_, expression, .. } => {
+                let type_ = type_.as_ref().expect("Let statements are typed by the semantic analysis!");
+                let local = self.program.new_local(Some(identifier.clone()), type_.clone());
+                if let Some(expression) = expression {
+                    if type_.is_aggregate() {
+                        let address = self.emit_address_of(local);
+                        self.generate_aggregate(address, expression);
+                    } else {
+                        let source = self.generate_expression(expression);
+                        self.emit(Instruction::Move { destination: local, source });
+                    }
+                }
+                // Registered afterwards so the initializer still sees a shadowed variable
+                self.variables.insert(identifier.clone(), local);
+            }
+            Statement::Assign { assignee, operator, expression } => {
+                // Plain variables can be written directly instead of going through their address
+                let variable = match assignee {
+                    Expression::Reference { reference } => match reference.as_ref() {
+                        Expression::IdentifierLiteral { value, type_: Some(type_), .. } if !type_.is_aggregate() => Some(self.variables[value]),
+                        _ => None,
+                    },
+                    _ => None,
+                };
+                let address = match variable {
+                    Some(_) => None,
+                    None => Some(self.generate_expression(assignee)),
+                };
+
+                if let (Some(address), true) = (&address, expression.get_type().is_aggregate()) {
+                    self.generate_aggregate(address.clone(), expression);
+                    return;
+                }
+
+                let value = if operator.is_some() {
+                    // The address of the assignee is only evaluated once for its old and its new value
+                    let current = match (variable, &address) {
+                        (Some(variable), _) => Operand::Local(variable),
+                        (None, Some(address)) => {
+                            let destination = self.new_temporary(assignee.get_type().points_to());
+                            self.emit(Instruction::Load { destination, address: address.clone() });
+                            Operand::Local(destination)
+                        }
+                        (None, None) => unreachable!(),
+                    };
+                    self.generate_compound_value(expression, current)
+                } else {
+                    self.generate_expression(expression)
+                };
+
+                match (variable, address) {
+                    (Some(destination), _) => self.emit(Instruction::Move { destination, source: value }),
+                    (None, Some(address)) => self.emit(Instruction::Store { address, value }),
+                    (None, None) => unreachable!(),
+                }
+            }
+            Statement::Const { .. } => {}
+            Statement::Exit { expression, .. } => {
+                let value = self.generate_expression(expression);
+                self.emit(Instruction::Exit { value });
+            }
+            Statement::Print { expression, .. } => {
+                match expression.get_type() {
+                    ValueType::Array { len, .. } => {
+                        let address = self.generate_address(expression);
+                        self.generate_print_chars(address, Operand::Constant { value: len as u64, type_: ValueType::U64 });
+                    }
+                    ValueType::Slice { .. } => {
+                        let (address, len) = self.generate_slice(expression);
+                        self.generate_print_chars(address, len);
+                    }
+                    ValueType::Str => {
+                        let (address, len) = self.generate_slice(expression);
+                        self.emit(Instruction::PrintString { address, len });
+                    }
+                    ValueType::Char => {
+                        let value = self.generate_expression(expression);
+                        let buffer = self.new_temporary(ValueType::Array { content_type: Box::new(ValueType::U8), len: 4 });
+                        let buffer = self.emit_address_of(buffer);
+                        self.emit_print_char(value, buffer);
+                    }
+                    _ => {
+                        let value = self.generate_expression(expression);
+                        self.emit(Instruction::Print { value });
+                    }
+                }
+                let newline = self.program.new_data(b"\n".to_vec());
+                let address = self.new_temporary(ValueType::Pointer { points_to: Box::new(ValueType::U8), mutable: false });
+                self.emit(Instruction::DataAddress { destination: address, data: newline });
+                self.emit(Instruction::PrintString { address: Operand::Local(address), len: Operand::Constant { value: 1, type_: ValueType::U64 } });
+            }
+            Statement::Expression { expression } => {
+                self.generate_expression(expression);
+            }
+        }
+    }
+
+    fn generate_expression(&mut self, expression: &Expression) -> Operand {
+        match expression {
+            Expression::NumberLiteral { value, internal_type, .. } => Operand::Constant {
+                value: literal_to_string(value).parse().expect("Number literals are validated by the parser!"),
+                type_: internal_type.clone(),
+            },
+            Expression::CharLiteral { value, .. } => Operand::Constant { value: literal_to_char(value).expect("Char literals are validated by the tokenizer!"), type_: ValueType::Char },
+            Expression::BoolLiteral { value, .. } => Operand::Constant { value: *value as u64, type_: ValueType::Bool },
+            Expression::IdentifierLiteral { value, .. } => Operand::Local(self.variables[value]),
+            Expression::Operation { lhs, operator, rhs, .. } if lhs.get_type() == ValueType::Str => self.generate_string_comparison(lhs, operator, rhs),
+            Expression::Operation { lhs, operator, rhs, type_ } => {
+                let lhs = self.generate_expression(lhs);
+                let rhs = self.generate_expression(rhs);
+                if *operator == Operator::Divide { self.emit_division_check(&rhs); }
+                let destination = self.new_temporary(type_.clone().unwrap());
+                self.emit(Instruction::Binary { destination, lhs, operator: operator.clone(), rhs });
+                Operand::Local(destination)
+            }
+            Expression::Logical { lhs, operator, rhs } => {
+                let result = self.new_temporary(ValueType::Bool);
+                let evaluate_rhs = self.program.new_label();
+                let end = self.program.new_label();
+
+                let lhs = self.generate_expression(lhs);
+                self.emit(Instruction::Move { destination: result, source: lhs.clone() });
+                let (if_true, if_false) = match operator {
+                    Operator::LogicalAnd => (evaluate_rhs, end),
+                    Operator::LogicalOr => (end, evaluate_rhs),
+                    _ => unreachable!(),
+                };
+                self.emit(Instruction::Branch { condition: lhs, if_true, if_false });
+
+                self.emit(Instruction::Label { label: evaluate_rhs });
+                let rhs = self.generate_expression(rhs);
+                self.emit(Instruction::Move { destination: result, source: rhs });
+                self.emit(Instruction::Jump { label: end });
+
+                self.emit(Instruction::Label { label: end });
+                Operand::Local(result)
+            }
+            Expression::Array { .. } |
+            Expression::Repeat { .. } => {
+                let local = self.new_temporary(expression.get_type());
+                let address = self.emit_address_of(local);
+                self.generate_aggregate(address, expression);
+                Operand::Local(local)
+            }
+            Expression::Reference { reference } => self.generate_address(reference),
+            Expression::Deref { value } => {
+                let address = self.generate_expression(value);
+                let destination = self.new_temporary(expression.get_type());
+                self.emit(Instruction::Load { destination, address });
+                Operand::Local(destination)
+            }
+            Expression::Access { value, .. } => {
+                let address = self.generate_address(expression);
+                // Accessing an array yields the address of the element, accessing a pointer the element itself
+                if let ValueType::Array { .. } = value.get_type() {
+                    return address;
+                }
+                let destination = self.new_temporary(expression.get_type());
+                self.emit(Instruction::Load { destination, address });
+                Operand::Local(destination)
+            }
+            Expression::Cast { to: ValueType::Slice { .. }, .. } |
+            Expression::Slice { .. } |
+            Expression::StringLiteral { .. } => {
+                let local = self.new_temporary(expression.get_type());
+                let address = self.emit_address_of(local);
+                self.generate_aggregate(address, expression);
+                Operand::Local(local)
+            }
+            Expression::Length { value } => match value.get_type().known_len() {
+                Some(len) => Operand::Constant { value: len as u64, type_: ValueType::U64 },
+                None => self.generate_slice(value).1,
+            },
+            Expression::Cast { value, to } => {
+                let from = value.get_type();
+                let value = self.generate_expression(value);
+                self.emit_cast(value, from, to)
+            }
+        }
+    }
+
+    /// Generates the pointer to the first element and the length of a slice or string.
+    fn generate_slice(&mut self, expression: &Expression) -> (Operand, Operand) {
+        let content_type = match expression.get_type() {
+            ValueType::Slice { content_type, .. } => *content_type,
+            ValueType::Str => ValueType::U8,
+            _ => unreachable!(),
+        };
+        match expression {
+            Expression::StringLiteral { value, .. } => {
+                let bytes = string_literal_bytes(value);
+                let len = Operand::Constant { value: bytes.len() as u64, type_: ValueType::U64 };
+                let data = self.program.new_data(bytes);
+                let pointer = self.new_temporary(ValueType::Pointer { points_to: Box::new(ValueType::U8), mutable: false });
+                self.emit(Instruction::DataAddress { destination: pointer, data });
+                (Operand::Local(pointer), len)
+            }
+            Expression::Cast { value, .. } => match value.get_type() {
+                ValueType::Slice { .. } => self.generate_slice(value),
+                type_ => {
+                    let len = type_.known_len().expect("Only pointers to arrays can be turned into slices!");
+                    (self.generate_expression(value), Operand::Constant { value: len as u64, type_: ValueType::U64 })
+                }
+            },
+            Expression::Slice { value, start, end } => {
+                // Constant bounds into arrays are already checked by the semantic analysis
+                let checked = self.bounds_checks && (value.get_type().known_len().is_none()
+                    || [start, end].into_iter().flatten().any(|bound| bound.evaluate_constant(&HashMap::new()).is_err()));
+                let (pointer, len) = match value.get_type() {
+                    ValueType::Array { len, .. } => (self.generate_address(value), Operand::Constant { value: len as u64, type_: ValueType::U64 }),
+                    ValueType::Slice { .. } |
+                    ValueType::Str => self.generate_slice(value),
+                    type_ => (self.generate_expression(value), Operand::Constant { value: type_.known_len().unwrap() as u64, type_: ValueType::U64 }),
+                };
+                let start_value = match start {
+                    Some(start) => self.generate_expression(start),
+                    None => Operand::Constant { value: 0, type_: ValueType::U64 },
+                };
+                let end_value = match end {
+                    Some(end) => self.generate_expression(end),
+                    None => len.clone(),
+                };
+                // Left out bounds are always valid
+                if checked && end.is_some() {
+                    self.emit_bounds_check(end_value.clone(), Operator::LessEqual, len);
+                }
+                if checked && start.is_some() {
+                    self.emit_bounds_check(start_value.clone(), Operator::LessEqual, end_value.clone());
+                }
+                let (start, end) = (start_value, end_value);
+
+                let offset = self.new_temporary(ValueType::U64);
+                self.emit(Instruction::Binary {
+                    destination: offset,
+                    lhs: start.clone(),
+                    operator: Operator::Times,
+                    rhs: Operand::Constant { value: content_type.size_of(), type_: ValueType::U64 },
+                });
+                let slice_pointer = self.new_temporary(ValueType::Pointer { points_to: Box::new(content_type), mutable: true });
+                self.emit(Instruction::Binary { destination: slice_pointer, lhs: pointer, operator: Operator::Plus, rhs: Operand::Local(offset) });
+                let slice_len = self.new_temporary(ValueType::U64);
+                self.emit(Instruction::Binary { destination: slice_len, lhs: end, operator: Operator::Minus, rhs: start });
+                (Operand::Local(slice_pointer), Operand::Local(slice_len))
+            }
+            // Slices stored in memory hold their pointer first and their length right after it
+            _ => {
+                let address = self.generate_address(expression);
+                let pointer = self.new_temporary(ValueType::Pointer { points_to: Box::new(content_type), mutable: true });
+                self.emit(Instruction::Load { destination: pointer, address: address.clone() });
+                let len_address = self.emit_offset(address, 8, &ValueType::U64);
+                let len = self.new_temporary(ValueType::U64);
+                self.emit(Instruction::Load { destination: len, address: len_address });
+                (Operand::Local(pointer), Operand::Local(len))
+            }
+        }
+    }
+
+    /// Generates the new value of a compound assignment, the dereferenced assignee at the
+    /// bottom of the left side is replaced with the already loaded current value.
+    fn generate_compound_value(&mut self, expression: &Expression, current: Operand) -> Operand {
+        match expression {
+            Expression::Deref { .. } => current,
+            Expression::Cast { value, to } => {
+                let from = value.get_type();
+                let value = self.generate_compound_value(value, current);
+                self.emit_cast(value, from, to)
+            }
+            Expression::Operation { lhs, operator, rhs, type_ } => {
+                let lhs = self.generate_compound_value(lhs, current);
+                let rhs = self.generate_expression(rhs);
+                if *operator == Operator::Divide { self.emit_division_check(&rhs); }
+                let destination = self.new_temporary(type_.clone().unwrap());
+                self.emit(Instruction::Binary { destination, lhs, operator: operator.clone(), rhs });
+                Operand::Local(destination)
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    /// Generates a pointer to the memory holding the value of the expression.
+    fn generate_address(&mut self, expression: &Expression) -> Operand {
+        match expression {
+            Expression::IdentifierLiteral { value, .. } => {
+                let local = self.variables[value];
+                self.emit_address_of(local)
+            }
+            Expression::Array { .. } |
+            Expression::Repeat { .. } => {
+                if let Operand::Local(local) = self.generate_expression(expression) {
+                    self.emit_address_of(local)
+                } else { unreachable!() }
+            }
+            Expression::Access { value, index } => {
+                let (base, element_type, len) = match value.get_type() {
+                    ValueType::Array { content_type, len } => (self.generate_address(value), *content_type, Some(Operand::Constant { value: len as u64, type_: ValueType::U64 })),
+                    ValueType::Pointer { points_to, .. } => match *points_to {
+                        ValueType::Array { content_type, len } => (self.generate_expression(value), *content_type, Some(Operand::Constant { value: len as u64, type_: ValueType::U64 })),
+                        points_to => (self.generate_expression(value), points_to, None),
+                    },
+                    ValueType::Slice { content_type, .. } => {
+                        let (pointer, len) = self.generate_slice(value);
+                        (pointer, *content_type, Some(len))
+                    }
+                    ValueType::Str => {
+                        let (pointer, len) = self.generate_slice(value);
+                        (pointer, ValueType::U8, Some(len))
+                    }
+                    _ => unreachable!(),
+                };
+                // Constant indices into arrays are already checked by the semantic analysis
+                let checked = self.bounds_checks && (matches!(len, Some(Operand::Local(_))) || index.evaluate_constant(&HashMap::new()).is_err());
+                let index = self.generate_expression(index);
+                if let (true, Some(len)) = (checked, len) {
+                    self.emit_bounds_check(index.clone(), Operator::Less, len);
+                }
+
+                let offset = self.new_temporary(ValueType::U64);
+                self.emit(Instruction::Binary {
+                    destination: offset,
+                    lhs: index,
+                    operator: Operator::Times,
+                    rhs: Operand::Constant { value: element_type.size_of(), type_: ValueType::U64 },
+                });
+                let address = self.new_temporary(ValueType::Pointer { points_to: Box::new(element_type), mutable: true });
+                self.emit(Instruction::Binary { destination: address, lhs: base, operator: Operator::Plus, rhs: Operand::Local(offset) });
+                Operand::Local(address)
+            }
+            Expression::Deref { value } => self.generate_expression(value),
+            Expression::Cast { value, .. } => self.generate_address(value),
+            _ => unreachable!("Tried to take the address of a temporary value!"),
+        }
+    }
+
+    /// Stores the array value of the expression into the memory behind the address.
+    fn generate_aggregate(&mut self, address: Operand, expression: &Expression) {
+        if expression.get_type().is_fat_pointer() {
+            let (pointer, len) = self.generate_slice(expression);
+            self.emit(Instruction::Store { address: address.clone(), value: pointer });
+            let len_address = self.emit_offset(address, 8, &ValueType::U64);
+            self.emit(Instruction::Store { address: len_address, value: len });
+            return;
+        }
+        let element_type = match expression.get_type() {
+            ValueType::Array { content_type, .. } => *content_type,
+            _ => unreachable!(),
+        };
+
+        if let Expression::Array { content } = expression {
+            for (index, element) in content.iter().enumerate() {
+                let element_address = self.emit_offset(address.clone(), index as u64 * element_type.size_of(), &element_type);
+                if element_type.is_aggregate() {
+                    self.generate_aggregate(element_address, element);
+                } else {
+                    let value = self.generate_expression(element);
+                    self.emit(Instruction::Store { address: element_address, value });
+                }
+            }
+        } else if let Expression::Repeat { value, len } = expression {
+            self.generate_fill(address, value, *len, &element_type);
+        } else {
+            let source = self.generate_address(expression);
+            self.generate_copy(address, source, &expression.get_type());
+        }
+    }
+
+    /// Compares the length first and then every byte, stopping at the first difference.
+    fn generate_string_comparison(&mut self, lhs: &Expression, operator: &Operator, rhs: &Expression) -> Operand {
+        let (lhs_pointer, lhs_len) = self.generate_slice(lhs);
+        let (rhs_pointer, rhs_len) = self.generate_slice(rhs);
+        let equal = self.new_temporary(ValueType::Bool);
+        let index = self.new_temporary(ValueType::U64);
+        let condition = self.new_temporary(ValueType::Bool);
+        let check = self.program.new_label();
+        let body = self.program.new_label();
+        let end = self.program.new_label();
+        let compare_bytes = self.program.new_label();
+
+        self.emit(Instruction::Binary { destination: equal, lhs: lhs_len.clone(), operator: Operator::Equal, rhs: rhs_len });
+        self.emit(Instruction::Branch { condition: Operand::Local(equal), if_true: compare_bytes, if_false: end });
+        self.emit(Instruction::Label { label: compare_bytes });
+        self.emit(Instruction::Move { destination: index, source: Operand::Constant { value: 0, type_: ValueType::U64 } });
+
+        self.emit(Instruction::Label { label: check });
+        self.emit(Instruction::Binary { destination: condition, lhs: Operand::Local(index), operator: Operator::Less, rhs: lhs_len });
+        self.emit(Instruction::Branch { condition: Operand::Local(condition), if_true: body, if_false: end });
+
+        self.emit(Instruction::Label { label: body });
+        let mut bytes = vec![];
+        for pointer in [lhs_pointer, rhs_pointer] {
+            let address = self.new_temporary(ValueType::Pointer { points_to: Box::new(ValueType::U8), mutable: false });
+            self.emit(Instruction::Binary { destination: address, lhs: pointer, operator: Operator::Plus, rhs: Operand::Local(index) });
+            let byte = self.new_temporary(ValueType::U8);
+            self.emit(Instruction::Load { destination: byte, address: Operand::Local(address) });
+            bytes.push(Operand::Local(byte));
+        }
+        self.emit(Instruction::Binary { destination: equal, lhs: bytes[0].clone(), operator: Operator::Equal, rhs: bytes[1].clone() });
+        self.emit(Instruction::Binary {
+            destination: index,
+            lhs: Operand::Local(index),
+            operator: Operator::Plus,
+            rhs: Operand::Constant { value: 1, type_: ValueType::U64 },
+        });
+        self.emit(Instruction::Branch { condition: Operand::Local(equal), if_true: check, if_false: end });
+
+        self.emit(Instruction::Label { label: end });
+        match operator {
+            Operator::Equal => Operand::Local(equal),
+            Operator::NotEqual => {
+                let not_equal = self.new_temporary(ValueType::Bool);
+                self.emit(Instruction::Binary { destination: not_equal, lhs: Operand::Local(equal), operator: Operator::Equal, rhs: Operand::Constant { value: 0, type_: ValueType::Bool } });
+                Operand::Local(not_equal)
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    /// Prints `len` chars starting at `address` one after another.
+    fn generate_print_chars(&mut self, address: Operand, len: Operand) {
+        let buffer = self.new_temporary(ValueType::Array { content_type: Box::new(ValueType::U8), len: 4 });
+        let buffer = self.emit_address_of(buffer);
+        let index = self.new_temporary(ValueType::U64);
+        let condition = self.new_temporary(ValueType::Bool);
+        let check = self.program.new_label();
+        let body = self.program.new_label();
+        let end = self.program.new_label();
+
+        self.emit(Instruction::Move { destination: index, source: Operand::Constant { value: 0, type_: ValueType::U64 } });
+        self.emit(Instruction::Label { label: check });
+        self.emit(Instruction::Binary { destination: condition, lhs: Operand::Local(index), operator: Operator::Less, rhs: len });
+        self.emit(Instruction::Branch { condition: Operand::Local(condition), if_true: body, if_false: end });
+
+        self.emit(Instruction::Label { label: body });
+        let offset = self.new_temporary(ValueType::U64);
+        self.emit(Instruction::Binary {
+            destination: offset,
+            lhs: Operand::Local(index),
+            operator: Operator::Times,
+            rhs: Operand::Constant { value: ValueType::Char.size_of(), type_: ValueType::U64 },
+        });
+        let element_address = self.new_temporary(ValueType::Pointer { points_to: Box::new(ValueType::Char), mutable: false });
+        self.emit(Instruction::Binary { destination: element_address, lhs: address, operator: Operator::Plus, rhs: Operand::Local(offset) });
+        let char = self.new_temporary(ValueType::Char);
+        self.emit(Instruction::Load { destination: char, address: Operand::Local(element_address) });
+        self.emit_print_char(Operand::Local(char), buffer);
+        self.emit(Instruction::Binary {
+            destination: index,
+            lhs: Operand::Local(index),
+            operator: Operator::Plus,
+            rhs: Operand::Constant { value: 1, type_: ValueType::U64 },
+        });
+        self.emit(Instruction::Jump { label: check });
+
+        self.emit(Instruction::Label { label: end });
+    }
+
+    /// Encodes the char as UTF-8 into the 4 byte `buffer` and prints those bytes.
+    fn emit_print_char(&mut self, char: Operand, buffer: Operand) {
+        let value = self.emit_cast(char, ValueType::Char, &ValueType::U32);
+        let len = self.new_temporary(ValueType::U64);
+        let condition = self.new_temporary(ValueType::Bool);
+        let encodings: Vec<_> = (0..4).map(|_| self.program.new_label()).collect();
+        let end = self.program.new_label();
+
+        // The first byte marks how many bytes follow, every following byte carries 6 bits
+        for (len, limit) in [(1, 0x80), (2, 0x800), (3, 0x10000)] {
+            let next = self.program.new_label();
+            self.emit(Instruction::Binary { destination: condition, lhs: value.clone(), operator: Operator::Less, rhs: Operand::Constant { value: limit, type_: ValueType::U32 } });
+            self.emit(Instruction::Branch { condition: Operand::Local(condition), if_true: encodings[len - 1], if_false: next });
+            self.emit(Instruction::Label { label: next });
+        }
+        self.emit(Instruction::Jump { label: encodings[3] });
+
+        for (label, (bytes, prefix)) in encodings.into_iter().zip([(1, 0x00), (2, 0xC0), (3, 0xE0), (4, 0xF0)]) {
+            self.emit(Instruction::Label { label });
+            for index in 0..bytes {
+                let shifted = match bytes - 1 - index {
+                    0 => value.clone(),
+                    following => self.emit_binary(value.clone(), Operator::Divide, 1 << (6 * following)),
+                };
+                let byte = if index == 0 {
+                    if prefix == 0 { shifted } else { self.emit_binary(shifted, Operator::Plus, prefix) }
+                } else {
+                    let upper = self.emit_binary(shifted.clone(), Operator::Divide, 64);
+                    let upper = self.emit_binary(upper, Operator::Times, 64);
+                    let lower = self.new_temporary(ValueType::U32);
+                    self.emit(Instruction::Binary { destination: lower, lhs: shifted, operator: Operator::Minus, rhs: upper });
+                    self.emit_binary(Operand::Local(lower), Operator::Plus, 0x80)
+                };
+                let byte = self.emit_cast(byte, ValueType::U32, &ValueType::U8);
+                let address = self.emit_offset(buffer.clone(), index, &ValueType::U8);
+                self.emit(Instruction::Store { address, value: byte });
+            }
+            self.emit(Instruction::Move { destination: len, source: Operand::Constant { value: bytes, type_: ValueType::U64 } });
+            self.emit(Instruction::Jump { label: end });
+        }
+
+        self.emit(Instruction::Label { label: end });
+        self.emit(Instruction::PrintString { address: buffer, len: Operand::Local(len) });
+    }
+
+    /// Evaluates the value once and stores it into every element with a loop instead of one store per element.
+    fn generate_fill(&mut self, address: Operand, value: &Expression, len: usize, element_type: &ValueType) {
+        let value = if element_type.is_fat_pointer() {
+            match self.generate_expression(value) {
+                Operand::Local(local) => self.emit_address_of(local),
+                Operand::Constant { .. } => unreachable!(),
+            }
+        } else if let ValueType::Array { .. } = element_type {
+            self.generate_address(value)
+        } else {
+            self.generate_expression(value)
+        };
+        let index = self.new_temporary(ValueType::U64);
+        let condition = self.new_temporary(ValueType::Bool);
+        let check = self.program.new_label();
+        let body = self.program.new_label();
+        let end = self.program.new_label();
+
+        self.emit(Instruction::Move { destination: index, source: Operand::Constant { value: 0, type_: ValueType::U64 } });
+        self.emit(Instruction::Label { label: check });
+        self.emit(Instruction::Binary {
+            destination: condition,
+            lhs: Operand::Local(index),
+            operator: Operator::Less,
+            rhs: Operand::Constant { value: len as u64, type_: ValueType::U64 },
+        });
+        self.emit(Instruction::Branch { condition: Operand::Local(condition), if_true: body, if_false: end });
+
+        self.emit(Instruction::Label { label: body });
+        let offset = self.new_temporary(ValueType::U64);
+        self.emit(Instruction::Binary {
+            destination: offset,
+            lhs: Operand::Local(index),
+            operator: Operator::Times,
+            rhs: Operand::Constant { value: element_type.size_of(), type_: ValueType::U64 },
+        });
+        let element_address = self.new_temporary(ValueType::Pointer { points_to: Box::new(element_type.clone()), mutable: true });
+        self.emit(Instruction::Binary { destination: element_address, lhs: address, operator: Operator::Plus, rhs: Operand::Local(offset) });
+        if element_type.is_aggregate() {
+            self.generate_copy(Operand::Local(element_address), value, element_type);
+        } else {
+            self.emit(Instruction::Store { address: Operand::Local(element_address), value });
+        }
+        self.emit(Instruction::Binary {
+            destination: index,
+            lhs: Operand::Local(index),
+            operator: Operator::Plus,
+            rhs: Operand::Constant { value: 1, type_: ValueType::U64 },
+        });
+        self.emit(Instruction::Jump { label: check });
+
+        self.emit(Instruction::Label { label: end });
+    }
+
+    fn generate_copy(&mut self, destination: Operand, source: Operand, type_: &ValueType) {
+        if type_.is_fat_pointer() {
+            return self.generate_copy(destination, source, &ValueType::Array { content_type: Box::new(ValueType::U64), len: 2 });
+        }
+        if let ValueType::Array { content_type, len } = type_ {
+            for index in 0..*len as u64 {
+                let offset = index * content_type.size_of();
+                let element_destination = self.emit_offset(destination.clone(), offset, content_type);
+                let element_source = self.emit_offset(source.clone(), offset, content_type);
+                self.generate_copy(element_destination, element_source, content_type);
+            }
+        } else {
+            let value = self.new_temporary(type_.clone());
+            self.emit(Instruction::Load { destination: value, address: source });
+            self.emit(Instruction::Store { address: destination, value: Operand::Local(value) });
+        }
+    }
+
+    /// Exits the program unless `lhs operator rhs` holds.
+    fn emit_bounds_check(&mut self, lhs: Operand, operator: Operator, rhs: Operand) {
+        self.emit_check(lhs, operator, rhs, BOUNDS_CHECK_EXIT_CODE);
+    }
+
+    /// Divisors that arent known to be nonzero are checked, the processor would raise an exception otherwise.
+    fn emit_division_check(&mut self, divisor: &Operand) {
+        if matches!(divisor, Operand::Constant { value, .. } if *value != 0) { return; }
+        let zero = Operand::Constant { value: 0, type_: self.program.operand_type(divisor) };
+        self.emit_check(divisor.clone(), Operator::NotEqual, zero, DIVISION_BY_ZERO_EXIT_CODE);
+    }
+
+    fn emit_check(&mut self, lhs: Operand, operator: Operator, rhs: Operand, exit_code: u64) {
+        let holds = self.new_temporary(ValueType::Bool);
+        let fails = self.program.new_label();
+        let end = self.program.new_label();
+
+        self.emit(Instruction::Binary { destination: holds, lhs, operator, rhs });
+        self.emit(Instruction::Branch { condition: Operand::Local(holds), if_true: end, if_false: fails });
+        self.emit(Instruction::Label { label: fails });
+        self.emit(Instruction::Exit { value: Operand::Constant { value: exit_code, type_: ValueType::U64 } });
+        self.emit(Instruction::Label { label: end });
+    }
+
+    fn emit_cast(&mut self, value: Operand, from: ValueType, to: &ValueType) -> Operand {
+        // Pointers only differ in their mutability which doesnt exist at runtime
+        if &from == to || (from.is_pointer() && to.is_pointer()) { return value; }
+        if to == &ValueType::Char && from != ValueType::U8 { return self.emit_char_cast(value, from); }
+
+        let destination = self.new_temporary(to.clone());
+        self.emit(Instruction::Cast { destination, value, from, to: to.clone() });
+        Operand::Local(destination)
+    }
+
+    /// Integers that arent unicode scalar values become U+FFFD when cast to char.
+    fn emit_char_cast(&mut self, value: Operand, from: ValueType) -> Operand {
+        let destination = self.new_temporary(ValueType::Char);
+        let condition = self.new_temporary(ValueType::Bool);
+        let above_surrogates = self.program.new_label();
+        let below_max = self.program.new_label();
+        let convert = self.program.new_label();
+        let replace = self.program.new_label();
+        let end = self.program.new_label();
+
+        self.emit(Instruction::Binary { destination: condition, lhs: value.clone(), operator: Operator::Less, rhs: Operand::Constant { value: 0xD800, type_: from.clone() } });
+        self.emit(Instruction::Branch { condition: Operand::Local(condition), if_true: convert, if_false: above_surrogates });
+        self.emit(Instruction::Label { label: above_surrogates });
+        self.emit(Instruction::Binary { destination: condition, lhs: value.clone(), operator: Operator::Greater, rhs: Operand::Constant { value: 0xDFFF, type_: from.clone() } });
+        self.emit(Instruction::Branch { condition: Operand::Local(condition), if_true: below_max, if_false: replace });
+        self.emit(Instruction::Label { label: below_max });
+        self.emit(Instruction::Binary { destination: condition, lhs: value.clone(), operator: Operator::LessEqual, rhs: Operand::Constant { value: 0x10FFFF, type_: from.clone() } });
+        self.emit(Instruction::Branch { condition: Operand::Local(condition), if_true: convert, if_false: replace });
+
+        self.emit(Instruction::Label { label: convert });
+        self.emit(Instruction::Cast { destination, value, from, to: ValueType::Char });
+        self.emit(Instruction::Jump { label: end });
+        self.emit(Instruction::Label { label: replace });
+        self.emit(Instruction::Move { destination, source: Operand::Constant { value: char::REPLACEMENT_CHARACTER as u64, type_: ValueType::Char } });
+        self.emit(Instruction::Jump { label: end });
+
+        self.emit(Instruction::Label { label: end });
+        Operand::Local(destination)
+    }
+
+    /// `lhs operator rhs` on u32s, used while encoding chars.
+    fn emit_binary(&mut self, lhs: Operand, operator: Operator, rhs: u64) -> Operand {
+        let destination = self.new_temporary(ValueType::U32);
+        self.emit(Instruction::Binary { destination, lhs, operator, rhs: Operand::Constant { value: rhs, type_: ValueType::U32 } });
+        Operand::Local(destination)
+    }
+
+    fn emit_offset(&mut self, address: Operand, offset: u64, points_to: &ValueType) -> Operand {
+        if offset == 0 { return address; }
+
+        let destination = self.new_temporary(ValueType::Pointer { points_to: Box::new(points_to.clone()), mutable: true });
+        self.emit(Instruction::Binary {
+            destination,
+            lhs: address,
+            operator: Operator::Plus,
+            rhs: Operand::Constant { value: offset, type_: ValueType::U64 },
+        });
+        Operand::Local(destination)
+    }
+
+    fn emit_address_of(&mut self, local: LocalId) -> Operand {
+        let destination = self.new_temporary(ValueType::Pointer { points_to: Box::new(self.program.locals[local].type_.clone()), mutable: true });
+        self.emit(Instruction::AddressOf { destination, local });
+        Operand::Local(destination)
+    }
+
+    fn new_temporary(&mut self, type_: ValueType) -> LocalId {
+        self.program.new_local(None, type_)
+    }
+
+    fn emit(&mut self, instruction: Instruction) {
+        self.program.instructions.push(instruction);
+    }
+}
@@ -0,0 +1,111 @@
+use crate::parser::r#type::ValueType;
+use crate::tokenizer::token::{Literal, Operator};
+
+pub type LocalId = usize;
+pub type Label = usize;
+pub type DataId = usize;
+
+#[derive(Debug, Clone)]
+pub struct Local {
+    pub name: Option<Literal>,
+    pub type_: ValueType,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum Operand {
+    Local(LocalId),
+    Constant { value: u64, type_: ValueType },
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Instruction {
+    Move { destination: LocalId, source: Operand },
+    Binary { destination: LocalId, lhs: Operand, operator: Operator, rhs: Operand },
+    Cast { destination: LocalId, value: Operand, from: ValueType, to: ValueType },
+    AddressOf { destination: LocalId, local: LocalId },
+    /// Address of read only data placed into the data section of the program.
+    DataAddress { destination: LocalId, data: DataId },
+    Load { destination: LocalId, address: Operand },
+    Store { address: Operand, value: Operand },
+    Label { label: Label },
+    Jump { label: Label },
+    Branch { condition: Operand, if_true: Label, if_false: Label },
+    Exit { value: Operand },
+    /// Writes an integer in decimal or a bool as `true`/`false` to stdout, without a newline.
+    Print { value: Operand },
+    /// Writes `len` bytes starting at `address` to stdout.
+    PrintString { address: Operand, len: Operand },
+    /// Only exists in SSA form, picks the source belonging to the block control came from.
+    Phi { destination: LocalId, sources: Vec<(Label, Operand)> },
+}
+
+impl Instruction {
+    pub fn destination(&self) -> Option<LocalId> {
+        match self {
+            Instruction::Move { destination, .. } |
+            Instruction::Binary { destination, .. } |
+            Instruction::Cast { destination, .. } |
+            Instruction::AddressOf { destination, .. } |
+            Instruction::DataAddress { destination, .. } |
+            Instruction::Load { destination, .. } |
+            Instruction::Phi { destination, .. } => Some(*destination),
+            _ => None,
+        }
+    }
+
+    pub fn destination_mut(&mut self) -> Option<&mut LocalId> {
+        match self {
+            Instruction::Move { destination, .. } |
+            Instruction::Binary { destination, .. } |
+            Instruction::Cast { destination, .. } |
+            Instruction::AddressOf { destination, .. } |
+            Instruction::DataAddress { destination, .. } |
+            Instruction::Load { destination, .. } |
+            Instruction::Phi { destination, .. } => Some(destination),
+            _ => None,
+        }
+    }
+
+    /// The operands the instruction reads, `AddressOf` only takes the address of its local.
+    pub fn operands(&self) -> Vec<&Operand> {
+        match self {
+            Instruction::Move { source, .. } => vec![source],
+            Instruction::Binary { lhs, rhs, .. } => vec![lhs, rhs],
+            Instruction::Cast { value, .. } |
+            Instruction::Exit { value } |
+            Instruction::Print { value } => vec![value],
+            Instruction::Load { address, .. } => vec![address],
+            Instruction::Store { address, value } => vec![address, value],
+            Instruction::Branch { condition, .. } => vec![condition],
+            Instruction::PrintString { address, len } => vec![address, len],
+            Instruction::Phi { sources, .. } => sources.iter().map(|(_, source)| source).collect(),
+            Instruction::AddressOf { .. } |
+            Instruction::DataAddress { .. } |
+            Instruction::Label { .. } |
+            Instruction::Jump { .. } => vec![],
+        }
+    }
+
+    pub fn operands_mut(&mut self) -> Vec<&mut Operand> {
+        match self {
+            Instruction::Move { source, .. } => vec![source],
+            Instruction::Binary { lhs, rhs, .. } => vec![lhs, rhs],
+            Instruction::Cast { value, .. } |
+            Instruction::Exit { value } |
+            Instruction::Print { value } => vec![value],
+            Instruction::Load { address, .. } => vec![address],
+            Instruction::Store { address, value } => vec![address, value],
+            Instruction::Branch { condition, .. } => vec![condition],
+            Instruction::PrintString { address, len } => vec![address, len],
+            Instruction::Phi { sources, .. } => sources.iter_mut().map(|(_, source)| source).collect(),
+            Instruction::AddressOf { .. } |
+            Instruction::DataAddress { .. } |
+            Instruction::Label { .. } |
+            Instruction::Jump { .. } => vec![],
+        }
+    }
+
+    pub fn is_terminator(&self) -> bool {
+        matches!(self, Instruction::Jump { .. } | Instruction::Branch { .. } | Instruction::Exit { .. })
+    }
+}
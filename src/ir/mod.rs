@@ -0,0 +1,116 @@
+use std::collections::{BTreeSet, HashSet};
+use std::fmt::{Display, Formatter};
+use crate::ir::instruction::{DataId, Instruction, Label, Local, LocalId, Operand};
+use crate::parser::r#type::ValueType;
+use crate::tokenizer::token::{Literal, literal_to_string};
+
+pub mod instruction;
+pub mod generator;
+pub mod cfg;
+pub mod ssa;
+pub mod optimization;
+pub mod verify;
+
+#[derive(Debug, Clone, Default)]
+pub struct Program {
+    pub instructions: Vec<Instruction>,
+    pub locals: Vec<Local>,
+    pub label_count: usize,
+    /// Read only bytes, like the contents of string literals.
+    pub data: Vec<Vec<u8>>,
+}
+
+impl Program {
+    pub fn new_local(&mut self, name: Option<Literal>, type_: ValueType) -> LocalId {
+        self.locals.push(Local { name, type_ });
+        self.locals.len() - 1
+    }
+
+    /// Identical data is only stored once.
+    pub fn new_data(&mut self, bytes: Vec<u8>) -> DataId {
+        match self.data.iter().position(|data| data == &bytes) {
+            Some(data) => data,
+            None => {
+                self.data.push(bytes);
+                self.data.len() - 1
+            }
+        }
+    }
+
+    pub fn new_label(&mut self) -> Label {
+        self.label_count += 1;
+        self.label_count - 1
+    }
+
+    /// Scalars whose address is never taken live in registers, they cant be changed behind our back.
+    /// Aggregates and everything else live in memory.
+    pub fn register_locals<'a>(&self, instructions: impl IntoIterator<Item = &'a Instruction>) -> BTreeSet<LocalId> {
+        let address_taken: HashSet<LocalId> = instructions.into_iter()
+            .filter_map(|instruction| match instruction {
+                Instruction::AddressOf { local, .. } => Some(*local),
+                _ => None,
+            })
+            .collect();
+        (0..self.locals.len())
+            .filter(|local| !self.locals[*local].type_.is_aggregate() && !address_taken.contains(local))
+            .collect()
+    }
+
+    pub fn operand_type(&self, operand: &Operand) -> ValueType {
+        match operand {
+            Operand::Local(local) => self.locals[*local].type_.clone(),
+            Operand::Constant { type_, .. } => type_.clone(),
+        }
+    }
+
+    pub fn local_name(&self, local: LocalId) -> String {
+        match &self.locals[local].name {
+            Some(name) => literal_to_string(name),
+            None => format!("t{}", local),
+        }
+    }
+
+    fn format_operand(&self, operand: &Operand) -> String {
+        match operand {
+            Operand::Local(local) => self.local_name(*local),
+            Operand::Constant { value, type_ } => format!("{}({})", type_, value),
+        }
+    }
+
+    pub fn format_instruction(&self, instruction: &Instruction) -> String {
+        match instruction {
+            Instruction::Move { destination, source } => format!("{} = {}", self.local_name(*destination), self.format_operand(source)),
+            Instruction::Binary { destination, lhs, operator, rhs } => format!("{} = {} {} {}", self.local_name(*destination), self.format_operand(lhs), operator, self.format_operand(rhs)),
+            Instruction::Cast { destination, value, from, to } => format!("{} = cast_{}_{}({})", self.local_name(*destination), from, to, self.format_operand(value)),
+            Instruction::AddressOf { destination, local } => format!("{} = &{}", self.local_name(*destination), self.local_name(*local)),
+            Instruction::DataAddress { destination, data } => format!("{} = &data{}", self.local_name(*destination), data),
+            Instruction::Load { destination, address } => format!("{} = *{}", self.local_name(*destination), self.format_operand(address)),
+            Instruction::Store { address, value } => format!("*{} = {}", self.format_operand(address), self.format_operand(value)),
+            Instruction::Label { label } => format!("L{}:", label),
+            Instruction::Jump { label } => format!("jump L{}", label),
+            Instruction::Branch { condition, if_true, if_false } => format!("branch {}, L{}, L{}", self.format_operand(condition), if_true, if_false),
+            Instruction::Exit { value } => format!("exit({})", self.format_operand(value)),
+            Instruction::Print { value } => format!("print({})", self.format_operand(value)),
+            Instruction::PrintString { address, len } => format!("print_string({}, {})", self.format_operand(address), self.format_operand(len)),
+            Instruction::Phi { destination, sources } => format!("{} = phi({})", self.local_name(*destination), sources.iter()
+                .map(|(label, source)| format!("L{}: {}", label, self.format_operand(source)))
+                .collect::<Vec<_>>()
+                .join(", ")),
+        }
+    }
+}
+
+impl Display for Program {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for (id, data) in self.data.iter().enumerate() {
+            writeln!(f, "data{}: \"{}\"", id, data.escape_ascii())?;
+        }
+        for instruction in &self.instructions {
+            match instruction {
+                Instruction::Label { .. } => writeln!(f, "{}", self.format_instruction(instruction))?,
+                _ => writeln!(f, "    {}", self.format_instruction(instruction))?,
+            }
+        }
+        Ok(())
+    }
+}
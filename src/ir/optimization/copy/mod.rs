@@ -0,0 +1,38 @@
+use std::collections::HashMap;
+use crate::ir::cfg::ControlFlowGraph;
+use crate::ir::instruction::{Instruction, LocalId, Operand};
+use crate::ir::Program;
+use crate::ir::ssa;
+
+/// Replaces the uses of a local that is only a copy of another one with the original and removes the copy.
+/// Expects the program in SSA form, where the original holds the same value wherever the copy is read.
+pub fn propagate_copies(program: &mut Program, graph: &mut ControlFlowGraph) -> bool {
+    let values = ssa::single_assignment_locals(program, graph);
+    let copies: HashMap<LocalId, LocalId> = graph.blocks.iter()
+        .flat_map(|block| block.instructions.iter())
+        .filter_map(|instruction| match instruction {
+            Instruction::Move { destination, source: Operand::Local(source) } if values.contains(destination) && values.contains(source) => Some((*destination, *source)),
+            _ => None,
+        })
+        .collect();
+    if copies.is_empty() { return false; }
+
+    for block in graph.blocks.iter_mut() {
+        block.instructions.retain(|instruction| !matches!(instruction.destination(), Some(destination) if copies.contains_key(&destination)));
+    }
+    replace_locals(graph, &copies);
+    true
+}
+
+/// Replaces every read of a local with the local it maps to, following chains of replacements.
+pub fn replace_locals(graph: &mut ControlFlowGraph, replacements: &HashMap<LocalId, LocalId>) {
+    for instruction in graph.blocks.iter_mut().flat_map(|block| block.instructions.iter_mut()) {
+        for operand in instruction.operands_mut() {
+            if let Operand::Local(local) = operand {
+                while let Some(replacement) = replacements.get(local) {
+                    *local = *replacement;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,112 @@
+use std::collections::HashMap;
+use crate::ir::cfg::ControlFlowGraph;
+use crate::ir::instruction::{DataId, Instruction, LocalId, Operand};
+use crate::ir::optimization::copy;
+use crate::ir::Program;
+use crate::ir::ssa;
+use crate::parser::r#type::ValueType;
+use crate::tokenizer::token::Operator;
+
+/// What an instruction computes, instructions with the same key compute the same value.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+enum Key {
+    Binary { lhs: Operand, operator: Operator, rhs: Operand, type_: ValueType },
+    Cast { value: Operand, to: ValueType },
+    AddressOf { local: LocalId },
+    DataAddress { data: DataId },
+}
+
+/// Global value numbering, an instruction computing the same as one in a dominating block, or earlier
+/// in the same block, reuses that result. Expects the program in SSA form.
+pub fn eliminate_common_subexpressions(program: &mut Program, graph: &mut ControlFlowGraph) -> bool {
+    let values = ssa::single_assignment_locals(program, graph);
+    let children = graph.dominator_tree();
+    let mut available: HashMap<Key, LocalId> = HashMap::new();
+    let mut replacements: HashMap<LocalId, LocalId> = HashMap::new();
+
+    // The keys a block added are forgotten once every block it dominates was visited
+    let mut stack = vec![(0, true)];
+    let mut added: Vec<Vec<Key>> = vec![vec![]; graph.blocks.len()];
+    while let Some((block, enter)) = stack.pop() {
+        if !enter {
+            for key in &added[block] {
+                available.remove(key);
+            }
+            continue;
+        }
+        for instruction in graph.blocks[block].instructions.iter_mut() {
+            for operand in instruction.operands_mut() {
+                if let Operand::Local(local) = operand {
+                    if let Some(replacement) = replacements.get(local) {
+                        *local = *replacement;
+                    }
+                }
+            }
+            let destination = match instruction.destination() {
+                Some(destination) if values.contains(&destination) => destination,
+                _ => continue,
+            };
+            let key = match key(program, instruction) {
+                Some(key) if key_operands(&key).all(|local| values.contains(&local)) => key,
+                _ => continue,
+            };
+            match available.get(&key) {
+                Some(existing) => {
+                    replacements.insert(destination, *existing);
+                }
+                None => {
+                    available.insert(key.clone(), destination);
+                    added[block].push(key);
+                }
+            }
+        }
+        stack.push((block, false));
+        stack.extend(children[block].iter().map(|child| (*child, true)));
+    }
+    if replacements.is_empty() { return false; }
+
+    for block in graph.blocks.iter_mut() {
+        block.instructions.retain(|instruction| !matches!(instruction.destination(), Some(destination) if replacements.contains_key(&destination)));
+    }
+    // Phis in blocks that were visited before the replaced local was found still read it
+    copy::replace_locals(graph, &replacements);
+    true
+}
+
+fn key(program: &Program, instruction: &Instruction) -> Option<Key> {
+    match instruction {
+        Instruction::Binary { destination, lhs, operator, rhs } => {
+            let (lhs, rhs) = match operator {
+                // Operands of commutative operations are ordered, so that `a + b` and `b + a` get the same key
+                Operator::Plus | Operator::Times | Operator::Equal | Operator::NotEqual if order(lhs) > order(rhs) => (rhs, lhs),
+                _ => (lhs, rhs),
+            };
+            Some(Key::Binary { lhs: lhs.clone(), operator: operator.clone(), rhs: rhs.clone(), type_: program.locals[*destination].type_.clone() })
+        }
+        Instruction::Cast { value, to, .. } => Some(Key::Cast { value: value.clone(), to: to.clone() }),
+        Instruction::AddressOf { local, .. } => Some(Key::AddressOf { local: *local }),
+        Instruction::DataAddress { data, .. } => Some(Key::DataAddress { data: *data }),
+        _ => None,
+    }
+}
+
+fn order(operand: &Operand) -> (bool, u64) {
+    match operand {
+        Operand::Local(local) => (false, *local as u64),
+        Operand::Constant { value, .. } => (true, *value),
+    }
+}
+
+/// The locals a key reads, they have to hold the same value everywhere for the key to mean the same thing.
+fn key_operands(key: &Key) -> impl Iterator<Item = LocalId> + '_ {
+    let operands: Vec<&Operand> = match key {
+        Key::Binary { lhs, rhs, .. } => vec![lhs, rhs],
+        Key::Cast { value, .. } => vec![value],
+        Key::AddressOf { .. } |
+        Key::DataAddress { .. } => vec![],
+    };
+    operands.into_iter().filter_map(|operand| match operand {
+        Operand::Local(local) => Some(*local),
+        Operand::Constant { .. } => None,
+    })
+}
@@ -0,0 +1,290 @@
+use std::collections::{HashMap, HashSet};
+use crate::ir::cfg::{transfer, ControlFlowGraph};
+use crate::ir::instruction::{Instruction, Label, LocalId, Operand};
+use crate::ir::Program;
+use crate::ir::ssa;
+use crate::tokenizer::token::Operator;
+
+/// Removes blocks that can never run, like everything after an `exit`, and instructions whose result is never read.
+/// Assignments to a local and stores to memory that are overwritten before being read are removed as well.
+pub fn eliminate_dead_code(program: &mut Program, graph: &mut ControlFlowGraph) -> bool {
+    let mut changed = remove_unreachable_blocks(graph);
+    changed |= remove_dead_stores(program, graph);
+    while remove_dead_instructions(program, graph) {
+        changed = true;
+    }
+    while merge_blocks(graph) {
+        changed = true;
+    }
+    changed |= remove_redundant_jumps(graph);
+    changed
+}
+
+fn remove_unreachable_blocks(graph: &mut ControlFlowGraph) -> bool {
+    let reachable: Vec<bool> = (0..graph.blocks.len()).map(|block| graph.is_reachable(block)).collect();
+    let unreachable: Vec<Option<Label>> = graph.blocks.iter().zip(&reachable)
+        .filter(|(_, reachable)| !**reachable)
+        .map(|(block, _)| block.label())
+        .collect();
+    if unreachable.is_empty() { return false; }
+
+    let mut reachable = reachable.into_iter();
+    graph.blocks.retain(|_| reachable.next().unwrap());
+    for instruction in graph.blocks.iter_mut().flat_map(|block| block.instructions.iter_mut()) {
+        if let Instruction::Phi { sources, .. } = instruction {
+            sources.retain(|(predecessor, _)| !unreachable.contains(&Some(*predecessor)));
+        }
+    }
+    graph.recompute();
+    true
+}
+
+/// Appends a block to its only predecessor if that predecessor always continues with it,
+/// this removes the jumps left behind by folded branches.
+fn merge_blocks(graph: &mut ControlFlowGraph) -> bool {
+    let mergeable = (0..graph.blocks.len()).find_map(|block| {
+        let successor = match graph.blocks[block].successors.as_slice() {
+            [successor] => *successor,
+            _ => return None,
+        };
+        let removed = &graph.blocks[successor];
+        if successor == block || successor == 0 || removed.predecessors.len() != 1 { return None; }
+        if matches!(graph.blocks[block].instructions.last(), Some(Instruction::Branch { .. })) { return None; }
+        // A block falling through needs a label to jump to once it moved, the end of the program has none
+        let continues_at = match successor == block + 1 || !removed.falls_through() {
+            true => None,
+            false => Some(graph.blocks.get(successor + 1)?.label()?),
+        };
+        Some((block, successor, continues_at))
+    });
+    let (block, successor, continues_at) = match mergeable {
+        Some(mergeable) => mergeable,
+        None => return false,
+    };
+
+    let removed = graph.blocks.remove(successor);
+    let label = graph.blocks[block].label();
+    let target = &mut graph.blocks[block].instructions;
+    if matches!(target.last(), Some(Instruction::Jump { .. })) {
+        target.pop();
+    }
+    for instruction in removed.instructions.iter().skip(removed.label().is_some() as usize) {
+        target.push(match instruction {
+            // The phis of a block with a single predecessor have a single source
+            Instruction::Phi { destination, sources } => Instruction::Move { destination: *destination, source: sources[0].1.clone() },
+            instruction => instruction.clone(),
+        });
+    }
+    if let Some(next) = continues_at {
+        target.push(Instruction::Jump { label: next });
+    }
+    // Phis of the following blocks now come from the merged block
+    if let (Some(removed), Some(label)) = (removed.label(), label) {
+        for instruction in graph.blocks.iter_mut().flat_map(|block| block.instructions.iter_mut()) {
+            if let Instruction::Phi { sources, .. } = instruction {
+                for (predecessor, _) in sources.iter_mut().filter(|(predecessor, _)| *predecessor == removed) {
+                    *predecessor = label;
+                }
+            }
+        }
+    }
+    graph.recompute();
+    true
+}
+
+/// Jumps to the block that follows anyway.
+fn remove_redundant_jumps(graph: &mut ControlFlowGraph) -> bool {
+    let mut changed = false;
+    for block in 0..graph.blocks.len().saturating_sub(1) {
+        let next = graph.blocks[block + 1].label();
+        if let Some(Instruction::Jump { label }) = graph.blocks[block].instructions.last() {
+            if Some(*label) == next {
+                graph.blocks[block].instructions.pop();
+                changed = true;
+            }
+        }
+    }
+    if changed { graph.recompute(); }
+    changed
+}
+
+/// A backwards liveness analysis over the locals that live in registers, a pure instruction defining a local
+/// that isnt live afterwards is dead.
+fn remove_dead_instructions(program: &Program, graph: &mut ControlFlowGraph) -> bool {
+    let registers = graph.register_locals(program);
+    let live_in = graph.live_in(&registers);
+
+    let mut changed = false;
+    for id in 0..graph.blocks.len() {
+        let mut live = graph.live_out(&live_in, id);
+        let mut dead = vec![];
+        for (index, instruction) in graph.blocks[id].instructions.iter().enumerate().rev() {
+            if let Some(destination) = instruction.destination() {
+                if registers.contains(&destination) && !live.contains(&destination) && is_pure(instruction) {
+                    dead.push(index);
+                    continue;
+                }
+            }
+            transfer(instruction, &mut live, &registers);
+        }
+        changed |= !dead.is_empty();
+        for index in dead {
+            graph.blocks[id].instructions.remove(index);
+        }
+    }
+    changed
+}
+
+/// A store is dead if a later store of the same block writes the same bytes before anything could read them.
+/// Addresses are compared as a base plus a constant offset, so different locals holding the same address still match.
+/// Only locals assigned once in registers are bases, anything else can change between the two stores.
+fn remove_dead_stores(program: &Program, graph: &mut ControlFlowGraph) -> bool {
+    let values = ssa::single_assignment_locals(program, graph);
+    let registers = graph.register_locals(program);
+    let mut offsets: HashMap<LocalId, (Operand, u64)> = HashMap::new();
+    for instruction in graph.blocks.iter().flat_map(|block| block.instructions.iter()) {
+        if let Instruction::Binary { destination, lhs, operator: Operator::Plus, rhs: Operand::Constant { value, .. } } = instruction {
+            if !values.contains(destination) || !program.locals[*destination].type_.is_pointer() { continue; }
+            if let Some((base, offset)) = base_and_offset(&values, &offsets, lhs) {
+                offsets.insert(*destination, (base, offset.wrapping_add(*value)));
+            }
+        }
+    }
+
+    let mut changed = false;
+    for block in graph.blocks.iter_mut() {
+        // The bytes written further down the block that nothing reads in between
+        let mut overwritten: Vec<(Operand, u64, u64)> = vec![];
+        let mut dead = vec![];
+        for (index, instruction) in block.instructions.iter().enumerate().rev() {
+            // Locals in memory are read by using them
+            let reads_memory = instruction.operands().into_iter().any(|operand| matches!(operand, Operand::Local(local) if !registers.contains(local)));
+            match instruction {
+                Instruction::Store { address, value } => {
+                    if reads_memory { overwritten.clear(); }
+                    let Some((base, offset)) = base_and_offset(&values, &offsets, address) else { continue };
+                    let size = program.operand_type(value).size_of();
+                    if overwritten.iter().any(|(other, start, len)| other == &base && *start <= offset && offset + size <= start + len) {
+                        dead.push(index);
+                    } else {
+                        overwritten.push((base, offset, size));
+                    }
+                }
+                Instruction::Load { .. } | Instruction::PrintString { .. } => overwritten.clear(),
+                _ if reads_memory => overwritten.clear(),
+                _ => {}
+            }
+        }
+        changed |= !dead.is_empty();
+        for index in dead {
+            block.instructions.remove(index);
+        }
+    }
+    changed
+}
+
+fn base_and_offset(values: &HashSet<LocalId>, offsets: &HashMap<LocalId, (Operand, u64)>, address: &Operand) -> Option<(Operand, u64)> {
+    match address {
+        Operand::Local(local) if values.contains(local) => Some(offsets.get(local).cloned().unwrap_or((address.clone(), 0))),
+        Operand::Local(_) => None,
+        Operand::Constant { .. } => Some((address.clone(), 0)),
+    }
+}
+
+/// Instructions that only compute their destination, a division might still fail because of a zero.
+fn is_pure(instruction: &Instruction) -> bool {
+    match instruction {
+        Instruction::Binary { operator: Operator::Divide, rhs, .. } => matches!(rhs, Operand::Constant { value, .. } if *value != 0),
+        Instruction::Move { .. } |
+        Instruction::Binary { .. } |
+        Instruction::Cast { .. } |
+        Instruction::AddressOf { .. } |
+        Instruction::DataAddress { .. } |
+        Instruction::Load { .. } |
+        Instruction::Phi { .. } => true,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::r#type::ValueType;
+
+    fn byte(value: u64) -> Operand {
+        Operand::Constant { value, type_: ValueType::U8 }
+    }
+
+    fn pointer_to(type_: ValueType) -> ValueType {
+        ValueType::Pointer { points_to: Box::new(type_), mutable: true }
+    }
+
+    fn stores(graph: &ControlFlowGraph) -> Vec<Instruction> {
+        graph.instructions().into_iter().filter(|instruction| matches!(instruction, Instruction::Store { .. })).collect()
+    }
+
+    #[test]
+    fn overwritten_stores_are_removed() {
+        // let mut a: [u8, 2] = [0, 0]; a[0] = 1; a[0] = 2; print(a[0])
+        let mut program = Program::default();
+        let array = program.new_local(None, ValueType::Array { content_type: Box::new(ValueType::U8), len: 2 });
+        let [start, element] = [0; 2].map(|_| program.new_local(None, pointer_to(ValueType::U8)));
+        let value = program.new_local(None, ValueType::U8);
+        program.instructions = vec![
+            Instruction::AddressOf { destination: start, local: array },
+            Instruction::Store { address: Operand::Local(start), value: byte(0) },
+            Instruction::Binary { destination: element, lhs: Operand::Local(start), operator: Operator::Plus, rhs: Operand::Constant { value: 0, type_: ValueType::U64 } },
+            Instruction::Store { address: Operand::Local(element), value: byte(1) },
+            Instruction::Store { address: Operand::Local(element), value: byte(2) },
+            Instruction::Load { destination: value, address: Operand::Local(element) },
+            Instruction::Print { value: Operand::Local(value) },
+        ];
+        let mut graph = ControlFlowGraph::new(&program.instructions);
+        eliminate_dead_code(&mut program, &mut graph);
+        assert_eq!(stores(&graph), vec![Instruction::Store { address: Operand::Local(element), value: byte(2) }]);
+    }
+
+    #[test]
+    fn stores_through_a_changing_pointer_are_kept() {
+        // let mut p: &mut u8 = &x; let pp: &mut &mut u8 = &p; p = 1; pp = &y; p = 2; print(x) print(y)
+        let mut program = Program::default();
+        let [x, y] = [0; 2].map(|_| program.new_local(None, ValueType::U8));
+        let p = program.new_local(None, pointer_to(ValueType::U8));
+        let pp = program.new_local(None, pointer_to(pointer_to(ValueType::U8)));
+        let [to_x, to_y] = [0; 2].map(|_| program.new_local(None, pointer_to(ValueType::U8)));
+        program.instructions = vec![
+            Instruction::Move { destination: x, source: byte(0) },
+            Instruction::Move { destination: y, source: byte(0) },
+            Instruction::AddressOf { destination: to_x, local: x },
+            Instruction::Move { destination: p, source: Operand::Local(to_x) },
+            Instruction::AddressOf { destination: pp, local: p },
+            Instruction::Store { address: Operand::Local(p), value: byte(1) },
+            Instruction::AddressOf { destination: to_y, local: y },
+            Instruction::Store { address: Operand::Local(pp), value: Operand::Local(to_y) },
+            Instruction::Store { address: Operand::Local(p), value: byte(2) },
+            Instruction::Print { value: Operand::Local(x) },
+            Instruction::Print { value: Operand::Local(y) },
+        ];
+        let mut graph = ControlFlowGraph::new(&program.instructions);
+        eliminate_dead_code(&mut program, &mut graph);
+        assert_eq!(stores(&graph).len(), 3);
+    }
+
+    #[test]
+    fn stores_read_by_using_a_local_in_memory_are_kept() {
+        // let mut x: u8 = 0; let p = &x; p = 1; print(x) p = 2; print(x)
+        let mut program = Program::default();
+        let x = program.new_local(None, ValueType::U8);
+        let p = program.new_local(None, pointer_to(ValueType::U8));
+        program.instructions = vec![
+            Instruction::AddressOf { destination: p, local: x },
+            Instruction::Store { address: Operand::Local(p), value: byte(1) },
+            Instruction::Print { value: Operand::Local(x) },
+            Instruction::Store { address: Operand::Local(p), value: byte(2) },
+            Instruction::Print { value: Operand::Local(x) },
+        ];
+        let mut graph = ControlFlowGraph::new(&program.instructions);
+        eliminate_dead_code(&mut program, &mut graph);
+        assert_eq!(stores(&graph).len(), 2);
+    }
+}
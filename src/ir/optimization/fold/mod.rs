@@ -0,0 +1,149 @@
+use std::collections::HashMap;
+use crate::ir::cfg::ControlFlowGraph;
+use crate::ir::instruction::{Instruction, Label, LocalId, Operand};
+use crate::ir::Program;
+use crate::ir::ssa;
+use crate::tokenizer::token::Operator;
+
+/// Computes everything that only depends on constants while compiling and replaces the uses of those locals
+/// with the constants. Branches on a constant condition become jumps. Expects the program in SSA form.
+pub fn fold_constants(program: &mut Program, graph: &mut ControlFlowGraph) -> bool {
+    let values = ssa::single_assignment_locals(program, graph);
+    let mut constants: HashMap<LocalId, u64> = HashMap::new();
+    let mut changed = false;
+
+    let mut progress = true;
+    while progress {
+        progress = false;
+        for instruction in graph.blocks.iter_mut().flat_map(|block| block.instructions.iter_mut()) {
+            for operand in instruction.operands_mut() {
+                if let Operand::Local(local) = operand {
+                    if let Some(value) = constants.get(local) {
+                        *operand = Operand::Constant { value: *value, type_: program.locals[*local].type_.clone() };
+                        progress = true;
+                    }
+                }
+            }
+
+            let destination = match instruction.destination() {
+                Some(destination) if values.contains(&destination) && !constants.contains_key(&destination) => destination,
+                _ => continue,
+            };
+            if let Some(value) = evaluate(program, instruction) {
+                let type_ = program.locals[destination].type_.clone();
+                *instruction = Instruction::Move { destination, source: Operand::Constant { value, type_ } };
+                constants.insert(destination, value);
+                progress = true;
+            }
+        }
+        changed |= progress;
+    }
+
+    // Folded phis became moves in place, the phis that are left still have to come first
+    for block in graph.blocks.iter_mut() {
+        let start = block.instructions.iter()
+            .take_while(|instruction| matches!(instruction, Instruction::Label { .. } | Instruction::Phi { .. } | Instruction::Move { source: Operand::Constant { .. }, .. }))
+            .count();
+        let (phis, moves): (Vec<Instruction>, Vec<Instruction>) = block.instructions.drain(..start)
+            .partition(|instruction| matches!(instruction, Instruction::Label { .. } | Instruction::Phi { .. }));
+        block.instructions.splice(0..0, phis.into_iter().chain(moves));
+    }
+
+    // A branch that always goes the same way no longer comes from its block for the phis of the other target
+    let mut removed_edges: Vec<(Label, Label)> = vec![];
+    for block in graph.blocks.iter_mut() {
+        let label = block.label();
+        if let Some(instruction @ Instruction::Branch { condition: Operand::Constant { .. }, .. }) = block.instructions.last_mut() {
+            let (taken, skipped) = match instruction {
+                Instruction::Branch { condition: Operand::Constant { value: 0, .. }, if_true, if_false } => (*if_false, *if_true),
+                Instruction::Branch { if_true, if_false, .. } => (*if_true, *if_false),
+                _ => unreachable!(),
+            };
+            *instruction = Instruction::Jump { label: taken };
+            if let Some(label) = label.filter(|_| taken != skipped) {
+                removed_edges.push((label, skipped));
+            }
+            changed = true;
+        }
+    }
+    for block in graph.blocks.iter_mut() {
+        let label = block.label();
+        for instruction in block.instructions.iter_mut() {
+            if let Instruction::Phi { sources, .. } = instruction {
+                sources.retain(|(predecessor, _)| !removed_edges.iter().any(|edge| Some(edge.1) == label && edge.0 == *predecessor));
+            }
+        }
+    }
+    graph.recompute();
+    changed
+}
+
+/// The value of the destination if all operands are known, wraps around like the operation at runtime would.
+fn evaluate(program: &Program, instruction: &Instruction) -> Option<u64> {
+    match instruction {
+        Instruction::Move { source: Operand::Constant { value, .. }, .. } => Some(*value),
+        Instruction::Binary { destination, lhs: Operand::Constant { value: lhs, .. }, operator, rhs: Operand::Constant { value: rhs, .. } } => {
+            let type_ = &program.locals[*destination].type_;
+            match operator {
+                Operator::Plus => Some(type_.wrap(lhs.wrapping_add(*rhs))),
+                Operator::Minus => Some(type_.wrap(lhs.wrapping_sub(*rhs))),
+                Operator::Times => Some(type_.wrap(lhs.wrapping_mul(*rhs))),
+                // Dividing by zero is left for the program to fail at runtime
+                Operator::Divide if *rhs != 0 => Some(lhs / rhs),
+                Operator::Equal => Some((lhs == rhs) as u64),
+                Operator::NotEqual => Some((lhs != rhs) as u64),
+                Operator::Less => Some((lhs < rhs) as u64),
+                Operator::Greater => Some((lhs > rhs) as u64),
+                Operator::LessEqual => Some((lhs <= rhs) as u64),
+                Operator::GreaterEqual => Some((lhs >= rhs) as u64),
+                _ => None,
+            }
+        }
+        Instruction::Cast { value: Operand::Constant { value, .. }, to, .. } => Some(to.cast_value(*value)),
+        Instruction::Phi { sources, .. } => match sources.first() {
+            Some((_, Operand::Constant { value, .. })) if sources.iter().all(|(_, source)| matches!(source, Operand::Constant { value: other, .. } if other == value)) => Some(*value),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir::verify::verify;
+    use crate::parser::r#type::ValueType;
+
+    fn constant(value: u64) -> Operand {
+        Operand::Constant { value, type_: ValueType::U64 }
+    }
+
+    #[test]
+    fn folded_phis_move_behind_the_remaining_phis() {
+        let mut program = Program::default();
+        let [k, i] = [0; 2].map(|_| program.new_local(None, ValueType::U64));
+        let condition = program.new_local(None, ValueType::Bool);
+        let [check, body, end] = [0; 3].map(|_| program.new_label());
+        program.instructions = vec![
+            Instruction::Move { destination: k, source: constant(5) },
+            Instruction::Move { destination: i, source: constant(0) },
+            Instruction::Label { label: check },
+            Instruction::Binary { destination: condition, lhs: Operand::Local(i), operator: Operator::Less, rhs: constant(3) },
+            Instruction::Branch { condition: Operand::Local(condition), if_true: body, if_false: end },
+            Instruction::Label { label: body },
+            Instruction::Move { destination: k, source: constant(5) },
+            Instruction::Binary { destination: i, lhs: Operand::Local(i), operator: Operator::Plus, rhs: constant(1) },
+            Instruction::Jump { label: check },
+            Instruction::Label { label: end },
+            Instruction::Print { value: Operand::Local(k) },
+            Instruction::Print { value: Operand::Local(i) },
+        ];
+        let mut graph = ControlFlowGraph::new(&program.instructions);
+        ssa::into_ssa(&mut program, &mut graph);
+        fold_constants(&mut program, &mut graph);
+        verify(&program, &graph, true).unwrap();
+
+        let header = &graph.blocks[graph.block_of_label(check).unwrap()].instructions;
+        assert!(matches!(header[..3], [Instruction::Label { .. }, Instruction::Phi { .. }, Instruction::Move { source: Operand::Constant { value: 5, .. }, .. }]));
+    }
+}
@@ -0,0 +1,153 @@
+use anyhow::{bail, Context};
+use crate::ir::cfg::ControlFlowGraph;
+use crate::ir::Program;
+use crate::ir::ssa;
+use crate::ir::verify::verify;
+
+pub mod fold;
+pub mod dce;
+pub mod cse;
+pub mod copy;
+
+/// An optimisation working on the SSA form of the program, returns whether it changed anything.
+pub struct Pass {
+    pub name: &'static str,
+    pub run: fn(&mut Program, &mut ControlFlowGraph) -> bool,
+}
+
+/// Every pass in the order they run in.
+pub const PASSES: [Pass; 4] = [
+    Pass { name: "fold", run: fold::fold_constants },
+    Pass { name: "cse", run: cse::eliminate_common_subexpressions },
+    Pass { name: "copy", run: copy::propagate_copies },
+    Pass { name: "dce", run: dce::eliminate_dead_code },
+];
+
+/// The pipeline is repeated while it still changes something, but not forever.
+const MAX_ROUNDS: usize = 8;
+
+/// Runs the enabled passes and verifies the program after each of them.
+pub struct PassManager {
+    enabled: Vec<&'static str>,
+    print_after: Vec<String>,
+}
+
+impl PassManager {
+    /// The passes of an optimisation level, `-O0` doesnt run any.
+    pub fn new(level: u8) -> Self {
+        let enabled = match level {
+            0 => vec![],
+            1 => vec!["fold", "dce"],
+            _ => vec!["fold", "cse", "copy", "dce"],
+        };
+        Self {
+            enabled,
+            print_after: vec![],
+        }
+    }
+
+    /// Enables or disables single passes, like `+fold,-cse`.
+    pub fn with_overrides(mut self, overrides: &str) -> anyhow::Result<Self> {
+        for toggle in overrides.split(',').filter(|toggle| !toggle.is_empty()) {
+            let (enable, name) = match toggle.split_at(1) {
+                ("+", name) => (true, name),
+                ("-", name) => (false, name),
+                _ => bail!("Expected `+` or `-` before the pass `{}`!", toggle),
+            };
+            let name = pass_name(name)?;
+            self.enabled.retain(|enabled| *enabled != name);
+            if enable { self.enabled.push(name); }
+        }
+        Ok(self)
+    }
+
+    /// Prints the program after every run of these passes.
+    pub fn with_print_after(mut self, passes: &str) -> anyhow::Result<Self> {
+        for name in passes.split(',').filter(|name| !name.is_empty()) {
+            self.print_after.push(pass_name(name)?.to_string());
+        }
+        Ok(self)
+    }
+
+    pub fn run(&self, program: &mut Program) -> anyhow::Result<()> {
+        if self.enabled.is_empty() { return Ok(()); }
+
+        let mut graph = ControlFlowGraph::new(&program.instructions);
+        verify(program, &graph, false).context("Invalid IR before optimising")?;
+        ssa::into_ssa(program, &mut graph);
+        verify(program, &graph, true).context("Invalid IR after converting into SSA form")?;
+
+        for _ in 0..MAX_ROUNDS {
+            let mut changed = false;
+            for pass in PASSES.iter().filter(|pass| self.enabled.contains(&pass.name)) {
+                changed |= (pass.run)(program, &mut graph);
+                verify(program, &graph, true).with_context(|| format!("Invalid IR after the pass `{}`", pass.name))?;
+
+                if self.print_after.iter().any(|name| name == pass.name) {
+                    program.instructions = graph.instructions();
+                    println!("; IR after {}", pass.name);
+                    print!("{}", program);
+                }
+            }
+            if !changed { break; }
+        }
+
+        ssa::out_of_ssa(program, &mut graph);
+        verify(program, &graph, false).context("Invalid IR after converting out of SSA form")?;
+        program.instructions = graph.instructions();
+        Ok(())
+    }
+}
+
+fn pass_name(name: &str) -> anyhow::Result<&'static str> {
+    match PASSES.iter().find(|pass| pass.name == name) {
+        Some(pass) => Ok(pass.name),
+        None => bail!("Unknown pass `{}`, expected one of: {}", name, PASSES.iter().map(|pass| pass.name).collect::<Vec<_>>().join(", ")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir::instruction::{Instruction, Operand};
+    use crate::parser::r#type::ValueType;
+    use crate::tokenizer::token::Operator;
+
+    #[test]
+    fn overrides_toggle_single_passes() {
+        let passes = PassManager::new(1).with_overrides("+cse,-fold").unwrap();
+        assert_eq!(passes.enabled, vec!["dce", "cse"]);
+        let passes = PassManager::new(0).with_overrides("+dce,+dce").unwrap();
+        assert_eq!(passes.enabled, vec!["dce"]);
+        let passes = PassManager::new(2).with_overrides("").unwrap();
+        assert_eq!(passes.enabled, vec!["fold", "cse", "copy", "dce"]);
+    }
+
+    #[test]
+    fn overrides_reject_unknown_passes() {
+        assert!(PassManager::new(2).with_overrides("fold").is_err());
+        assert!(PassManager::new(2).with_overrides("+inline").is_err());
+        assert!(PassManager::new(2).with_print_after("fold,inline").is_err());
+    }
+
+    #[test]
+    fn only_enabled_passes_run() {
+        let mut program = Program::default();
+        let local = program.new_local(None, ValueType::U64);
+        let two = Operand::Constant { value: 2, type_: ValueType::U64 };
+        program.instructions = vec![
+            Instruction::Binary { destination: local, lhs: two.clone(), operator: Operator::Plus, rhs: two },
+            Instruction::Print { value: Operand::Local(local) },
+        ];
+        let is_print_of = |instruction: &Instruction, value: u64| matches!(instruction, Instruction::Print { value: Operand::Constant { value: printed, .. } } if *printed == value);
+
+        let mut unfolded = program.clone();
+        PassManager::new(2).with_overrides("-fold").unwrap().run(&mut unfolded).unwrap();
+        assert!(unfolded.instructions.iter().any(|instruction| matches!(instruction, Instruction::Binary { .. })));
+
+        // Without dce the folded addition stays behind as a move
+        PassManager::new(0).with_overrides("+fold").unwrap().run(&mut program).unwrap();
+        assert!(program.instructions.iter().any(|instruction| matches!(instruction, Instruction::Move { .. })));
+        assert!(program.instructions.iter().any(|instruction| is_print_of(instruction, 4)));
+    }
+}
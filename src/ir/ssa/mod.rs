@@ -0,0 +1,331 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+use crate::ir::cfg::{BasicBlock, BlockId, ControlFlowGraph};
+use crate::ir::instruction::{Instruction, Label, Local, LocalId, Operand};
+use crate::ir::Program;
+
+/// Renames the locals living in registers so that every one of them is assigned exactly once,
+/// where different definitions meet a phi picks the right one. Locals in memory are left alone.
+pub fn into_ssa(program: &mut Program, graph: &mut ControlFlowGraph) {
+    graph.label_blocks(program);
+    let promoted = graph.register_locals(program);
+    let live_in = graph.live_in(&promoted);
+    let frontiers = graph.dominance_frontiers();
+
+    // Phis go into the frontiers of every definition, but only where the local is still needed
+    let mut phis: Vec<Vec<LocalId>> = vec![vec![]; graph.blocks.len()];
+    for &local in &promoted {
+        let mut worklist: Vec<BlockId> = graph.blocks.iter().enumerate()
+            .filter(|(_, block)| block.instructions.iter().any(|instruction| instruction.destination() == Some(local)))
+            .map(|(id, _)| id)
+            .collect();
+        let mut placed: HashSet<BlockId> = HashSet::new();
+        while let Some(block) = worklist.pop() {
+            for &frontier in &frontiers[block] {
+                if !live_in[frontier].contains(&local) || !placed.insert(frontier) { continue; }
+                phis[frontier].push(local);
+                worklist.push(frontier);
+            }
+        }
+    }
+    for (block, locals) in phis.iter().enumerate() {
+        let predecessors: Vec<Label> = graph.blocks[block].predecessors.iter()
+            .map(|predecessor| graph.blocks[*predecessor].label().unwrap())
+            .collect();
+        for (index, local) in locals.iter().enumerate() {
+            let sources = predecessors.iter().map(|label| (*label, Operand::Local(*local))).collect();
+            graph.blocks[block].instructions.insert(1 + index, Instruction::Phi { destination: *local, sources });
+        }
+    }
+
+    Renamer { program, promoted: &promoted, phis: &phis, versions: HashMap::new(), counts: HashMap::new() }.rename(graph);
+}
+
+/// Replaces every phi with moves at the end of the predecessors. Edges leaving a branch get a block of their own,
+/// so that the moves only run when that edge is taken.
+pub fn out_of_ssa(program: &mut Program, graph: &mut ControlFlowGraph) {
+    graph.label_blocks(program);
+    let mut copies: HashMap<(Label, Label), Vec<(LocalId, Operand)>> = HashMap::new();
+    for block in graph.blocks.iter_mut() {
+        let label = block.label().unwrap();
+        block.instructions.retain(|instruction| match instruction {
+            Instruction::Phi { destination, sources } => {
+                for (predecessor, source) in sources {
+                    copies.entry((*predecessor, label)).or_default().push((*destination, source.clone()));
+                }
+                false
+            }
+            _ => true,
+        });
+    }
+
+    let mut blocks = vec![];
+    for mut block in std::mem::take(&mut graph.blocks) {
+        let label = block.label().unwrap();
+        let mut edge_blocks = vec![];
+        if let Some(Instruction::Branch { if_true, if_false, .. }) = block.instructions.last_mut() {
+            let mut edges: HashMap<Label, Label> = HashMap::new();
+            for target in [if_true, if_false] {
+                if let Some(copies) = copies.remove(&(label, *target)) {
+                    let edge = program.new_label();
+                    let mut instructions = vec![Instruction::Label { label: edge }];
+                    instructions.extend(sequentialize(program, copies));
+                    instructions.push(Instruction::Jump { label: *target });
+                    edge_blocks.push(BasicBlock { instructions, ..BasicBlock::default() });
+                    edges.insert(*target, edge);
+                }
+                // Both targets can be the same block
+                if let Some(edge) = edges.get(target) {
+                    *target = *edge;
+                }
+            }
+        } else {
+            let moves: Vec<(LocalId, Operand)> = copies.iter()
+                .filter(|((predecessor, _), _)| *predecessor == label)
+                .flat_map(|(_, copies)| copies.iter().cloned())
+                .collect();
+            let position = block.instructions.len() - block.instructions.last().is_some_and(Instruction::is_terminator) as usize;
+            block.instructions.splice(position..position, sequentialize(program, moves));
+        }
+        // Edge blocks end with a jump, so placing them right after a branch doesnt change what falls through
+        blocks.push(block);
+        blocks.extend(edge_blocks);
+    }
+    graph.blocks = blocks;
+    graph.recompute();
+}
+
+/// Locals assigned exactly once that only live in registers, in SSA form they hold the same value everywhere.
+pub fn single_assignment_locals(program: &Program, graph: &ControlFlowGraph) -> HashSet<LocalId> {
+    let mut definitions: HashMap<LocalId, usize> = HashMap::new();
+    for instruction in graph.blocks.iter().flat_map(|block| block.instructions.iter()) {
+        if let Some(destination) = instruction.destination() {
+            *definitions.entry(destination).or_default() += 1;
+        }
+    }
+    let promotable = graph.register_locals(program);
+    definitions.into_iter()
+        .filter(|(local, count)| *count == 1 && promotable.contains(local))
+        .map(|(local, _)| local)
+        .collect()
+}
+
+/// Turns copies that happen at the same time into moves one after another,
+/// a cycle like swapping two locals needs a temporary.
+fn sequentialize(program: &mut Program, mut copies: Vec<(LocalId, Operand)>) -> Vec<Instruction> {
+    copies.retain(|(destination, source)| source != &Operand::Local(*destination));
+    let mut moves = vec![];
+    while !copies.is_empty() {
+        let ready = copies.iter().position(|(destination, _)| !copies.iter().any(|(_, source)| source == &Operand::Local(*destination)));
+        match ready {
+            Some(index) => {
+                let (destination, source) = copies.remove(index);
+                moves.push(Instruction::Move { destination, source });
+            }
+            None => {
+                let (destination, _) = copies[0];
+                let temporary = program.new_local(None, program.locals[destination].type_.clone());
+                moves.push(Instruction::Move { destination: temporary, source: Operand::Local(destination) });
+                for (_, source) in copies.iter_mut().filter(|(_, source)| source == &Operand::Local(destination)) {
+                    *source = Operand::Local(temporary);
+                }
+            }
+        }
+    }
+    moves
+}
+
+struct Renamer<'a> {
+    program: &'a mut Program,
+    promoted: &'a BTreeSet<LocalId>,
+    /// The original local of every phi placed at the start of a block.
+    phis: &'a [Vec<LocalId>],
+    /// The current version of every promoted local, changes while walking down the dominator tree.
+    versions: HashMap<LocalId, Vec<LocalId>>,
+    counts: HashMap<LocalId, usize>,
+}
+
+impl Renamer<'_> {
+    fn rename(&mut self, graph: &mut ControlFlowGraph) {
+        let children = graph.dominator_tree();
+        // Blocks are left again once all blocks they dominate are renamed, undoing their definitions
+        let mut stack = vec![(0, true)];
+        let mut defined: Vec<Vec<LocalId>> = vec![vec![]; graph.blocks.len()];
+        while let Some((block, enter)) = stack.pop() {
+            if !enter {
+                for local in &defined[block] {
+                    self.versions.get_mut(local).unwrap().pop();
+                }
+                continue;
+            }
+            defined[block] = self.rename_block(graph, block);
+            stack.push((block, false));
+            stack.extend(children[block].iter().rev().map(|child| (*child, true)));
+        }
+    }
+
+    /// Returns the original locals defined in the block.
+    fn rename_block(&mut self, graph: &mut ControlFlowGraph, block: BlockId) -> Vec<LocalId> {
+        let mut defined = vec![];
+        for instruction in graph.blocks[block].instructions.iter_mut() {
+            if !matches!(instruction, Instruction::Phi { .. }) {
+                for operand in instruction.operands_mut() {
+                    if let Operand::Local(local) = operand {
+                        *local = self.current(*local);
+                    }
+                }
+            }
+            if let Some(destination) = instruction.destination_mut().filter(|destination| self.promoted.contains(destination)) {
+                let original = *destination;
+                *destination = self.new_version(original);
+                defined.push(original);
+            }
+        }
+
+        let label = graph.blocks[block].label().unwrap();
+        for successor in graph.blocks[block].successors.clone() {
+            let instructions = &mut graph.blocks[successor].instructions;
+            for (index, local) in self.phis[successor].iter().enumerate() {
+                if let Instruction::Phi { sources, .. } = &mut instructions[1 + index] {
+                    for (_, source) in sources.iter_mut().filter(|(predecessor, _)| *predecessor == label) {
+                        *source = Operand::Local(self.current(*local));
+                    }
+                }
+            }
+        }
+        defined
+    }
+
+    /// Locals read before any definition keep their original name.
+    fn current(&self, local: LocalId) -> LocalId {
+        self.versions.get(&local).and_then(|versions| versions.last().copied()).unwrap_or(local)
+    }
+
+    fn new_version(&mut self, original: LocalId) -> LocalId {
+        let Local { name, type_ } = self.program.locals[original].clone();
+        let count = self.counts.entry(original).or_default();
+        *count += 1;
+        let name = name.map(|name| [name, format!(".{}", count).into_bytes()].concat());
+        let version = self.program.new_local(name, type_);
+        self.versions.entry(original).or_default().push(version);
+        version
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir::verify::verify;
+    use crate::parser::r#type::ValueType;
+    use crate::tokenizer::token::Operator;
+
+    fn constant(value: u64) -> Operand {
+        Operand::Constant { value, type_: ValueType::U64 }
+    }
+
+    /// Swaps `a` and `b` three times through `t`, then prints both.
+    fn swap_loop() -> Program {
+        let mut program = Program::default();
+        let [a, b, i, t] = [0; 4].map(|_| program.new_local(None, ValueType::U64));
+        let condition = program.new_local(None, ValueType::Bool);
+        let [check, body, end] = [0; 3].map(|_| program.new_label());
+        program.instructions = vec![
+            Instruction::Move { destination: a, source: constant(1) },
+            Instruction::Move { destination: b, source: constant(2) },
+            Instruction::Move { destination: i, source: constant(0) },
+            Instruction::Label { label: check },
+            Instruction::Binary { destination: condition, lhs: Operand::Local(i), operator: Operator::Less, rhs: constant(3) },
+            Instruction::Branch { condition: Operand::Local(condition), if_true: body, if_false: end },
+            Instruction::Label { label: body },
+            Instruction::Move { destination: t, source: Operand::Local(a) },
+            Instruction::Move { destination: a, source: Operand::Local(b) },
+            Instruction::Move { destination: b, source: Operand::Local(t) },
+            Instruction::Binary { destination: i, lhs: Operand::Local(i), operator: Operator::Plus, rhs: constant(1) },
+            Instruction::Jump { label: check },
+            Instruction::Label { label: end },
+            Instruction::Print { value: Operand::Local(a) },
+            Instruction::Print { value: Operand::Local(b) },
+        ];
+        program
+    }
+
+    #[test]
+    fn sequentialize_breaks_cycles_with_a_temporary() {
+        let mut program = Program::default();
+        let [a, b] = [0; 2].map(|_| program.new_local(None, ValueType::U64));
+        let moves = sequentialize(&mut program, vec![(a, Operand::Local(b)), (b, Operand::Local(a))]);
+        let temporary = program.locals.len() - 1;
+        assert_eq!(moves, vec![
+            Instruction::Move { destination: temporary, source: Operand::Local(a) },
+            Instruction::Move { destination: a, source: Operand::Local(b) },
+            Instruction::Move { destination: b, source: Operand::Local(temporary) },
+        ]);
+    }
+
+    #[test]
+    fn sequentialize_orders_chains_and_drops_self_copies() {
+        let mut program = Program::default();
+        let [a, b, c] = [0; 3].map(|_| program.new_local(None, ValueType::U64));
+        let moves = sequentialize(&mut program, vec![(b, Operand::Local(a)), (c, Operand::Local(b)), (a, Operand::Local(a))]);
+        assert_eq!(program.locals.len(), 3);
+        assert_eq!(moves, vec![
+            Instruction::Move { destination: c, source: Operand::Local(b) },
+            Instruction::Move { destination: b, source: Operand::Local(a) },
+        ]);
+    }
+
+    #[test]
+    fn into_ssa_places_phis_where_definitions_meet() {
+        let mut program = swap_loop();
+        let mut graph = ControlFlowGraph::new(&program.instructions);
+        into_ssa(&mut program, &mut graph);
+
+        verify(&program, &graph, true).unwrap();
+
+        let check = graph.block_of_label(0).unwrap();
+        let phis = graph.blocks[check].instructions.iter().filter(|instruction| matches!(instruction, Instruction::Phi { .. })).count();
+        // The temporary and the condition dont live into the loop header
+        assert_eq!(phis, 3);
+    }
+
+    #[test]
+    fn out_of_ssa_keeps_swaps_apart() {
+        let mut program = Program::default();
+        let [a0, b0, i0, a1, b1, i1, i2] = [0; 7].map(|_| program.new_local(None, ValueType::U64));
+        let condition = program.new_local(None, ValueType::Bool);
+        let [entry, check, body, end] = [0; 4].map(|_| program.new_label());
+        // The phis of the header read each other, as they do once the copies of a swap are propagated
+        program.instructions = vec![
+            Instruction::Label { label: entry },
+            Instruction::Move { destination: a0, source: constant(1) },
+            Instruction::Move { destination: b0, source: constant(2) },
+            Instruction::Move { destination: i0, source: constant(0) },
+            Instruction::Label { label: check },
+            Instruction::Phi { destination: a1, sources: vec![(entry, Operand::Local(a0)), (body, Operand::Local(b1))] },
+            Instruction::Phi { destination: b1, sources: vec![(entry, Operand::Local(b0)), (body, Operand::Local(a1))] },
+            Instruction::Phi { destination: i1, sources: vec![(entry, Operand::Local(i0)), (body, Operand::Local(i2))] },
+            Instruction::Binary { destination: condition, lhs: Operand::Local(i1), operator: Operator::Less, rhs: constant(3) },
+            Instruction::Branch { condition: Operand::Local(condition), if_true: body, if_false: end },
+            Instruction::Label { label: body },
+            Instruction::Binary { destination: i2, lhs: Operand::Local(i1), operator: Operator::Plus, rhs: constant(1) },
+            Instruction::Jump { label: check },
+            Instruction::Label { label: end },
+            Instruction::Print { value: Operand::Local(a1) },
+            Instruction::Print { value: Operand::Local(b1) },
+        ];
+        let mut graph = ControlFlowGraph::new(&program.instructions);
+        let locals = program.locals.len();
+        out_of_ssa(&mut program, &mut graph);
+        assert_eq!(program.locals.len(), locals + 1, "the swap needs a temporary");
+        verify(&program, &graph, false).unwrap();
+        assert!(graph.blocks.iter().flat_map(|block| block.instructions.iter()).all(|instruction| !matches!(instruction, Instruction::Phi { .. })));
+
+        // Run the moves at the end of the loop body with every local holding its own id
+        let mut values: HashMap<LocalId, LocalId> = (0..program.locals.len()).map(|local| (local, local)).collect();
+        for instruction in &graph.blocks[graph.block_of_label(body).unwrap()].instructions {
+            if let Instruction::Move { destination, source: Operand::Local(source) } = instruction {
+                values.insert(*destination, values[source]);
+            }
+        }
+        assert_eq!((values[&a1], values[&b1], values[&i1]), (b1, a1, i2));
+    }
+}
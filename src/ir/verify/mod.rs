@@ -0,0 +1,121 @@
+use std::collections::{HashMap, HashSet};
+use anyhow::bail;
+use crate::ir::cfg::ControlFlowGraph;
+use crate::ir::instruction::{Instruction, Label, LocalId, Operand};
+use crate::ir::Program;
+use crate::parser::r#type::ValueType;
+use crate::tokenizer::token::Operator;
+
+/// Checks the invariants every pass relies on, in SSA form every local in a register is assigned once
+/// and its definition dominates all of its uses.
+pub fn verify(program: &Program, graph: &ControlFlowGraph, ssa: bool) -> anyhow::Result<()> {
+    let mut labels: HashSet<Label> = HashSet::new();
+    for label in graph.blocks.iter().filter_map(|block| block.label()) {
+        if !labels.insert(label) { bail!("Label L{} is defined more than once!", label) }
+    }
+
+    // Only locals kept in registers are renamed in SSA form
+    let registers = graph.register_locals(program);
+
+    let mut definitions: HashMap<LocalId, (usize, usize)> = HashMap::new();
+    for (id, block) in graph.blocks.iter().enumerate() {
+        for (index, instruction) in block.instructions.iter().enumerate() {
+            let line = program.format_instruction(instruction);
+            for local in instruction.operands().into_iter().filter_map(local_of).chain(instruction.destination()) {
+                if local >= program.locals.len() { bail!("`{}` uses the unknown local {}!", line, local) }
+            }
+            if let Instruction::AddressOf { local, .. } = instruction {
+                if *local >= program.locals.len() { bail!("`{}` uses the unknown local {}!", line, local) }
+            }
+            match instruction {
+                Instruction::Jump { label } if !labels.contains(label) => bail!("`{}` jumps to a missing label!", line),
+                Instruction::Branch { if_true, if_false, .. } if !labels.contains(if_true) || !labels.contains(if_false) => bail!("`{}` jumps to a missing label!", line),
+                Instruction::Label { .. } if index != 0 => bail!("`{}` is in the middle of a block!", line),
+                _ if instruction.is_terminator() && index + 1 != block.instructions.len() => bail!("`{}` is in the middle of a block!", line),
+                _ => {}
+            }
+            verify_types(program, instruction, &line)?;
+
+            if let Instruction::Phi { sources, .. } = instruction {
+                if !ssa { bail!("`{}` is a phi outside of SSA form!", line) }
+                if block.instructions[..index].iter().any(|previous| !matches!(previous, Instruction::Label { .. } | Instruction::Phi { .. })) {
+                    bail!("`{}` doesnt come before the other instructions of its block!", line)
+                }
+                let predecessors: HashSet<Option<Label>> = block.predecessors.iter().map(|predecessor| graph.blocks[*predecessor].label()).collect();
+                if let Some((label, _)) = sources.iter().find(|(label, _)| !predecessors.contains(&Some(*label))) {
+                    bail!("`{}` has a source for L{} which isnt a predecessor!", line, label)
+                }
+            }
+            if let Some(destination) = instruction.destination() {
+                if definitions.insert(destination, (id, index)).is_some() && ssa && registers.contains(&destination) {
+                    bail!("`{}` assigns {} a second time in SSA form!", line, program.local_name(destination))
+                }
+            }
+        }
+    }
+
+    if !ssa { return Ok(()); }
+    for (id, block) in graph.blocks.iter().enumerate().filter(|(id, _)| graph.is_reachable(*id)) {
+        for (index, instruction) in block.instructions.iter().enumerate() {
+            // Phis read their sources at the end of the predecessors
+            let uses: Vec<(LocalId, usize, usize)> = match instruction {
+                Instruction::Phi { sources, .. } => sources.iter()
+                    .filter_map(|(label, source)| Some((local_of(source)?, graph.block_of_label(*label)?, usize::MAX)))
+                    .collect(),
+                _ => instruction.operands().into_iter().filter_map(local_of).map(|local| (local, id, index)).collect(),
+            };
+            for (local, block, index) in uses {
+                let dominated = match definitions.get(&local) {
+                    Some((definition, position)) if *definition == block => *position < index,
+                    Some((definition, _)) => graph.dominates(*definition, block),
+                    None => false,
+                };
+                if !dominated && registers.contains(&local) && graph.is_reachable(block) {
+                    bail!("`{}` reads {} which isnt defined on every path to it!", program.format_instruction(instruction), program.local_name(local))
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn verify_types(program: &Program, instruction: &Instruction, line: &str) -> anyhow::Result<()> {
+    let type_of = |operand: &Operand| program.operand_type(operand);
+    let compatible = |lhs: &ValueType, rhs: &ValueType| lhs == rhs || (lhs.is_pointer() && rhs.is_pointer());
+    match instruction {
+        Instruction::Move { destination, source } if !compatible(&program.locals[*destination].type_, &type_of(source)) => {
+            bail!("`{}` moves a {} into a {}!", line, type_of(source), program.locals[*destination].type_)
+        }
+        Instruction::Binary { destination, lhs, operator, rhs } => match operator {
+            Operator::Equal | Operator::NotEqual | Operator::Less | Operator::Greater | Operator::LessEqual | Operator::GreaterEqual => {
+                if !compatible(&type_of(lhs), &type_of(rhs)) { bail!("`{}` compares a {} with a {}!", line, type_of(lhs), type_of(rhs)) }
+                if program.locals[*destination].type_ != ValueType::Bool { bail!("`{}` doesnt produce a bool!", line) }
+            }
+            // Pointer arithmetic adds byte offsets to addresses
+            _ if program.locals[*destination].type_.is_pointer() => {}
+            _ => if type_of(lhs) != type_of(rhs) || type_of(lhs) != program.locals[*destination].type_ {
+                bail!("`{}` mixes the types {}, {} and {}!", line, type_of(lhs), type_of(rhs), program.locals[*destination].type_)
+            },
+        },
+        Instruction::Cast { destination, value, from, to } if &type_of(value) != from || &program.locals[*destination].type_ != to => {
+            bail!("`{}` doesnt match the types of its operands!", line)
+        }
+        Instruction::Branch { condition, .. } if type_of(condition) != ValueType::Bool => {
+            bail!("`{}` branches on a {}!", line, type_of(condition))
+        }
+        Instruction::Phi { destination, sources } => {
+            if let Some((_, source)) = sources.iter().find(|(_, source)| !compatible(&type_of(source), &program.locals[*destination].type_)) {
+                bail!("`{}` merges a {} into a {}!", line, type_of(source), program.locals[*destination].type_)
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+fn local_of(operand: &Operand) -> Option<LocalId> {
+    match operand {
+        Operand::Local(local) => Some(*local),
+        Operand::Constant { .. } => None,
+    }
+}
@@ -1,3 +1,6 @@
+pub mod diagnostic;
 pub mod tokenizer;
 pub mod parser;
-pub mod semantic_analysis;
\ No newline at end of file
+pub mod semantic_analysis;
+pub mod ir;
+pub mod backend;
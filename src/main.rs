@@ -1,40 +1,177 @@
+pub mod diagnostic;
 pub mod tokenizer;
 pub mod parser;
 pub mod semantic_analysis;
+pub mod ir;
+pub mod backend;
 
 
 use std::fs;
+use std::io::Write;
 use std::str::Chars;
+use crate::backend::bytecode::Bytecode;
+use crate::backend::c::CGenerator;
+use crate::backend::bytecode::generator::BytecodeGenerator;
+use crate::backend::bytecode::vm::Vm;
+use crate::backend::wasm::encoder;
+use crate::backend::wasm::generator::WasmGenerator;
+use crate::backend::x86_64::generator::MachineGenerator;
+use crate::backend::x86_64::MachineProgram;
+use crate::diagnostic::Diagnostic;
+use crate::ir::cfg::ControlFlowGraph;
+use crate::ir::generator::IrGenerator;
+use crate::ir::optimization::PassManager;
+use crate::ir::ssa;
 use crate::parser::Parser;
+use crate::semantic_analysis::initialization::InitializationAnalysis;
+use crate::semantic_analysis::lint::Linter;
 use crate::semantic_analysis::symbol_table::SymbolTable;
 use crate::tokenizer::Tokenizer;
 
 fn main() {
 
-    let input_string: String = fs::read_to_string("res/script.he").expect("Unknown file!");
+    let (flags, files): (Vec<String>, Vec<String>) = std::env::args().skip(1).partition(|argument| argument.starts_with('-'));
+    let bounds_checks = flags.iter().any(|flag| flag == "--bounds-checks");
+    let optimization_level = match flags.iter().rev().find_map(|flag| flag.strip_prefix("-O")) {
+        None | Some("0") => 0,
+        Some("1") => 1,
+        Some("2") => 2,
+        Some(level) => {
+            eprintln!("Unknown optimisation level -O{}, expected one of: -O0, -O1, -O2", level);
+            std::process::exit(1);
+        }
+    };
+    // Emitting something specific only prints that, so the output can be piped into other tools
+    let emit = flags.iter().find_map(|flag| flag.strip_prefix("--emit="));
+    if let Some(emit) = emit.filter(|emit| !["cfg-dot", "ssa", "asm", "bytecode", "c", "wat", "wasm"].contains(emit)) {
+        eprintln!("Unknown --emit={}, expected one of: cfg-dot, ssa, asm, bytecode, c, wat, wasm", emit);
+        std::process::exit(1);
+    }
+    let run = flags.iter().any(|flag| flag == "--run");
+    if run && emit.is_some() {
+        eprintln!("--run cannot be combined with --emit");
+        std::process::exit(1);
+    }
+    // Only what the program prints is shown when running it
+    let verbose = emit.is_none() && !run;
+
+    let input_path = files.first().cloned().unwrap_or("res/script.he".to_string());
+    // Precompiled programs are run directly
+    if input_path.ends_with(".hbc") {
+        let bytes = fs::read(&input_path).expect("Unknown file!");
+        let result = Bytecode::from_bytes(&bytes)
+            .and_then(|bytecode| Vm::new(&bytecode).run(&mut std::io::stdout()));
+        match result {
+            Ok(code) => std::process::exit(code as i32),
+            Err(error) => {
+                eprintln!("ERROR WHILE RUNNING: {:#}", error);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    let input_string: String = fs::read_to_string(input_path).expect("Unknown file!");
     let input_chars: Chars = input_string.chars();
 
     let tokens = Tokenizer::new(input_chars.peekable()).tokenize()
         .into_iter()
         .peekable();
 
-    tokens
-        .clone()
-        .for_each(|token| println!("{}", token));
+    if verbose {
+        tokens
+            .clone()
+            .for_each(|token| println!("{}", token));
 
-    println!();
+        println!();
+    }
 
     let mut statements = Parser::new(tokens).parse_statements();
 
-    statements.iter()
-        .for_each(|statement| println!("{:?}", statement));
+    if verbose {
+        statements.iter()
+            .for_each(|statement| println!("{:?}", statement));
 
-    println!();
+        println!();
+    }
 
     let mut symbol_table = SymbolTable::new();
-    statements.iter_mut()
-        .for_each(|statements| statements.resolve(&mut symbol_table));
+    for statement in statements.iter_mut() {
+        if let Err(error) = statement.resolve(&mut symbol_table) {
+            match error.downcast_ref::<Diagnostic>() {
+                Some(diagnostic) => eprint!("{}", diagnostic.render(&input_string)),
+                None => eprintln!("ERROR WHILE ANALYSING: {}", error),
+            }
+            std::process::exit(1);
+        }
+    }
+
+    if let Err(diagnostic) = InitializationAnalysis::new().analyse(&statements) {
+        eprint!("{}", diagnostic.render(&input_string));
+        std::process::exit(1);
+    }
+
+    for warning in Linter::new().lint(&statements) {
+        eprint!("{}", warning.render(&input_string));
+    }
+
+    if verbose {
+        statements.iter()
+            .for_each(|statement| println!("{:?}", statement));
+
+        println!();
+    }
+
+    // C is translated from the statements, it doesnt go through the IR
+    if emit == Some("c") {
+        print!("{}", CGenerator::new().with_bounds_checks(bounds_checks).generate(&statements));
+        return;
+    }
+
+    let mut program = IrGenerator::new().with_bounds_checks(bounds_checks).generate(&statements);
+    let passes = PassManager::new(optimization_level)
+        .with_overrides(flags.iter().find_map(|flag| flag.strip_prefix("--pass=")).unwrap_or_default())
+        .and_then(|passes| passes.with_print_after(flags.iter().find_map(|flag| flag.strip_prefix("--print-after=")).unwrap_or_default()));
+    if let Err(error) = passes.and_then(|passes| passes.run(&mut program)) {
+        eprintln!("ERROR WHILE OPTIMISING: {:#}", error);
+        std::process::exit(1);
+    }
+
+    if run {
+        run_natively(&MachineGenerator::new(&program).generate());
+    }
+
+    match emit {
+        Some("cfg-dot") => print!("{}", ControlFlowGraph::new(&program.instructions).to_dot(&program)),
+        Some("ssa") => {
+            let mut graph = ControlFlowGraph::new(&program.instructions);
+            ssa::into_ssa(&mut program, &mut graph);
+            program.instructions = graph.instructions();
+            print!("{}", program);
+        }
+        Some("bytecode") => std::io::stdout().write_all(&BytecodeGenerator::new(&program).generate().to_bytes()).expect("Cannot write the bytecode!"),
+        Some("wat") => print!("{}", WasmGenerator::new(&program).generate()),
+        Some("wasm") => std::io::stdout().write_all(&encoder::encode(&WasmGenerator::new(&program).generate())).expect("Cannot write the module!"),
+        Some("asm") => print!("{}", MachineGenerator::new(&program).generate()),
+        _ => print!("{}", program),
+    }
+}
+
+/// Runs the machine code in this process and exits with the exit code of the program.
+#[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+fn run_natively(program: &MachineProgram) -> ! {
+    let result = backend::x86_64::jit::Jit::new(program)
+        .and_then(|jit| jit.run(&mut std::io::stdout()));
+    match result {
+        Ok(code) => std::process::exit(code as i32),
+        Err(error) => {
+            eprintln!("ERROR WHILE RUNNING: {:#}", error);
+            std::process::exit(1);
+        }
+    }
+}
 
-    statements.iter()
-        .for_each(|statement| println!("{:?}", statement));
+#[cfg(not(all(target_arch = "x86_64", target_os = "linux")))]
+fn run_natively(_: &MachineProgram) -> ! {
+    eprintln!("--run is only supported on x86-64 Linux");
+    std::process::exit(1);
 }
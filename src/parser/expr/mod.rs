@@ -1,3 +1,4 @@
+use crate::diagnostic::Span;
 use crate::parser::r#type::ValueType;
 use crate::tokenizer::token::{Literal, Operator};
 
@@ -5,14 +6,27 @@ use crate::tokenizer::token::{Literal, Operator};
 pub enum Expression {
     NumberLiteral {
         value: Literal,
+        span: Span,
         internal_type: ValueType,
     },
     IdentifierLiteral {
         value: Literal,
+        span: Span,
         type_: Option<ValueType>,
+        mutable: bool,
     },
     CharLiteral {
         value: Literal,
+        span: Span,
+    },
+    BoolLiteral {
+        value: bool,
+        span: Span,
+    },
+    /// Still contains the escape sequences as written in the source.
+    StringLiteral {
+        value: Literal,
+        span: Span,
     },
     Operation {
         lhs: Box<Expression>,
@@ -20,9 +34,29 @@ pub enum Expression {
         rhs: Box<Expression>,
         type_: Option<ValueType>,
     },
+    Logical {
+        lhs: Box<Expression>,
+        operator: Operator,
+        rhs: Box<Expression>,
+    },
     Array {
         content: Vec<Expression>,
     },
+    /// `value.len`, the number of elements of an array or slice.
+    Length {
+        value: Box<Expression>,
+    },
+    /// `value[start..end]`, a slice of some of the elements, both bounds are optional.
+    Slice {
+        value: Box<Expression>,
+        start: Option<Box<Expression>>,
+        end: Option<Box<Expression>>,
+    },
+    /// `[value; len]`, every element holds the same value.
+    Repeat {
+        value: Box<Expression>,
+        len: usize,
+    },
     Reference {
         reference: Box<Expression>,
     },
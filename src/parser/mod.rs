@@ -1,9 +1,12 @@
+use std::collections::HashMap;
 use std::iter::Peekable;
 use std::vec::IntoIter;
 use anyhow::bail;
+use crate::diagnostic::Span;
 use crate::parser::expr::Expression;
 use crate::parser::r#type::ValueType;
 use crate::parser::stmt::Statement;
+use crate::semantic_analysis::constant::Constant;
 use crate::tokenizer::token::{Keyword, Literal, literal_to_string, LiteralType, Operator, Token, TypeType};
 
 pub mod expr;
@@ -12,12 +15,14 @@ pub mod r#type;
 
 pub struct Parser {
     tokens: Peekable<IntoIter<Token>>,
+    constants: HashMap<Literal, Constant>,
 }
 
 impl Parser {
     pub fn new(tokens: Peekable<IntoIter<Token>>) -> Self {
         Self {
-            tokens
+            tokens,
+            constants: HashMap::new(),
         }
     }
 
@@ -36,11 +41,13 @@ impl Parser {
         statements
     }
     fn parse_statement(&mut self) -> anyhow::Result<Statement> {
-        if let Ok(Token::Keyword { keyword }) = self.get_keyword() {
+        if let Ok(Token::Keyword { keyword, span }) = self.get_keyword() {
             match keyword {
                 Keyword::Let => self.parse_let(),
-                Keyword::Exit => self.parse_exit(),
-                Keyword::Print => self.parse_print(),
+                Keyword::Const => self.parse_const(),
+                Keyword::Exit => self.parse_exit(span),
+                Keyword::Print => self.parse_print(span),
+                Keyword::Mut => bail!("Keyword 'mut' is only allowed after 'let'!"),
             }
         } else {
             self.parse_assign()
@@ -48,36 +55,70 @@ impl Parser {
     }
 
     fn parse_let(&mut self) -> anyhow::Result<Statement> {
-        let identifier = if let Ok(Token::Literal { type_: LiteralType::Identifier, value }) = self.get_literal() {
-            value
+        let mutable = if let Token::Keyword { keyword: Keyword::Mut, .. } = self.peek_token()? {
+            self.consume_token()?;
+            true
+        } else { false };
+        let (identifier, span) = if let Ok(Token::Literal { type_: LiteralType::Identifier, value, span }) = self.get_literal() {
+            (value, span)
         } else {
             bail!("Let statement requires identifier to assign to!")
         };
-        if let Token::Colon = self.consume_token()? {} else { bail!("Let statement requires ':' after assignee identifier!") }
-        let type_ = self.parse_type()?;
-        if let Token::Operation { operator: Operator::Assign } = self.consume_token()? {} else { bail!("Let statement requires '=' after the identifier declaration!") }
-
+        if self.constants.contains_key(&identifier) { bail!("Cannot redeclare constant `{}` as a variable!", literal_to_string(&identifier)) }
+        let type_ = if let Token::Colon = self.peek_token()? {
+            self.consume_token()?;
+            Some(self.parse_type()?)
+        } else { None };
         if let Token::Semicolon = self.peek_token()? {
-            return Ok(Statement::Let { identifier, type_, expression: None });
+            self.consume_token()?;
+            return Ok(Statement::Let { identifier, span, mutable, type_, expression: None });
         }
+        if let Token::Operation { operator: Operator::Assign } = self.consume_token()? {} else { bail!("Let statement requires '=' or ';' after the identifier declaration!") }
+
+        let expression = self.parse_expression(Precedence::Lowest)?;
+        if let Token::Semicolon = self.consume_token()? {} else { bail!("Statement didnt end with ';'!") }
+
+        Ok(Statement::Let { identifier, span, mutable, type_, expression: Some(expression) })
+    }
 
+    fn parse_const(&mut self) -> anyhow::Result<Statement> {
+        let identifier = if let Ok(Token::Literal { type_: LiteralType::Identifier, value, .. }) = self.get_literal() {
+            value
+        } else {
+            bail!("Const statement requires identifier to assign to!")
+        };
+        if self.constants.contains_key(&identifier) { bail!("Constant `{}` is already defined!", literal_to_string(&identifier)) }
+        if let Token::Colon = self.consume_token()? {} else { bail!("Const statement requires ':' after assignee identifier!") }
+        let type_ = self.parse_type()?;
+        if let Token::Operation { operator: Operator::Assign } = self.consume_token()? {} else { bail!("Const statement requires '=' after the identifier declaration!") }
         let expression = self.parse_expression(Precedence::Lowest)?;
         if let Token::Semicolon = self.consume_token()? {} else { bail!("Statement didnt end with ';'!") }
 
-        Ok(Statement::Let { identifier, type_, expression: Some(expression) })
+        let constant = expression.evaluate_constant_as(&self.constants, &type_)?;
+        match (&constant.type_, &type_) {
+            (from, to) if from == to => {}
+            (from, to) if from.is_integer() && to.is_integer() => {
+                if constant.value > to.max_value() { bail!("Value {} of constant `{}` does not fit into `{}`!", constant.value, literal_to_string(&identifier), to) }
+            }
+            (from, to) => bail!("Cannot assign value of type `{}` to constant of type `{}`!", from, to),
+        }
+        self.constants.insert(identifier.clone(), Constant { value: constant.value, type_: type_.clone() });
+
+        Ok(Statement::Const { identifier, type_, expression })
     }
 
     fn parse_type(&mut self) -> anyhow::Result<ValueType> {
         if let Token::Operation { operator: Operator::And } = self.peek_token()? {
             self.consume_token()?;
-            return Ok(
-                ValueType::Pointer {
-                    points_to: Box::new(self.parse_type()?)
-                }
-            );
+            return self.parse_pointer_type();
+        }
+        // `&&T` is lexed as a single `&&` but is a pointer to a pointer
+        if let Token::Operation { operator: Operator::LogicalAnd } = self.peek_token()? {
+            self.consume_token()?;
+            return Ok(ValueType::Pointer { points_to: Box::new(self.parse_pointer_type()?), mutable: false });
         }
 
-        return if let Ok(Token::Type { type_ }) = self.get_type() {
+        if let Ok(Token::Type { type_ }) = self.get_type() {
             Ok(
                 match type_ {
                     TypeType::U64 => ValueType::U64,
@@ -85,54 +126,101 @@ impl Parser {
                     TypeType::U16 => ValueType::U16,
                     TypeType::U8 => ValueType::U8,
                     TypeType::Char => ValueType::Char,
+                    TypeType::Bool => ValueType::Bool,
+                    TypeType::Str => ValueType::Str,
                 }
             )
         } else if let Token::OpenBracket = self.consume_token()? {
             let type_ = self.parse_type()?;
-            if let Token::Comma = self.consume_token()? {} else { bail!("Array type expected comma after internal type descriptor!") }
-            let len = if let Ok(Token::Literal { type_: LiteralType::Number, value }) = self.get_literal() { value } else { bail!("Expected number literal to describe the length of the array!") };
-            if let Token::ClosedBracket = self.consume_token()? {} else { bail!("Array type didnt end with ']'!") }
-            Ok(
-                ValueType::Array {
-                    content_type: Box::new(type_),
-                    len: String::from_utf8_lossy(len.as_slice()).parse::<usize>().unwrap(),
-                }
-            )
-        } else { bail!("Got unexpected token for a type!") };
+            self.parse_array_type(type_)
+        } else { bail!("Got unexpected token for a type!") }
     }
 
-    fn parse_exit(&mut self) -> anyhow::Result<Statement> {
+    /// The type behind a `&` that was already consumed.
+    fn parse_pointer_type(&mut self) -> anyhow::Result<ValueType> {
+        let mutable = if let Token::Keyword { keyword: Keyword::Mut, .. } = self.peek_token()? {
+            self.consume_token()?;
+            true
+        } else { false };
+        // `&[T]` is a slice, `[T]` on its own doesnt have a size and isnt a valid type
+        if let Token::OpenBracket = self.peek_token()? {
+            self.consume_token()?;
+            let content_type = self.parse_type()?;
+            if let Token::ClosedBracket = self.peek_token()? {
+                self.consume_token()?;
+                return Ok(ValueType::Slice { content_type: Box::new(content_type), mutable });
+            }
+            return Ok(ValueType::Pointer { points_to: Box::new(self.parse_array_type(content_type)?), mutable });
+        }
+        Ok(
+            ValueType::Pointer {
+                points_to: Box::new(self.parse_type()?),
+                mutable,
+            }
+        )
+    }
+
+    /// Parses the rest of an array type after its element type, `, len]`.
+    fn parse_array_type(&mut self, content_type: ValueType) -> anyhow::Result<ValueType> {
+        if let Token::Comma = self.consume_token()? {} else { bail!("Array type expected comma after internal type descriptor!") }
+        let len = self.parse_expression(Precedence::Lowest)?.evaluate_constant(&self.constants)?;
+        if !len.type_.is_integer() { bail!("Expected an integer constant to describe the length of the array but got `{}`!", len.type_) }
+        if let Token::ClosedBracket = self.consume_token()? {} else { bail!("Array type didnt end with ']'!") }
+        Ok(
+            ValueType::Array {
+                content_type: Box::new(content_type),
+                len: len.value as usize,
+            }
+        )
+    }
+
+    fn parse_exit(&mut self, span: Span) -> anyhow::Result<Statement> {
         if let Token::OpenParent = self.consume_token()? {} else { bail!("Expected '(' after function identifier!") }
         let expression = self.parse_expression(Precedence::Lowest).unwrap();
         if let Token::ClosedParent = self.consume_token()? {} else { bail!("Expected ')' after function parameters!") }
 
-        Ok(Statement::Exit { expression })
+        Ok(Statement::Exit { expression, span })
     }
 
-    fn parse_print(&mut self) -> anyhow::Result<Statement> {
+    fn parse_print(&mut self, span: Span) -> anyhow::Result<Statement> {
         if let Token::OpenParent = self.consume_token()? {} else { bail!("Expected '(' after function identifier!") }
         let expression = self.parse_expression(Precedence::Lowest)?;
         if let Token::ClosedParent = self.consume_token()? {} else { bail!("Expected ')' after function parameters!") }
 
-        Ok(Statement::Print { expression })
+        Ok(Statement::Print { expression, span })
     }
 
     fn parse_assign(&mut self) -> anyhow::Result<Statement> {
         let assignee = self.parse_expression(Precedence::Lowest)?;
+        if let Token::Semicolon = self.peek_token()? {
+            self.consume_token()?;
+            return Ok(Statement::Expression { expression: assignee });
+        }
 
-        if let Token::Operation { operator: Operator::Assign } = self.consume_token()? {} else { bail!("Expected '=' after the identifier in let statement!") }
+        let operator = match self.consume_token()? {
+            Token::Operation { operator: Operator::Assign } => None,
+            Token::Operation { operator: Operator::PlusAssign } => Some(Operator::Plus),
+            Token::Operation { operator: Operator::MinusAssign } => Some(Operator::Minus),
+            Token::Operation { operator: Operator::TimesAssign } => Some(Operator::Times),
+            Token::Operation { operator: Operator::DivideAssign } => Some(Operator::Divide),
+            _ => bail!("Expected '=' after the identifier in let statement!")
+        };
         let expression = self.parse_expression(Precedence::Lowest)?;
         if let Token::Semicolon = self.consume_token()? {} else { bail!("Statement didnt end with ';'!") }
 
-        Ok(Statement::Assign { assignee, expression })
+        Ok(Statement::Assign { assignee, operator, expression })
     }
 
     fn parse_expression(&mut self, precedence: Precedence) -> anyhow::Result<Expression> {
         let mut left_expression = match self.consume_token()? {
-            Token::Literal { type_: LiteralType::Identifier, value } => Expression::IdentifierLiteral { value, type_: None },
-            Token::Literal { type_: LiteralType::Number, value } => Self::parse_number_literal(value)?,
-            Token::Literal { type_: LiteralType::Char, value } => Expression::CharLiteral { value },
-            Token::Literal { type_: LiteralType::String, value } => Expression::Array { content: Self::string_to_char_array(value) },
+            Token::Literal { type_: LiteralType::Identifier, value, span } => match self.constants.get(&value) {
+                Some(constant) => constant.to_expression(span),
+                None => Expression::IdentifierLiteral { value, span, type_: None, mutable: false },
+            },
+            Token::Literal { type_: LiteralType::Number, value, span } => Self::parse_number_literal(value, span)?,
+            Token::Literal { type_: LiteralType::Char, value, span } => Expression::CharLiteral { value, span },
+            Token::Literal { type_: LiteralType::Boolean, value, span } => Expression::BoolLiteral { value: value.as_slice() == b"true", span },
+            Token::Literal { type_: LiteralType::String, value, span } => Expression::StringLiteral { value, span },
             Token::Operation { operator } => self.parse_prefix_expression(operator)?,
             Token::OpenParent => self.parse_grouped()?,
             Token::OpenBracket => self.parse_array()?,
@@ -147,9 +235,13 @@ impl Parser {
                     self.parse_cast(left_expression)?
                 } else if let Ok(Token::Operation { operator }) = operation {
                     self.parse_infix_expression(left_expression, operator)?
-                } else if let Token::OpenBracket = self.consume_token()? {
-                    self.parse_access(left_expression)?
-                } else { bail!("Invalid operator for infix operation found!") };
+                } else {
+                    match self.consume_token()? {
+                        Token::OpenBracket => self.parse_access(left_expression)?,
+                        Token::Dot => self.parse_member(left_expression)?,
+                        _ => bail!("Invalid operator for infix operation found!"),
+                    }
+                };
 
             left_expression = infix;
         }
@@ -166,7 +258,7 @@ impl Parser {
         })
     }
 
-    fn parse_number_literal(value: Literal) -> anyhow::Result<Expression> {
+    fn parse_number_literal(value: Literal, span: Span) -> anyhow::Result<Expression> {
         let string_representation = literal_to_string(&value);
         let type_ = if let Ok(huge_int) = string_representation.parse::<u128>() {
             if huge_int <= u8::MAX as u128 { ValueType::U8 }
@@ -177,21 +269,7 @@ impl Parser {
         } else {
             bail!("To big integer literal found!");
         };
-        Ok(Expression::NumberLiteral { value, internal_type: type_ })
-    }
-
-    fn string_to_char_array(string: Literal) -> Vec<Expression> {
-        let mut chars: Vec<Expression> = vec![];
-        let mut iter = string.into_iter();
-        while iter.len() != 0 {
-            let c = iter.next().unwrap();
-            if c == b'\\' {
-                chars.push(Expression::CharLiteral { value: vec![b'\\', iter.next().unwrap()] });
-            } else {
-                chars.push(Expression::CharLiteral { value: vec![c] });
-            }
-        }
-        chars
+        Ok(Expression::NumberLiteral { value, span, internal_type: type_ })
     }
 
     fn parse_grouped(&mut self) -> anyhow::Result<Expression> {
@@ -205,10 +283,30 @@ impl Parser {
             Operator::Plus |
             Operator::Minus |
             Operator::Times |
-            Operator::Divide => {}
+            Operator::Divide |
+            Operator::Equal |
+            Operator::NotEqual |
+            Operator::Less |
+            Operator::Greater |
+            Operator::LessEqual |
+            Operator::GreaterEqual |
+            Operator::LogicalAnd |
+            Operator::LogicalOr => {}
             _ => bail!("Found invalid infix operator!")
         }
         let right = self.parse_expression(operator.get_precedence()?)?;
+
+        // Logical operators only evaluate their right operand if needed, so they cant be a plain operation
+        if let Operator::LogicalAnd | Operator::LogicalOr = operator {
+            return Ok(
+                Expression::Logical {
+                    lhs: Box::new(left),
+                    operator,
+                    rhs: Box::new(right),
+                }
+            );
+        }
+
         Ok(
             Expression::Operation {
                 lhs: Box::new(left),
@@ -226,6 +324,8 @@ impl Parser {
         let expression = match operator {
             Operator::Times => Expression::Deref { value: Box::new(right) },
             Operator::And => Expression::Reference { reference: Box::new(right) },
+            // `&&x` is lexed as a single `&&` but references a reference
+            Operator::LogicalAnd => Expression::Reference { reference: Box::new(Expression::Reference { reference: Box::new(right) }) },
             _ => bail!("Found invalid prefix operator!")
         };
 
@@ -233,17 +333,20 @@ impl Parser {
     }
 
     fn parse_array(&mut self) -> anyhow::Result<Expression> {
-        let mut content: Vec<Expression> = vec![];
+        let first = self.parse_expression(Precedence::Lowest)?;
 
-        loop {
-            let expression = self.parse_expression(Precedence::Lowest)?;
-            content.push(expression);
+        if let Token::Semicolon = self.peek_token()? {
+            self.consume_token()?;
+            let len = self.parse_expression(Precedence::Lowest)?.evaluate_constant(&self.constants)?;
+            if !len.type_.is_integer() { bail!("Expected an integer constant to describe the length of the array but got `{}`!", len.type_) }
+            if let Token::ClosedBracket = self.consume_token()? {} else { bail!("Array expression didnt end with ']'!") }
+            return Ok(Expression::Repeat { value: Box::new(first), len: len.value as usize });
+        }
 
-            if let Token::Comma = self.peek_token()? {
-                self.consume_token()?;
-            } else {
-                break;
-            }
+        let mut content: Vec<Expression> = vec![first];
+        while let Token::Comma = self.peek_token()? {
+            self.consume_token()?;
+            content.push(self.parse_expression(Precedence::Lowest)?);
         }
         if let Token::ClosedBracket = self.consume_token()? {} else { bail!("Array expression didnt end with ']'!") }
 
@@ -251,10 +354,31 @@ impl Parser {
     }
 
     fn parse_access(&mut self, left: Expression) -> anyhow::Result<Expression> {
-        let expression = self.parse_expression(Precedence::Lowest)?;
+        let start = match self.peek_token()? {
+            Token::DotDot => None,
+            _ => Some(Box::new(self.parse_expression(Precedence::Lowest)?)),
+        };
+        if let Token::DotDot = self.peek_token()? {
+            self.consume_token()?;
+            let end = match self.peek_token()? {
+                Token::ClosedBracket => None,
+                _ => Some(Box::new(self.parse_expression(Precedence::Lowest)?)),
+            };
+            if let Token::ClosedBracket = self.consume_token()? {} else { bail!("Slice expression didnt end with ']'!") }
+            return Ok(Expression::Slice { value: Box::new(left), start, end });
+        }
+        let index = start.expect("Only slices may leave out the index!");
 
         if let Token::ClosedBracket = self.consume_token()? {} else { bail!("Access expression didnt end with ']'!") }
-        Ok(Expression::Access { value: Box::new(left), index: Box::new(expression) })
+        Ok(Expression::Access { value: Box::new(left), index })
+    }
+
+    fn parse_member(&mut self, left: Expression) -> anyhow::Result<Expression> {
+        match self.consume_token()? {
+            Token::Literal { type_: LiteralType::Identifier, value, .. } if value.as_slice() == b"len" => Ok(Expression::Length { value: Box::new(left) }),
+            Token::Literal { type_: LiteralType::Identifier, value, .. } => bail!("Unknown member `{}`, only `len` is available!", literal_to_string(&value)),
+            _ => bail!("Expected a member name after '.'!"),
+        }
     }
 
     fn get_keyword(&mut self) -> anyhow::Result<Token> {
@@ -308,10 +432,13 @@ impl Parser {
 #[derive(Ord, PartialOrd, Eq, PartialEq)]
 enum Precedence {
     Lowest = 0,
-    Sum = 1,
-    Product = 2,
-    Prefix = 3,
-    Postfix = 4,
+    LogicalOr = 1,
+    LogicalAnd = 2,
+    Comparison = 3,
+    Sum = 4,
+    Product = 5,
+    Prefix = 6,
+    Postfix = 7,
 }
 
 impl Token {
@@ -320,7 +447,8 @@ impl Token {
             Token::Operation {
                 operator
             } => operator.get_precedence(),
-            Token::OpenBracket => Ok(Precedence::Postfix),
+            Token::OpenBracket |
+            Token::Dot => Ok(Precedence::Postfix),
             _ => bail!("Tried to get precedence of token that doesnt have a precedence!")
         }
     }
@@ -335,7 +463,49 @@ impl Operator {
             Operator::Times |
             Operator::Divide => Ok(Precedence::Product),
             Operator::And => Ok(Precedence::Prefix),
+            Operator::Equal |
+            Operator::NotEqual |
+            Operator::Less |
+            Operator::Greater |
+            Operator::LessEqual |
+            Operator::GreaterEqual => Ok(Precedence::Comparison),
+            Operator::LogicalAnd => Ok(Precedence::LogicalAnd),
+            Operator::LogicalOr => Ok(Precedence::LogicalOr),
             _ => bail!("Tried to get precedence of operation that doesnt have a precedence!")
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tokenizer::Tokenizer;
+
+    fn parser(source: &str) -> Parser {
+        Parser::new(Tokenizer::new(source.chars().peekable()).tokenize().into_iter().peekable())
+    }
+
+    #[test]
+    fn double_ampersands_nest_two_references() {
+        let type_ = parser("&&u8").parse_type().unwrap();
+        assert!(matches!(type_, ValueType::Pointer { points_to, .. } if matches!(*points_to, ValueType::Pointer { .. })));
+
+        let expression = parser("&&x").parse_expression(Precedence::Lowest).unwrap();
+        assert!(matches!(expression, Expression::Reference { reference } if matches!(*reference, Expression::Reference { .. })));
+    }
+
+    #[test]
+    fn constants_are_evaluated_in_their_declared_type() {
+        let mut parser = parser("const N: u16 = 200 + 100; const M: u32 = N * 1000;");
+        parser.parse_statement().unwrap();
+        parser.parse_statement().unwrap();
+        assert_eq!(parser.constants[&b"N".to_vec()], Constant { value: 300, type_: ValueType::U16 });
+        assert_eq!(parser.constants[&b"M".to_vec()], Constant { value: 300000, type_: ValueType::U32 });
+    }
+
+    #[test]
+    fn constants_overflowing_their_declared_type_are_rejected() {
+        assert!(parser("const N: u8 = 200 + 100;").parse_statement().is_err());
+        assert!(parser("const N: u16 = 300 * 300;").parse_statement().is_err());
+    }
+}
@@ -1,76 +1,160 @@
+use anyhow::bail;
+use crate::diagnostic::Span;
 use crate::parser::expr::Expression;
 use crate::parser::r#type::ValueType;
-use crate::semantic_analysis::symbol_table::SymbolTable;
-use crate::tokenizer::token::Literal;
+use crate::semantic_analysis::symbol_table::{Symbol, SymbolTable};
+use crate::tokenizer::token::{Literal, literal_to_string, Operator};
 
 #[derive(Debug)]
 pub enum Statement {
     Let {
         identifier: Literal,
-        type_: ValueType,
+        span: Span,
+        mutable: bool,
+        type_: Option<ValueType>,
         expression: Option<Expression>,
     },
+    Const {
+        identifier: Literal,
+        type_: ValueType,
+        expression: Expression,
+    },
     Assign {
         assignee: Expression,
+        operator: Option<Operator>,
         expression: Expression,
     },
     Exit {
         expression: Expression,
+        span: Span,
     },
     Print {
         expression: Expression,
+        span: Span,
+    },
+    Expression {
+        expression: Expression,
     },
 }
 
 impl Statement {
-    pub fn resolve(&mut self, symbol_table: &mut SymbolTable) {
-        println!("{:?}", self);
+    pub fn resolve(&mut self, symbol_table: &mut SymbolTable) -> anyhow::Result<()> {
         match self {
-            Statement::Let { type_, expression, identifier } => {
+            Statement::Let { type_, expression, identifier, span, mutable } => {
                 if let Some(expression) = expression {
-                    expression.resolve(symbol_table);
+                    match type_ {
+                        Some(type_) => expression.resolve_against(type_, symbol_table)?,
+                        None => {
+                            // The width of a literal says nothing about the values the variable holds later on
+                            expression.widen_integer_literals();
+                            expression.resolve(symbol_table)?
+                        }
+                    }
                     let expression_type = expression.get_type();
-                    if type_ != &expression_type {
-                        let _ = expression_type.get_casts().get(type_).unwrap();
-                        let _ = std::mem::replace(expression, Expression::Cast { value: Box::new(expression.clone()), to: type_.clone() });
-                        expression.resolve(symbol_table);
+                    match type_ {
+                        // Without an annotation the variable simply takes the type of its initializer
+                        None => { type_.replace(expression_type); }
+                        Some(type_) if type_ != &expression_type => {
+                            if !expression_type.get_casts().contains_key(type_) { bail!("Cannot assign value of type `{}` to variable of type `{}`!", expression_type, type_) }
+                            let _ = std::mem::replace(expression, Expression::Cast { value: Box::new(expression.clone()), to: type_.clone() });
+                            expression.resolve(symbol_table)?;
+                        }
+                        Some(_) => {}
                     }
                 }
-                symbol_table.register(identifier.clone(), type_.clone());
+                let type_ = match type_ {
+                    Some(type_) => type_.clone(),
+                    None => bail!("Type of `{}` cannot be inferred without an initializer, please annotate it!", literal_to_string(identifier)),
+                };
+                symbol_table.register(identifier.clone(), Symbol { type_, mutable: *mutable, initialized: expression.is_some(), span: *span })?;
             }
-            Statement::Assign { assignee, expression } => {
-                assignee.resolve(symbol_table);
+            Statement::Assign { assignee, operator, expression } => {
+                assignee.resolve(symbol_table)?;
                 let assignee_type = match assignee.get_type() {
-                    ValueType::Pointer { points_to } => *points_to,
+                    ValueType::Pointer { points_to, .. } => *points_to,
                     _ => {
                         match assignee {
                             Expression::IdentifierLiteral { .. } => {
                                 let stored_type = assignee.get_type();
                                 let _ = std::mem::replace(assignee, Expression::Reference { reference: Box::new(assignee.clone()) });
-                                assignee.resolve(symbol_table);
+                                assignee.resolve(symbol_table)?;
                                 stored_type
                             }
                             Expression::Access { .. } => {
                                 let stored_type = assignee.get_type();
                                 let _ = std::mem::replace(assignee, Expression::Reference { reference: Box::new(assignee.clone()) });
-                                assignee.resolve(symbol_table);
+                                assignee.resolve(symbol_table)?;
                                 stored_type
 
                             }
-                            _ => unreachable!(),
+                            _ => bail!("Can only assign to variables, array elements and pointers!"),
                         }
                     }
                 };
-                expression.resolve(symbol_table);
+                // Immutable variables declared without a value may still be initialized once, the initialization analysis makes sure of that
+                let deferred = assignee.assignee_root()
+                    .and_then(|root| symbol_table.get(root))
+                    .is_some_and(|symbol| !symbol.initialized);
+                if let (ValueType::Pointer { mutable: false, .. }, false) = (assignee.get_type(), deferred) {
+                    match assignee {
+                        Expression::Reference { reference: place } => bail!(place.explain_immutability()),
+                        Expression::Access { .. } => bail!(assignee.explain_immutability()),
+                        _ => bail!("Cannot assign through `{}` because it is not a mutable reference!", assignee.get_type()),
+                    }
+                }
+                if let Some(operator) = operator {
+                    // The assignee got rewritten into a pointer above, so its current value is right behind it
+                    let current = Expression::Deref { value: Box::new(assignee.clone()) };
+                    let _ = std::mem::replace(expression, Expression::Operation { lhs: Box::new(current), operator: operator.clone(), rhs: Box::new(expression.clone()), type_: None });
+                }
+                expression.resolve_against(&assignee_type, symbol_table)?;
                 let expression_type = expression.get_type();
                 if assignee_type != expression_type {
-                    let _ = expression_type.get_casts().get(&assignee_type).unwrap();
+                    if !expression_type.get_casts().contains_key(&assignee_type) { bail!("Cannot assign value of type `{}` to `{}`!", expression_type, assignee_type) }
                     let _ = std::mem::replace(expression, Expression::Cast { value: Box::new(expression.clone()), to: assignee_type });
-                    expression.resolve(symbol_table);
+                    expression.resolve(symbol_table)?;
+                }
+            }
+            // Constants are evaluated and inlined while parsing
+            Statement::Const { .. } => {}
+            Statement::Exit { expression, .. } => {
+                expression.resolve(symbol_table)?;
+                if expression.get_type().is_pointer() {
+                    let _ = std::mem::replace(expression, Expression::Deref { value: Box::new(expression.clone()) });
+                    expression.resolve(symbol_table)?;
+                }
+                match expression.get_type() {
+                    ValueType::U64 | ValueType::U32 | ValueType::U16 | ValueType::U8 => {}
+                    type_ => bail!("Exit code has to be an unsigned integer but got `{}`!", type_),
+                }
+            }
+            Statement::Print { expression, .. } => {
+                expression.resolve(symbol_table)?;
+                if expression.get_type().is_pointer() {
+                    let _ = std::mem::replace(expression, Expression::Deref { value: Box::new(expression.clone()) });
+                    expression.resolve(symbol_table)?;
+                }
+                match expression.get_type() {
+                    ValueType::Array { content_type, .. } |
+                    ValueType::Slice { content_type, .. } if *content_type != ValueType::Char => bail!("Can only print arrays and slices of `char` but got `{}`!", expression.get_type()),
+                    ValueType::Pointer { .. } => bail!("Cannot print value of type `{}`!", expression.get_type()),
+                    _ => {}
                 }
             }
-            Statement::Exit { .. } => {}
-            Statement::Print { .. } => {}
+            Statement::Expression { expression } => expression.resolve(symbol_table)?,
+        }
+        Ok(())
+    }
+
+    /// Where the statement starts, as far as its tokens tell.
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            Statement::Let { span, .. } |
+            Statement::Exit { span, .. } |
+            Statement::Print { span, .. } => Some(*span),
+            Statement::Const { .. } => None,
+            Statement::Assign { assignee, .. } => assignee.span(),
+            Statement::Expression { expression } => expression.span(),
         }
     }
 }
\ No newline at end of file
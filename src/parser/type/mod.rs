@@ -22,8 +22,13 @@ pub enum ValueType {
     U16,
     U8,
     Char,
-    Pointer { points_to: Box<Self> },
+    Bool,
+    Pointer { points_to: Box<Self>, mutable: bool },
     Array { content_type: Box<Self>, len: usize },
+    /// Pointer to the first element together with the number of elements.
+    Slice { content_type: Box<Self>, mutable: bool },
+    /// Pointer to read only UTF-8 bytes together with their number.
+    Str,
 }
 
 #[derive(Eq, PartialEq)]
@@ -66,24 +71,93 @@ impl ValueType {
                 ValueType::U8 => CastVariant::Explicit,
                 ValueType::Char => CastVariant::Explicit,
             ),
+            // Not every u16 or u32 is a unicode scalar value, those become U+FFFD when cast to char
             ValueType::Char => collection!(
                 ValueType::Char => CastVariant::Explicit,
 
-                ValueType::U8 => CastVariant::Explicit,
-                ValueType::U16 => CastVariant::Explicit,
+                ValueType::U8 => CastVariant::Implicit,
+                ValueType::U16 => CastVariant::Implicit,
                 ValueType::U32 => CastVariant::Explicit,
                 ValueType::U64 => CastVariant::Explicit,
             ),
+            ValueType::Bool => collection!(
+                ValueType::Bool => CastVariant::Explicit,
+            ),
+            ValueType::Str => collection!(
+                ValueType::Str => CastVariant::Explicit,
+            ),
+            // Pointers to arrays know the length, so they can be turned into slices
+            ValueType::Pointer { points_to, mutable } if matches!(points_to.as_ref(), ValueType::Array { .. }) => {
+                let content_type = match points_to.as_ref() {
+                    ValueType::Array { content_type, .. } => content_type.clone(),
+                    _ => unreachable!(),
+                };
+                let mut casts: HashMap<ValueType, CastVariant> = collection!(
+                    self.clone() => CastVariant::Explicit,
+                    ValueType::Pointer { points_to: points_to.clone(), mutable: false } => CastVariant::Explicit,
+                    ValueType::Slice { content_type: content_type.clone(), mutable: false } => CastVariant::Explicit,
+                );
+                if *mutable { casts.insert(ValueType::Slice { content_type, mutable: true }, CastVariant::Explicit); }
+                casts
+            }
+            // Mutable pointers can always be used where only reading is required
+            ValueType::Pointer { points_to, mutable: true } => collection!(
+                self.clone() => CastVariant::Explicit,
+                ValueType::Pointer { points_to: points_to.clone(), mutable: false } => CastVariant::Explicit,
+            ),
             ValueType::Pointer { .. } => collection!(
                 self.clone() => CastVariant::Explicit,
             ),
             ValueType::Array { .. } => collection!(
                 self.clone() => CastVariant::Explicit,
             ),
+            ValueType::Slice { content_type, .. } => collection!(
+                self.clone() => CastVariant::Explicit,
+                ValueType::Slice { content_type: content_type.clone(), mutable: false } => CastVariant::Explicit,
+            ),
         }
     }
 }
 
+impl ValueType {
+    pub fn size_of(&self) -> u64 {
+        match self {
+            ValueType::U64 => 8,
+            ValueType::U32 => 4,
+            ValueType::U16 => 2,
+            ValueType::U8 => 1,
+            ValueType::Char => 4,
+            ValueType::Bool => 1,
+            ValueType::Pointer { .. } => 8,
+            ValueType::Array { content_type, len } => content_type.size_of() * *len as u64,
+            ValueType::Slice { .. } |
+            ValueType::Str => 16,
+        }
+    }
+
+    /// Truncates the value to the width of the type, the same way a cast at runtime would.
+    pub fn wrap(&self, value: u64) -> u64 {
+        match self.size_of() {
+            8 => value,
+            bytes => value & ((1 << (bytes * 8)) - 1),
+        }
+    }
+
+    /// The value after casting it to this type, chars that arent unicode scalar values are replaced.
+    pub fn cast_value(&self, value: u64) -> u64 {
+        match self {
+            ValueType::Char => u32::try_from(value).ok()
+                .and_then(char::from_u32)
+                .map_or(char::REPLACEMENT_CHARACTER as u64, |char| char as u64),
+            _ => self.wrap(value),
+        }
+    }
+
+    pub fn max_value(&self) -> u64 {
+        self.wrap(u64::MAX)
+    }
+}
+
 pub struct OperationResult {
     pub input: ValueType,
     pub output: ValueType,
@@ -97,6 +171,12 @@ impl ValueType {
                 Operator::Minus => collection!(ValueType::U64 => ValueType::U64),
                 Operator::Times => collection!(ValueType::U64 => ValueType::U64),
                 Operator::Divide => collection!(ValueType::U64 => ValueType::U64),
+                Operator::Equal |
+                Operator::NotEqual |
+                Operator::Less |
+                Operator::Greater |
+                Operator::LessEqual |
+                Operator::GreaterEqual => collection!(ValueType::U64 => ValueType::Bool),
                 _ => collection!()
             },
             ValueType::U32 => match operator {
@@ -104,6 +184,12 @@ impl ValueType {
                 Operator::Minus => collection!(ValueType::U32 => ValueType::U32),
                 Operator::Times => collection!(ValueType::U32 => ValueType::U32),
                 Operator::Divide => collection!(ValueType::U32 => ValueType::U32),
+                Operator::Equal |
+                Operator::NotEqual |
+                Operator::Less |
+                Operator::Greater |
+                Operator::LessEqual |
+                Operator::GreaterEqual => collection!(ValueType::U32 => ValueType::Bool),
                 _ => collection!()
             },
             ValueType::U16 => match operator {
@@ -111,6 +197,12 @@ impl ValueType {
                 Operator::Minus => collection!(ValueType::U16 => ValueType::U16),
                 Operator::Times => collection!(ValueType::U16 => ValueType::U16),
                 Operator::Divide => collection!(ValueType::U16 => ValueType::U16),
+                Operator::Equal |
+                Operator::NotEqual |
+                Operator::Less |
+                Operator::Greater |
+                Operator::LessEqual |
+                Operator::GreaterEqual => collection!(ValueType::U16 => ValueType::Bool),
                 _ => collection!()
             },
             ValueType::U8 => match operator {
@@ -118,11 +210,36 @@ impl ValueType {
                 Operator::Minus => collection!(ValueType::U8 => ValueType::U8),
                 Operator::Times => collection!(ValueType::U8 => ValueType::U8),
                 Operator::Divide => collection!(ValueType::U8 => ValueType::U8),
+                Operator::Equal |
+                Operator::NotEqual |
+                Operator::Less |
+                Operator::Greater |
+                Operator::LessEqual |
+                Operator::GreaterEqual => collection!(ValueType::U8 => ValueType::Bool),
+                _ => collection!()
+            },
+            ValueType::Char => match operator {
+                Operator::Equal |
+                Operator::NotEqual |
+                Operator::Less |
+                Operator::Greater |
+                Operator::LessEqual |
+                Operator::GreaterEqual => collection!(ValueType::Char => ValueType::Bool),
+                _ => collection!()
+            },
+            ValueType::Bool => match operator {
+                Operator::Equal |
+                Operator::NotEqual => collection!(ValueType::Bool => ValueType::Bool),
                 _ => collection!()
             },
-            ValueType::Char => collection!(),
             ValueType::Pointer { .. } => collection!(),
             ValueType::Array { .. } => collection!(),
+            ValueType::Slice { .. } => collection!(),
+            ValueType::Str => match operator {
+                Operator::Equal |
+                Operator::NotEqual => collection!(ValueType::Str => ValueType::Bool),
+                _ => collection!()
+            },
         }
     }
 }
@@ -135,8 +252,11 @@ impl Display for ValueType {
             ValueType::U16 => f.write_str("u16"),
             ValueType::U8 => f.write_str("u8"),
             ValueType::Char => f.write_str("char"),
-            ValueType::Pointer { points_to } => {
+            ValueType::Bool => f.write_str("bool"),
+            ValueType::Str => f.write_str("str"),
+            ValueType::Pointer { points_to, mutable } => {
                 f.write_char('&').unwrap();
+                if *mutable { f.write_str("mut ").unwrap(); }
                 f.write_str(&points_to.to_string())
             }
             ValueType::Array { content_type, len } => {
@@ -146,6 +266,13 @@ impl Display for ValueType {
                 f.write_str(&len.to_string()).unwrap();
                 f.write_char(']')
             }
+            ValueType::Slice { content_type, mutable } => {
+                f.write_char('&').unwrap();
+                if *mutable { f.write_str("mut ").unwrap(); }
+                f.write_char('[').unwrap();
+                f.write_str(&content_type.to_string()).unwrap();
+                f.write_char(']')
+            }
         }
     }
 }
\ No newline at end of file
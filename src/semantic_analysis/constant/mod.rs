@@ -0,0 +1,124 @@
+use std::collections::HashMap;
+use anyhow::{anyhow, bail};
+use crate::diagnostic::Span;
+use crate::parser::expr::Expression;
+use crate::parser::r#type::ValueType;
+use crate::tokenizer::token::{Literal, literal_to_char, literal_to_string, Operator};
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Constant {
+    pub value: u64,
+    pub type_: ValueType,
+}
+
+impl Constant {
+    /// Turns the constant back into an expression of the same type, located where the constant got used.
+    pub fn to_expression(&self, span: Span) -> Expression {
+        match self.type_ {
+            ValueType::Bool => Expression::BoolLiteral { value: self.value != 0, span },
+            // Chars go through their scalar value, writing them back as source text would need escaping
+            ValueType::Char => Expression::Cast {
+                value: Box::new(Expression::NumberLiteral { value: self.value.to_string().into_bytes(), span, internal_type: ValueType::U32 }),
+                to: ValueType::Char,
+            },
+            _ => Expression::NumberLiteral { value: self.value.to_string().into_bytes(), span, internal_type: self.type_.clone() },
+        }
+    }
+}
+
+impl Expression {
+    pub fn evaluate_constant(&self, constants: &HashMap<Literal, Constant>) -> anyhow::Result<Constant> {
+        self.evaluate(constants, None)
+    }
+
+    /// Evaluates with the integer literals of the arithmetic as wide as the type the result is assigned to,
+    /// so that `200 + 100` fits into a `u16` even though both literals fit into a `u8`.
+    pub fn evaluate_constant_as(&self, constants: &HashMap<Literal, Constant>, type_: &ValueType) -> anyhow::Result<Constant> {
+        self.evaluate(constants, Some(type_).filter(|type_| type_.is_integer()))
+    }
+
+    fn evaluate(&self, constants: &HashMap<Literal, Constant>, literal_type: Option<&ValueType>) -> anyhow::Result<Constant> {
+        Ok(
+            match self {
+                Expression::NumberLiteral { value, internal_type, .. } => Constant {
+                    value: literal_to_string(value).parse()?,
+                    type_: match literal_type {
+                        Some(type_) if type_.size_of() > internal_type.size_of() => type_.clone(),
+                        _ => internal_type.clone(),
+                    },
+                },
+                Expression::CharLiteral { value, .. } => Constant { value: literal_to_char(value)?, type_: ValueType::Char },
+                Expression::BoolLiteral { value, .. } => Constant { value: *value as u64, type_: ValueType::Bool },
+                Expression::IdentifierLiteral { value, .. } => match constants.get(value) {
+                    Some(constant) => constant.clone(),
+                    None => bail!("`{}` is not a constant and cannot be used at compile time!", literal_to_string(value)),
+                },
+                Expression::Operation { lhs, operator, rhs, .. } => {
+                    let lhs = lhs.evaluate(constants, literal_type)?;
+                    let rhs = rhs.evaluate(constants, literal_type)?;
+
+                    let operand_type = if lhs.type_ == rhs.type_ {
+                        lhs.type_.clone()
+                    } else if lhs.type_.is_integer() && rhs.type_.is_integer() {
+                        if lhs.type_.size_of() > rhs.type_.size_of() { lhs.type_.clone() } else { rhs.type_.clone() }
+                    } else { bail!("Operation `{}` is not defined for `{}` and `{}`!", operator, lhs.type_, rhs.type_) };
+                    let type_ = match operand_type.get_operation_results(operator).get(&operand_type) {
+                        Some(type_) => type_.clone(),
+                        None => bail!("Operation `{}` is not defined for `{}` and `{}`!", operator, lhs.type_, rhs.type_),
+                    };
+
+                    let value = match operator {
+                        Operator::Plus => lhs.value.checked_add(rhs.value),
+                        Operator::Minus => lhs.value.checked_sub(rhs.value),
+                        Operator::Times => lhs.value.checked_mul(rhs.value),
+                        Operator::Divide => {
+                            if rhs.value == 0 { bail!("Constant expression divides `{}` by zero!", lhs.value) }
+                            lhs.value.checked_div(rhs.value)
+                        }
+                        Operator::Equal => Some((lhs.value == rhs.value) as u64),
+                        Operator::NotEqual => Some((lhs.value != rhs.value) as u64),
+                        Operator::Less => Some((lhs.value < rhs.value) as u64),
+                        Operator::Greater => Some((lhs.value > rhs.value) as u64),
+                        Operator::LessEqual => Some((lhs.value <= rhs.value) as u64),
+                        Operator::GreaterEqual => Some((lhs.value >= rhs.value) as u64),
+                        _ => unreachable!(),
+                    };
+
+                    Constant {
+                        value: value
+                            .filter(|value| *value <= type_.max_value())
+                            .ok_or_else(|| anyhow!("Constant expression `{} {} {}` overflows `{}`!", lhs.value, operator, rhs.value, type_))?,
+                        type_,
+                    }
+                }
+                Expression::Logical { lhs, operator, rhs } => {
+                    let lhs = lhs.evaluate_constant(constants)?;
+                    let rhs = rhs.evaluate_constant(constants)?;
+                    if lhs.type_ != ValueType::Bool || rhs.type_ != ValueType::Bool {
+                        bail!("Operator `{}` expects `bool` operands but got `{}` and `{}`!", operator, lhs.type_, rhs.type_)
+                    }
+
+                    let value = match operator {
+                        Operator::LogicalAnd => lhs.value != 0 && rhs.value != 0,
+                        Operator::LogicalOr => lhs.value != 0 || rhs.value != 0,
+                        _ => unreachable!(),
+                    };
+                    Constant { value: value as u64, type_: ValueType::Bool }
+                }
+                Expression::Cast { value, to } => {
+                    let value = value.evaluate_constant(constants)?;
+                    if !value.type_.get_casts().contains_key(to) { bail!("Cannot cast `{}` to `{}`!", value.type_, to) }
+                    Constant { value: to.cast_value(value.value), type_: to.clone() }
+                }
+                Expression::StringLiteral { .. } |
+                Expression::Array { .. } |
+                Expression::Repeat { .. } |
+                Expression::Length { .. } |
+                Expression::Slice { .. } |
+                Expression::Reference { .. } |
+                Expression::Deref { .. } |
+                Expression::Access { .. } => bail!("Expression cannot be evaluated at compile time!"),
+            }
+        )
+    }
+}
@@ -0,0 +1,223 @@
+use std::collections::HashMap;
+use std::ops::Range;
+use crate::diagnostic::{Diagnostic, Span};
+use crate::parser::expr::Expression;
+use crate::parser::r#type::ValueType;
+use crate::parser::stmt::Statement;
+use crate::tokenizer::token::{Literal, literal_to_string};
+
+/// Makes sure no variable is read before it is definitely written. Arrays are tracked per
+/// scalar element, so they may be initialized piecemeal through constant indices.
+#[derive(Default)]
+pub struct InitializationAnalysis {
+    variables: HashMap<Literal, Variable>,
+}
+
+#[derive(Clone)]
+struct Variable {
+    mutable: bool,
+    /// One entry for every scalar stored inside the variable.
+    initialized: Vec<bool>,
+}
+
+/// The scalars of a variable described by a place expression, not exact if an index isnt constant.
+struct Place<'a> {
+    root: &'a Literal,
+    span: Span,
+    scalars: Range<usize>,
+    exact: bool,
+}
+
+impl InitializationAnalysis {
+    pub fn new() -> Self {
+        Self {
+            variables: HashMap::new(),
+        }
+    }
+
+    pub fn analyse(&mut self, statements: &[Statement]) -> Result<(), Diagnostic> {
+        for statement in statements {
+            self.analyse_statement(statement)?;
+        }
+        Ok(())
+    }
+
+    fn analyse_statement(&mut self, statement: &Statement) -> Result<(), Diagnostic> {
+        match statement {
+            Statement::Let { identifier, mutable, type_, expression, .. } => {
+                if let Some(expression) = expression {
+                    self.analyse_expression(expression)?;
+                }
+                let scalars = scalar_count(type_.as_ref().expect("Let statements are typed by the semantic analysis!"));
+                self.variables.insert(identifier.clone(), Variable { mutable: *mutable, initialized: vec![expression.is_some(); scalars] });
+            }
+            Statement::Const { .. } => {}
+            Statement::Assign { assignee, expression, .. } => {
+                // Compound assignments read the assignee inside of their expression
+                self.analyse_expression(expression)?;
+                match assignee {
+                    Expression::Reference { reference } => self.write_place(reference)?,
+                    Expression::Access { .. } if assignee.place_root().is_some() => self.write_place(assignee)?,
+                    _ => self.analyse_expression(assignee)?,
+                }
+            }
+            Statement::Exit { expression, .. } |
+            Statement::Print { expression, .. } |
+            Statement::Expression { expression } => self.analyse_expression(expression)?,
+        }
+        Ok(())
+    }
+
+    fn analyse_expression(&mut self, expression: &Expression) -> Result<(), Diagnostic> {
+        match expression {
+            Expression::NumberLiteral { .. } |
+            Expression::CharLiteral { .. } |
+            Expression::BoolLiteral { .. } |
+            Expression::StringLiteral { .. } => {}
+            Expression::IdentifierLiteral { .. } => self.read_place(expression)?,
+            Expression::Access { value, index } => {
+                self.analyse_expression(index)?;
+                if expression.place_root().is_some() {
+                    self.read_place(expression)?;
+                } else {
+                    self.analyse_expression(value)?;
+                }
+            }
+            Expression::Operation { lhs, rhs, .. } => {
+                self.analyse_expression(lhs)?;
+                self.analyse_expression(rhs)?;
+            }
+            Expression::Logical { lhs, rhs, .. } => {
+                self.analyse_expression(lhs)?;
+                // The right side might not be evaluated, so only what both paths initialize is known afterwards
+                let skipped = self.variables.clone();
+                self.analyse_expression(rhs)?;
+                self.merge(skipped);
+            }
+            Expression::Array { content } => {
+                for element in content {
+                    self.analyse_expression(element)?;
+                }
+            }
+            Expression::Slice { value, start, end } => {
+                for bound in [start, end].into_iter().flatten() {
+                    self.analyse_expression(bound)?;
+                }
+                self.analyse_expression(value)?;
+            }
+            Expression::Reference { reference: value } |
+            Expression::Repeat { value, .. } |
+            Expression::Length { value } |
+            Expression::Deref { value } |
+            Expression::Cast { value, .. } => self.analyse_expression(value)?,
+        }
+        Ok(())
+    }
+
+    fn read_place(&mut self, expression: &Expression) -> Result<(), Diagnostic> {
+        let place = match self.place(expression) {
+            Some(place) => place,
+            None => return Ok(()),
+        };
+        let variable = &self.variables[place.root];
+
+        if variable.initialized[place.scalars.clone()].iter().all(|initialized| *initialized) {
+            return Ok(());
+        }
+        let message = if variable.initialized.len() == 1 {
+            format!("Use of possibly uninitialized variable `{}`!", literal_to_string(place.root))
+        } else {
+            format!("Use of possibly uninitialized elements of `{}`!", literal_to_string(place.root))
+        };
+        Err(Diagnostic::error(message, place.span).with_help("assign a value to it on every path before reading it"))
+    }
+
+    fn write_place(&mut self, expression: &Expression) -> Result<(), Diagnostic> {
+        self.analyse_indices(expression)?;
+        let place = match self.place(expression) {
+            Some(place) => place,
+            None => return Ok(()),
+        };
+        let variable = self.variables.get_mut(place.root).unwrap();
+
+        if !variable.mutable {
+            if !place.exact {
+                return Err(Diagnostic::error(format!("Cannot initialize elements of immutable `{}` through a non constant index!", literal_to_string(place.root)), place.span));
+            }
+            if variable.initialized[place.scalars.clone()].iter().any(|initialized| *initialized) {
+                return Err(
+                    Diagnostic::error(format!("Cannot assign twice to immutable variable `{}`!", literal_to_string(place.root)), place.span)
+                        .with_help(format!("declare it with `let mut {}` to allow this", literal_to_string(place.root)))
+                );
+            }
+        }
+        // Writes through an unknown index dont tell which element got initialized
+        if place.exact {
+            variable.initialized[place.scalars].fill(true);
+        }
+        Ok(())
+    }
+
+    fn analyse_indices(&mut self, expression: &Expression) -> Result<(), Diagnostic> {
+        match expression {
+            Expression::Access { value, index } => {
+                self.analyse_expression(index)?;
+                self.analyse_indices(value)
+            }
+            Expression::Cast { value, .. } => self.analyse_indices(value),
+            _ => Ok(()),
+        }
+    }
+
+    fn place<'a>(&self, expression: &'a Expression) -> Option<Place<'a>> {
+        match expression {
+            Expression::IdentifierLiteral { value, span, type_, .. } => Some(Place {
+                root: value,
+                span: *span,
+                scalars: 0..scalar_count(type_.as_ref().unwrap()),
+                exact: true,
+            }),
+            Expression::Access { value, index } => {
+                let (content_type, len) = match value.get_type() {
+                    ValueType::Array { content_type, len } => (content_type, len),
+                    // Nested arrays are indexed through the pointer their outer access decayed into
+                    ValueType::Pointer { points_to, .. } if value.is_array_element() => match *points_to {
+                        ValueType::Array { content_type, len } => (content_type, len),
+                        _ => return None,
+                    },
+                    _ => return None,
+                };
+                let mut place = self.place(value)?;
+                let size = scalar_count(&content_type);
+
+                match index.evaluate_constant(&HashMap::new()) {
+                    Ok(index) if (index.value as usize) < len => {
+                        let start = place.scalars.start + index.value as usize * size;
+                        place.scalars = start..start + size;
+                    }
+                    _ => place.exact = false,
+                }
+                Some(place)
+            }
+            Expression::Cast { value, .. } => self.place(value),
+            _ => None,
+        }
+    }
+
+    fn merge(&mut self, other: HashMap<Literal, Variable>) {
+        for (name, variable) in self.variables.iter_mut() {
+            if let Some(other) = other.get(name) {
+                variable.initialized.iter_mut()
+                    .zip(other.initialized.iter())
+                    .for_each(|(initialized, other)| *initialized &= *other);
+            }
+        }
+    }
+}
+
+fn scalar_count(type_: &ValueType) -> usize {
+    match type_ {
+        ValueType::Array { content_type, len } => scalar_count(content_type) * len,
+        _ => 1,
+    }
+}
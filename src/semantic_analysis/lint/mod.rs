@@ -0,0 +1,196 @@
+use std::collections::HashSet;
+use crate::diagnostic::{Diagnostic, Span};
+use crate::parser::expr::Expression;
+use crate::parser::r#type::ValueType;
+use crate::parser::stmt::Statement;
+use crate::tokenizer::token::{Literal, literal_to_string};
+
+pub const UNUSED_VARIABLE: &str = "W0001";
+pub const UNUSED_ASSIGNMENT: &str = "W0002";
+pub const UNUSED_RESULT: &str = "W0003";
+pub const UNREACHABLE_CODE: &str = "W0004";
+
+/// Looks for code that has no effect on the program. Variables starting with `_` are never reported.
+#[derive(Default)]
+pub struct Linter {
+    warnings: Vec<(usize, Diagnostic)>,
+}
+
+/// How a single statement uses the variables it mentions.
+#[derive(Default)]
+struct Accesses<'a> {
+    reads: Vec<&'a Literal>,
+    address_taken: Vec<&'a Literal>,
+    /// The variable that gets overwritten as a whole, elements written through an index dont count.
+    write: Option<(&'a Literal, Span)>,
+}
+
+impl Linter {
+    pub fn new() -> Self {
+        Self {
+            warnings: vec![],
+        }
+    }
+
+    pub fn lint(mut self, statements: &[Statement]) -> Vec<Diagnostic> {
+        let accesses: Vec<Accesses> = statements.iter().map(accesses).collect();
+
+        self.unused_variables(statements, &accesses);
+        self.unused_assignments(statements, &accesses);
+        self.unused_results(statements);
+        self.unreachable_code(statements);
+
+        self.warnings.sort_by_key(|(index, _)| *index);
+        self.warnings.into_iter().map(|(_, warning)| warning).collect()
+    }
+
+    fn unused_variables(&mut self, statements: &[Statement], accesses: &[Accesses]) {
+        let read: HashSet<&Literal> = accesses.iter().flat_map(|accesses| accesses.reads.iter().copied()).collect();
+
+        for (index, statement) in statements.iter().enumerate() {
+            if let Statement::Let { identifier, span, .. } = statement {
+                if read.contains(identifier) || is_silenced(identifier) { continue; }
+                self.warnings.push((index, Diagnostic::warning(UNUSED_VARIABLE, format!("Unused variable `{}`!", literal_to_string(identifier)), Some(*span))
+                    .with_help(format!("if this is intentional, prefix it with an underscore: `_{}`", literal_to_string(identifier)))));
+            }
+        }
+    }
+
+    /// Walks the statements backwards, a write to a variable that isnt read before the next write is useless.
+    fn unused_assignments(&mut self, statements: &[Statement], accesses: &[Accesses]) {
+        let read: HashSet<&Literal> = accesses.iter().flat_map(|accesses| accesses.reads.iter().copied()).collect();
+        let address_taken: HashSet<&Literal> = accesses.iter().flat_map(|accesses| accesses.address_taken.iter().copied()).collect();
+        // Unread variables are already reported as unused and pointers may read a variable behind our back
+        let tracked: HashSet<&Literal> = statements.iter()
+            .filter_map(|statement| match statement {
+                Statement::Let { identifier, type_: Some(type_), .. } if !matches!(type_, ValueType::Array { .. }) => Some(identifier),
+                _ => None,
+            })
+            .filter(|identifier| read.contains(identifier) && !address_taken.contains(identifier) && !is_silenced(identifier))
+            .collect();
+
+        let mut live: HashSet<&Literal> = HashSet::new();
+        for (index, (statement, accesses)) in statements.iter().zip(accesses.iter()).enumerate().rev() {
+            if let Statement::Exit { .. } = statement {
+                live.clear();
+            }
+            if let Some((variable, span)) = accesses.write {
+                if tracked.contains(variable) && !live.remove(variable) {
+                    self.warnings.push((index, Diagnostic::warning(UNUSED_ASSIGNMENT, format!("Value assigned to `{}` is never read!", literal_to_string(variable)), Some(span))
+                        .with_help("it is overwritten before being read")));
+                }
+            }
+            live.extend(accesses.reads.iter().copied());
+        }
+    }
+
+    fn unused_results(&mut self, statements: &[Statement]) {
+        for (index, statement) in statements.iter().enumerate() {
+            if let Statement::Expression { expression } = statement {
+                self.warnings.push((index, Diagnostic::warning(UNUSED_RESULT, "Unused result of expression!", expression.span())
+                    .with_help("if this is intentional, bind it to a variable starting with an underscore: `let _unused = ...;`")));
+            }
+        }
+    }
+
+    fn unreachable_code(&mut self, statements: &[Statement]) {
+        let exit = match statements.iter().position(|statement| matches!(statement, Statement::Exit { .. })) {
+            Some(exit) => exit,
+            None => return,
+        };
+        // Constants are evaluated while compiling, so they dont make for unreachable code
+        let unreachable = statements.iter().enumerate()
+            .skip(exit + 1)
+            .find(|(_, statement)| !matches!(statement, Statement::Const { .. }));
+
+        if let Some((index, statement)) = unreachable {
+            self.warnings.push((index, Diagnostic::warning(UNREACHABLE_CODE, "Unreachable statement!", statement.span())
+                .with_help(format!("any code following the `exit` at {} is never executed", statements[exit].span().unwrap_or_default()))));
+        }
+    }
+}
+
+fn is_silenced(identifier: &Literal) -> bool {
+    identifier.first() == Some(&b'_')
+}
+
+fn accesses(statement: &Statement) -> Accesses<'_> {
+    let mut accesses = Accesses::default();
+
+    match statement {
+        Statement::Let { identifier, span, expression, .. } => {
+            if let Some(expression) = expression {
+                visit(expression, &mut accesses);
+                accesses.write = Some((identifier, *span));
+            }
+        }
+        Statement::Const { .. } => {}
+        Statement::Assign { assignee, expression, .. } => {
+            visit(expression, &mut accesses);
+            match assignee {
+                Expression::Reference { reference } => match reference.as_ref() {
+                    Expression::IdentifierLiteral { value, span, .. } => accesses.write = Some((value, *span)),
+                    place => visit_indices(place, &mut accesses),
+                },
+                Expression::Access { .. } if assignee.place_root().is_some() => visit_indices(assignee, &mut accesses),
+                _ => visit(assignee, &mut accesses),
+            }
+        }
+        Statement::Exit { expression, .. } |
+        Statement::Print { expression, .. } |
+        Statement::Expression { expression } => visit(expression, &mut accesses),
+    }
+
+    accesses
+}
+
+fn visit<'a>(expression: &'a Expression, accesses: &mut Accesses<'a>) {
+    match expression {
+        Expression::NumberLiteral { .. } |
+        Expression::CharLiteral { .. } |
+        Expression::BoolLiteral { .. } |
+        Expression::StringLiteral { .. } => {}
+        Expression::IdentifierLiteral { value, .. } => accesses.reads.push(value),
+        Expression::Reference { reference } => {
+            if let Expression::IdentifierLiteral { value, .. } = reference.as_ref() {
+                accesses.address_taken.push(value);
+            }
+            visit(reference, accesses);
+        }
+        Expression::Deref { value } => match value.as_ref() {
+            // Compound assignments read their assignee through its reference
+            Expression::Reference { reference } => visit(reference, accesses),
+            value => visit(value, accesses),
+        },
+        Expression::Operation { lhs, rhs, .. } |
+        Expression::Logical { lhs, rhs, .. } => {
+            visit(lhs, accesses);
+            visit(rhs, accesses);
+        }
+        Expression::Access { value, index } => {
+            visit(value, accesses);
+            visit(index, accesses);
+        }
+        Expression::Array { content } => content.iter().for_each(|element| visit(element, accesses)),
+        Expression::Repeat { value, .. } |
+        Expression::Length { value } => visit(value, accesses),
+        Expression::Slice { value, start, end } => {
+            visit(value, accesses);
+            [start, end].into_iter().flatten().for_each(|bound| visit(bound, accesses));
+        }
+        Expression::Cast { value, .. } => visit(value, accesses),
+    }
+}
+
+/// Writing into an element only reads the indices on the way there, not the variable itself.
+fn visit_indices<'a>(expression: &'a Expression, accesses: &mut Accesses<'a>) {
+    match expression {
+        Expression::Access { value, index } if expression.place_root().is_some() => {
+            visit(index, accesses);
+            visit_indices(value, accesses);
+        }
+        Expression::Cast { value, .. } => visit_indices(value, accesses),
+        Expression::IdentifierLiteral { .. } => {}
+        _ => visit(expression, accesses),
+    }
+}
@@ -1,47 +1,109 @@
 pub mod symbol_table;
+pub mod constant;
+pub mod initialization;
+pub mod lint;
 
+use std::collections::HashMap;
 use std::mem;
 
+use anyhow::bail;
+
+use crate::diagnostic::{Diagnostic, Span};
 use crate::parser::expr::Expression;
 use crate::parser::r#type::{CastVariant, ValueType};
 use crate::semantic_analysis::symbol_table::SymbolTable;
+use crate::tokenizer::token::{Literal, literal_to_string, Operator, split_string_literal, string_literal_bytes};
 
 impl Expression {
-    pub fn resolve(&mut self, symbol_table: &SymbolTable) {
+    pub fn resolve(&mut self, symbol_table: &SymbolTable) -> anyhow::Result<()> {
         match self {
             Expression::NumberLiteral { .. } |
-            Expression::CharLiteral { .. } => {}
+            Expression::CharLiteral { .. } |
+            Expression::BoolLiteral { .. } |
+            Expression::StringLiteral { .. } => {}
             Expression::Deref { value } => {
-                value.resolve(symbol_table);
-                if let ValueType::Pointer { .. } = value.get_type() {} else { None.unwrap() }
+                value.resolve(symbol_table)?;
+                if let ValueType::Pointer { .. } = value.get_type() {} else { bail!("Cannot dereference value of type `{}`!", value.get_type()) }
             }
             Expression::Access { value, index } => {
-                value.resolve(symbol_table);
-                if let ValueType::Pointer { .. } = value.get_type() {} else if let ValueType::Array { .. } = value.get_type() {} else { None.unwrap() }
-
-                index.resolve(symbol_table);
-                let index_type = index.get_type();
-                if index_type != ValueType::U64 {
-                    let _ = index_type.get_casts().get(&ValueType::U64).unwrap();
-                    let _ = mem::replace(index, Box::new(Expression::Cast { value: index.clone(), to: ValueType::U64 }));
-                    index.resolve(symbol_table)
+                value.resolve(symbol_table)?;
+                if let ValueType::Pointer { .. } | ValueType::Array { .. } | ValueType::Slice { .. } | ValueType::Str = value.get_type() {} else { bail!("Cannot index into value of type `{}`!", value.get_type()) }
+                resolve_index(index, symbol_table)?;
+
+                // The length of slices and strings is only known at runtime, except for string literals
+                let len = match value.get_type().known_len().or_else(|| value.string_literal_len()) {
+                    Some(len) => len,
+                    None => return Ok(()),
+                };
+                if let Ok(constant) = index.evaluate_constant(&HashMap::new()) {
+                    if constant.value >= len as u64 {
+                        let message = format!("Index {} is out of bounds for an array of length {}!", constant.value, len);
+                        match value.span() {
+                            Some(span) if len == 0 => return Err(Diagnostic::error(message, span).with_help("the array is empty, so it cannot be indexed at all").into()),
+                            Some(span) => return Err(Diagnostic::error(message, span).with_help(format!("valid indices are 0 to {}", len - 1)).into()),
+                            None => bail!(message),
+                        }
+                    }
+                }
+            }
+            Expression::Length { value } => {
+                value.resolve(symbol_table)?;
+                if value.get_type().known_len().is_none() && !matches!(value.get_type(), ValueType::Slice { .. } | ValueType::Str) { bail!("Value of type `{}` has no length!", value.get_type()) }
+            }
+            Expression::Slice { value, start, end } => {
+                value.resolve(symbol_table)?;
+                if let ValueType::Array { .. } | ValueType::Slice { .. } | ValueType::Str = value.get_type() {} else if value.get_type().known_len().is_none() { bail!("Cannot slice value of type `{}`!", value.get_type()) }
+                for bound in [&mut *start, &mut *end].into_iter().flatten() {
+                    resolve_index(bound, symbol_table)?;
+                }
+
+                let start = match start {
+                    Some(start) => start.evaluate_constant(&HashMap::new()).ok().map(|start| start.value),
+                    None => Some(0),
+                };
+                let len = value.get_type().known_len().or_else(|| value.string_literal_len()).map(|len| len as u64);
+                let end = match end {
+                    Some(end) => end.evaluate_constant(&HashMap::new()).ok().map(|end| end.value),
+                    None => len,
+                };
+                let message = match (start, end, len) {
+                    (Some(start), Some(end), _) if start > end => Some(format!("Slice starts at {} but ends at {}!", start, end)),
+                    (_, Some(end), Some(len)) if end > len => Some(format!("Slice ends at {} which is past the end of the {} elements!", end, len)),
+                    (Some(start), _, Some(len)) if start > len => Some(format!("Slice starts at {} which is past the end of the {} elements!", start, len)),
+                    _ => None,
+                };
+                if let Some(message) = message {
+                    match value.span() {
+                        Some(span) => return Err(Diagnostic::error(message, span).into()),
+                        None => bail!(message),
+                    }
                 }
-                if let ValueType::U64 = index.get_type() {} else { None.unwrap() }
             }
-            Expression::IdentifierLiteral { value, type_, .. } => {
-                type_.replace(symbol_table.get(value).clone());
+            Expression::IdentifierLiteral { value, span, type_, mutable } => {
+                let symbol = match symbol_table.get(value) {
+                    Some(symbol) => symbol,
+                    None => {
+                        let mut diagnostic = Diagnostic::error(format!("Use of undeclared variable `{}`!", literal_to_string(value)), *span);
+                        if let Some(suggestion) = symbol_table.suggest(value) {
+                            diagnostic = diagnostic.with_help(format!("did you mean `{}`?", literal_to_string(suggestion)));
+                        }
+                        return Err(diagnostic.into());
+                    }
+                };
+                type_.replace(symbol.type_.clone());
+                *mutable = symbol.mutable;
             }
             Expression::Operation { rhs, lhs, operator, type_ } => {
-                lhs.resolve(symbol_table);
+                lhs.resolve(symbol_table)?;
                 if lhs.get_type().is_pointer() {
                     let _ = mem::replace(lhs, Box::new(Expression::Deref { value: lhs.clone() }));
-                    lhs.resolve(symbol_table)
+                    lhs.resolve(symbol_table)?
                 }
 
-                rhs.resolve(symbol_table);
+                rhs.resolve(symbol_table)?;
                 if rhs.get_type().is_pointer() {
                     let _ = mem::replace(rhs, Box::new(Expression::Deref { value: rhs.clone() }));
-                    rhs.resolve(symbol_table)
+                    rhs.resolve(symbol_table)?
                 }
 
 
@@ -65,7 +127,7 @@ impl Expression {
                     }
                     if let Some(cast_to) = cast_to {
                         let _ = mem::replace(rhs, Box::new(Expression::Cast { value: rhs.clone(), to: cast_to.clone() }));
-                        rhs.resolve(symbol_table)
+                        rhs.resolve(symbol_table)?
                     }
                 }
 
@@ -89,34 +151,64 @@ impl Expression {
                     }
                     if let Some(cast_to) = cast_to {
                         let _ = mem::replace(lhs, Box::new(Expression::Cast { value: lhs.clone(), to: cast_to.clone() }));
-                        lhs.resolve(symbol_table)
-                    } else { None::<()>.unwrap(); }
+                        lhs.resolve(symbol_table)?
+                    } else { bail!("Operation `{}` is not defined for `{}` and `{}`!", operator, lhs.get_type(), rhs.get_type()) }
                 }
 
-                type_.replace(right_operator_type_to_result_types.get(&rhs.get_type()).unwrap().clone());
+                match right_operator_type_to_result_types.get(&rhs.get_type()) {
+                    Some(result_type) => { type_.replace(result_type.clone()); }
+                    None => bail!("Operation `{}` is not defined for `{}` and `{}`!", operator, lhs.get_type(), rhs.get_type()),
+                }
             }
-            Expression::Array { content } => {
-                let mut last_type = None;
-                for expression in content {
-                    expression.resolve(symbol_table);
-                    let type_ = expression.get_type();
-                    if let Some(last_type) = &last_type {
-                        if &type_ != last_type { None.unwrap() }
+            Expression::Logical { lhs, operator, rhs } => {
+                for operand in [lhs, rhs] {
+                    operand.resolve(symbol_table)?;
+                    if operand.get_type().is_pointer() {
+                        let _ = mem::replace(operand, Box::new(Expression::Deref { value: operand.clone() }));
+                        operand.resolve(symbol_table)?
                     }
-                    last_type = Some(type_);
+                    if operand.get_type() != ValueType::Bool { bail!("Operator `{}` expects `bool` operands but got `{}`!", operator, operand.get_type()) }
                 }
             }
+            Expression::Array { content } => {
+                for expression in content.iter_mut() {
+                    expression.resolve(symbol_table)?;
+                }
+                // Without a declared type the elements are widened to the widest type among them
+                let types: Vec<ValueType> = content.iter().map(|expression| expression.get_type()).collect();
+                let content_type = types.iter()
+                    .find(|candidate| types.iter().all(|type_| type_ == *candidate || type_.widens_to(candidate)))
+                    .cloned();
+                let content_type = match content_type {
+                    Some(content_type) => content_type,
+                    None => {
+                        let compatible = |type_: &ValueType| type_ == &types[0] || type_.widens_to(&types[0]) || types[0].widens_to(type_);
+                        let element = content.iter().find(|expression| !compatible(&expression.get_type()))
+                            .or_else(|| content.iter().find(|expression| expression.get_type() != types[0]))
+                            .unwrap();
+                        let message = format!("Array elements must all have the same type but found `{}` and `{}`!", types[0], element.get_type());
+                        match element.span() {
+                            Some(span) => return Err(Diagnostic::error(message, span).into()),
+                            None => bail!(message),
+                        }
+                    }
+                };
+                let len = content.len();
+                self.resolve_against(&ValueType::Array { content_type: Box::new(content_type), len }, symbol_table)?;
+            }
+            Expression::Repeat { value, .. } => value.resolve(symbol_table)?,
             Expression::Reference { reference: to_reference } => {
-                to_reference.resolve(symbol_table);
-                if let Expression::IdentifierLiteral { .. } = to_reference.as_ref() {} else if let Expression::Access { .. } = to_reference.as_ref() {} else { None.unwrap() }
+                to_reference.resolve(symbol_table)?;
+                if let Expression::IdentifierLiteral { .. } = to_reference.as_ref() {} else if let Expression::Access { .. } = to_reference.as_ref() {} else { bail!("Can only reference variables and array elements!") }
             }
             Expression::Cast { value, to } => {
-                value.resolve(symbol_table);
+                value.resolve(symbol_table)?;
 
                 let allowed_casts = value.get_type().get_casts();
-                let _ = allowed_casts.get(to).unwrap();
+                if !allowed_casts.contains_key(to) { bail!("Cannot cast `{}` to `{}`!", value.get_type(), to) }
             }
         }
+        Ok(())
     }
 
     pub fn get_type(&self) -> ValueType {
@@ -124,28 +216,227 @@ impl Expression {
             Expression::NumberLiteral { internal_type, .. } => internal_type.clone(),
             Expression::IdentifierLiteral { type_, .. } => type_.clone().unwrap(),
             Expression::CharLiteral { .. } => ValueType::Char,
+            Expression::BoolLiteral { .. } => ValueType::Bool,
+            Expression::StringLiteral { .. } => ValueType::Str,
             Expression::Operation { type_, .. } => type_.clone().unwrap(),
+            Expression::Logical { .. } => ValueType::Bool,
             Expression::Array { content } => ValueType::Array { content_type: Box::new(content.first().unwrap().get_type()), len: content.len() },
+            Expression::Repeat { value, len } => ValueType::Array { content_type: Box::new(value.get_type()), len: *len },
             Expression::Deref { value } => {
-                if let ValueType::Pointer { points_to } = value.get_type() { *points_to } else { None.unwrap() }
+                if let ValueType::Pointer { points_to, .. } = value.get_type() { *points_to } else { unreachable!() }
             }
             Expression::Access { value, .. } => {
                 match value.get_type() {
-                    ValueType::Pointer { points_to } => match points_to.as_ref() {
+                    ValueType::Pointer { points_to, .. } => match points_to.as_ref() {
                         ValueType::Array { content_type, .. } => *content_type.clone(),
                         _ => *points_to.clone(),
                     },
-                    ValueType::Array { content_type, .. } => ValueType::Pointer { points_to: content_type.clone() },
+                    ValueType::Array { content_type, .. } => ValueType::Pointer { points_to: content_type.clone(), mutable: value.is_mutable_place() },
+                    ValueType::Slice { content_type, .. } => *content_type,
+                    ValueType::Str => ValueType::U8,
                     _ => unreachable!()
                 }
             }
-            Expression::Reference { reference } => ValueType::Pointer { points_to: Box::new(reference.get_type()) },
+            Expression::Length { .. } => ValueType::U64,
+            Expression::Slice { value, .. } => match value.get_type() {
+                ValueType::Array { content_type, .. } => ValueType::Slice { content_type, mutable: value.is_mutable_place() },
+                ValueType::Pointer { points_to, mutable } => match *points_to {
+                    ValueType::Array { content_type, .. } => ValueType::Slice { content_type, mutable },
+                    _ => unreachable!(),
+                },
+                slice => slice,
+            },
+            Expression::Reference { reference } => ValueType::Pointer { points_to: Box::new(reference.get_type()), mutable: reference.is_mutable_place() },
             Expression::Cast { to, .. } => to.clone(),
         }
     }
+
+    /// Whether the memory described by this expression may be written to.
+    pub fn is_mutable_place(&self) -> bool {
+        match self {
+            Expression::IdentifierLiteral { mutable, .. } => *mutable,
+            Expression::Access { value, .. } => match value.get_type() {
+                ValueType::Pointer { mutable, .. } |
+                ValueType::Slice { mutable, .. } => mutable,
+                ValueType::Str => false,
+                _ => value.is_mutable_place(),
+            },
+            Expression::Deref { value } => matches!(value.get_type(), ValueType::Pointer { mutable: true, .. }),
+            Expression::Cast { value, .. } => value.is_mutable_place(),
+            Expression::Array { .. } |
+            Expression::Repeat { .. } => true,
+            _ => false,
+        }
+    }
+
+    /// Retypes an expression made only of integer literals to `u64`, returns whether it was one.
+    pub fn widen_integer_literals(&mut self) -> bool {
+        if !self.is_integer_literal() { return false; }
+        match self {
+            Expression::NumberLiteral { internal_type, .. } => *internal_type = ValueType::U64,
+            Expression::Operation { lhs, rhs, .. } => {
+                lhs.widen_integer_literals();
+                rhs.widen_integer_literals();
+            }
+            _ => unreachable!(),
+        }
+        true
+    }
+
+    fn is_integer_literal(&self) -> bool {
+        match self {
+            Expression::NumberLiteral { .. } => true,
+            Expression::Operation { lhs, operator, rhs, .. } => matches!(operator, Operator::Plus | Operator::Minus | Operator::Times | Operator::Divide) && lhs.is_integer_literal() && rhs.is_integer_literal(),
+            _ => false,
+        }
+    }
+
+    /// The variable an assignment to this (already rewritten) assignee writes into, if it writes into a variable at all.
+    pub fn assignee_root(&self) -> Option<&Literal> {
+        match self {
+            Expression::Reference { reference } => reference.place_root(),
+            Expression::Access { .. } => self.place_root(),
+            _ => None,
+        }
+    }
+
+    /// The variable holding the memory described by this expression.
+    pub fn place_root(&self) -> Option<&Literal> {
+        match self {
+            Expression::IdentifierLiteral { value, .. } => Some(value),
+            Expression::Access { value, .. } if matches!(value.get_type(), ValueType::Array { .. }) || value.is_array_element() => value.place_root(),
+            Expression::Cast { value, .. } => value.place_root(),
+            _ => None,
+        }
+    }
+
+    /// Whether this accesses an element stored inside an array, also through the pointers nested arrays decay into.
+    pub fn is_array_element(&self) -> bool {
+        match self {
+            Expression::Access { value, .. } => match value.get_type() {
+                ValueType::Array { .. } => true,
+                ValueType::Pointer { points_to, .. } => matches!(*points_to, ValueType::Array { .. }) && value.is_array_element(),
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+
+    /// Resolves the expression knowing the type it gets stored as, so array initializers can be checked element by element.
+    pub fn resolve_against(&mut self, expected: &ValueType, symbol_table: &SymbolTable) -> anyhow::Result<()> {
+        // String literals may still initialize arrays of chars
+        if let (Expression::StringLiteral { value, span }, ValueType::Array { .. }) = (&*self, expected) {
+            if value.is_empty() { bail!("Cannot store an empty string in `{}`!", expected) }
+            let content = split_string_literal(value).into_iter()
+                .map(|char| Expression::CharLiteral { value: char, span: *span })
+                .collect();
+            let _ = mem::replace(self, Expression::Array { content });
+        }
+        let span = self.span();
+        let (content_type, len) = match (&*self, expected) {
+            (Expression::Array { .. } | Expression::Repeat { .. }, ValueType::Array { content_type, len }) => (content_type, *len),
+            _ => return self.resolve(symbol_table),
+        };
+
+        let actual_len = match self {
+            Expression::Array { content } => content.len(),
+            Expression::Repeat { len, .. } => *len,
+            _ => unreachable!(),
+        };
+        if actual_len != len {
+            let message = format!("Expected an array of length {} but the initializer has {} elements!", len, actual_len);
+            match span {
+                Some(span) => return Err(Diagnostic::error(message, span).with_help(format!("the declared type is `{}`", expected)).into()),
+                None => bail!(message),
+            }
+        }
+        match self {
+            Expression::Array { content } => {
+                for (index, element) in content.iter_mut().enumerate() {
+                    element.resolve_element(content_type, symbol_table, format!("element {}", index))?;
+                }
+            }
+            Expression::Repeat { value, .. } => value.resolve_element(content_type, symbol_table, "the repeated value".to_string())?,
+            _ => unreachable!(),
+        }
+        Ok(())
+    }
+
+    /// Resolves an array element and widens it to the element type of the array.
+    fn resolve_element(&mut self, content_type: &ValueType, symbol_table: &SymbolTable, description: String) -> anyhow::Result<()> {
+        self.resolve_against(content_type, symbol_table)?;
+        let type_ = self.get_type();
+        if &type_ == content_type { return Ok(()); }
+
+        if !type_.widens_to(content_type) {
+            let message = format!("Expected array element of type `{}` but got `{}`!", content_type, type_);
+            match self.span() {
+                Some(span) => return Err(Diagnostic::error(message, span).with_help(format!("{} cannot be converted to `{}` implicitly", description, content_type)).into()),
+                None => bail!(message),
+            }
+        }
+        let _ = mem::replace(self, Expression::Cast { value: Box::new(self.clone()), to: content_type.clone() });
+        self.resolve(symbol_table)
+    }
+
+    pub fn string_literal_len(&self) -> Option<usize> {
+        match self {
+            Expression::StringLiteral { value, .. } => Some(string_literal_bytes(value).len()),
+            _ => None,
+        }
+    }
+
+    /// The span of the leftmost literal, only empty arrays dont have one.
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            Expression::IdentifierLiteral { span, .. } |
+            Expression::NumberLiteral { span, .. } |
+            Expression::CharLiteral { span, .. } |
+            Expression::BoolLiteral { span, .. } |
+            Expression::StringLiteral { span, .. } => Some(*span),
+            Expression::Operation { lhs, rhs, .. } |
+            Expression::Logical { lhs, rhs, .. } => lhs.span().or_else(|| rhs.span()),
+            Expression::Access { value, index } => value.span().or_else(|| index.span()),
+            Expression::Array { content } => content.iter().find_map(|element| element.span()),
+            Expression::Repeat { value, .. } |
+            Expression::Length { value } |
+            Expression::Slice { value, .. } => value.span(),
+            Expression::Reference { reference: value } |
+            Expression::Deref { value } |
+            Expression::Cast { value, .. } => value.span(),
+        }
+    }
+
+    pub fn explain_immutability(&self) -> String {
+        match self {
+            Expression::IdentifierLiteral { value, .. } => format!("Cannot assign to immutable variable `{0}`, declare it with `let mut {0}` to allow this!", literal_to_string(value)),
+            Expression::Access { value, .. } if matches!(value.get_type(), ValueType::Array { .. }) => value.explain_immutability(),
+            Expression::Access { value, .. } if value.get_type() == ValueType::Str => "Cannot assign to the bytes of a `str`, strings are read only!".to_string(),
+            Expression::Access { value, .. } |
+            Expression::Deref { value } => format!("Cannot assign through `{}` because it is not a mutable reference!", value.get_type()),
+            Expression::Cast { value, .. } => value.explain_immutability(),
+            _ => "Cannot assign to a temporary value!".to_string(),
+        }
+    }
 }
 
 impl ValueType {
+    /// Whether values of this type can be converted to the other type without losing information.
+    pub fn widens_to(&self, other: &ValueType) -> bool {
+        self.get_casts().get(other) == Some(&CastVariant::Explicit)
+    }
+
+    pub fn points_to(&self) -> ValueType {
+        match self {
+            ValueType::Pointer { points_to, .. } => *points_to.clone(),
+            _ => unreachable!(),
+        }
+    }
+
+    pub fn is_integer(&self) -> bool {
+        matches!(self, ValueType::U64 | ValueType::U32 | ValueType::U16 | ValueType::U8)
+    }
+
     pub fn is_pointer(&self) -> bool {
         match self {
             ValueType::Pointer { .. } => true,
@@ -154,7 +445,43 @@ impl ValueType {
             ValueType::U16 |
             ValueType::U8 |
             ValueType::Char |
-            ValueType::Array { .. } => false,
+            ValueType::Bool |
+            ValueType::Array { .. } |
+            ValueType::Slice { .. } |
+            ValueType::Str => false,
         }
     }
+
+    /// Values of these types live in memory and are copied element by element.
+    pub fn is_aggregate(&self) -> bool {
+        matches!(self, ValueType::Array { .. } | ValueType::Slice { .. } | ValueType::Str)
+    }
+
+    /// Slices and strings are a pointer followed by a length.
+    pub fn is_fat_pointer(&self) -> bool {
+        matches!(self, ValueType::Slice { .. } | ValueType::Str)
+    }
+
+    /// The number of elements of an array, also behind a pointer, if it is known at compile time.
+    pub fn known_len(&self) -> Option<usize> {
+        match self {
+            ValueType::Array { len, .. } => Some(*len),
+            ValueType::Pointer { points_to, .. } => match points_to.as_ref() {
+                ValueType::Array { len, .. } => Some(*len),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+fn resolve_index(index: &mut Box<Expression>, symbol_table: &SymbolTable) -> anyhow::Result<()> {
+    index.resolve(symbol_table)?;
+    let index_type = index.get_type();
+    if index_type != ValueType::U64 {
+        if !index_type.get_casts().contains_key(&ValueType::U64) { bail!("Cannot use value of type `{}` as index!", index_type) }
+        let _ = mem::replace(index, Box::new(Expression::Cast { value: index.clone(), to: ValueType::U64 }));
+        index.resolve(symbol_table)?
+    }
+    Ok(())
 }
\ No newline at end of file
@@ -1,6 +1,7 @@
 use std::collections::HashMap;
+use crate::diagnostic::{Diagnostic, Span};
 use crate::parser::r#type::ValueType;
-use crate::tokenizer::token::Literal;
+use crate::tokenizer::token::{Literal, literal_to_string};
 
 pub struct SymbolTable {
     scopes: Vec<Scope>
@@ -21,21 +22,48 @@ impl SymbolTable {
         self.scopes.pop();
     }
 
-    pub fn register(&mut self, name: Literal, type_: ValueType) {
-        self.scopes.last_mut().unwrap().register(name, type_);
+    /// Declares a new variable in the innermost scope. A variable may shadow one of an outer
+    /// scope, but declaring the same name twice inside a single scope is an error.
+    pub fn register(&mut self, name: Literal, symbol: Symbol) -> Result<(), Diagnostic> {
+        self.scopes.last_mut().unwrap().register(name, symbol)
     }
 
-    pub fn get(&self, name: &Literal) -> &ValueType {
-        let scope = self.scopes.iter()
+    pub fn get(&self, name: &Literal) -> Option<&Symbol> {
+        self.scopes.iter()
             .rev()
-            .find(|scope| scope.get(name).is_some()).unwrap();
+            .find_map(|scope| scope.get(name))
+    }
+
+    /// Finds the visible variable whose name is closest to the given one, if any is close enough.
+    pub fn suggest(&self, name: &Literal) -> Option<&Literal> {
+        let max_distance = (name.len() / 3).max(1);
+        self.scopes.iter()
+            .flat_map(|scope| scope.variables.keys())
+            .map(|candidate| (edit_distance(name, candidate), candidate))
+            .filter(|(distance, _)| *distance <= max_distance)
+            .min()
+            .map(|(_, candidate)| candidate)
+    }
+}
 
-        scope.get(name).unwrap()
+impl Default for SymbolTable {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
+#[derive(Debug, Clone)]
+pub struct Symbol {
+    pub type_: ValueType,
+    pub mutable: bool,
+    /// Whether the variable got its value right at the declaration.
+    pub initialized: bool,
+    pub span: Span,
+}
+
+#[derive(Default)]
 pub struct Scope {
-    variables: HashMap<Literal, ValueType>
+    variables: HashMap<Literal, Symbol>
 }
 
 impl Scope {
@@ -45,11 +73,62 @@ impl Scope {
         }
     }
 
-    pub fn register(&mut self, name: Literal, type_: ValueType) {
-        self.variables.insert(name, type_);
+    pub fn register(&mut self, name: Literal, symbol: Symbol) -> Result<(), Diagnostic> {
+        if let Some(previous) = self.variables.get(&name) {
+            return Err(
+                Diagnostic::error(format!("Variable `{}` is already declared in this scope!", literal_to_string(&name)), symbol.span)
+                    .with_help(format!("the previous declaration is at {}, use a different name", previous.span))
+            );
+        }
+        self.variables.insert(name, symbol);
+        Ok(())
     }
 
-    pub fn get(&self, name: &Literal) -> Option<&ValueType> {
+    pub fn get(&self, name: &Literal) -> Option<&Symbol> {
         self.variables.get(name)
     }
-}
\ No newline at end of file
+}
+
+/// Optimal string alignment distance, a swap of two neighbouring characters counts as a single edit.
+fn edit_distance(a: &[u8], b: &[u8]) -> usize {
+    let mut distances: Vec<Vec<usize>> = (0..=a.len()).map(|i| (0..=b.len()).map(|j| if i == 0 { j } else { i }).collect()).collect();
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let substitution = distances[i - 1][j - 1] + (a[i - 1] != b[j - 1]) as usize;
+            distances[i][j] = substitution.min(distances[i - 1][j] + 1).min(distances[i][j - 1] + 1);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                distances[i][j] = distances[i][j].min(distances[i - 2][j - 2] + 1);
+            }
+        }
+    }
+    distances[a.len()][b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table(names: &[&str]) -> SymbolTable {
+        let mut table = SymbolTable::new();
+        for name in names {
+            let symbol = Symbol { type_: ValueType::U8, mutable: false, initialized: true, span: Span::default() };
+            table.register(name.as_bytes().to_vec(), symbol).unwrap();
+        }
+        table
+    }
+
+    #[test]
+    fn swapped_characters_are_a_single_edit() {
+        assert_eq!(edit_distance(b"valeu", b"value"), 1);
+        assert_eq!(edit_distance(b"ab", b"ba"), 1);
+        assert_eq!(edit_distance(b"kitten", b"sitting"), 3);
+    }
+
+    #[test]
+    fn typos_suggest_the_closest_variable() {
+        let table = table(&["value", "counter"]);
+        assert_eq!(table.suggest(&b"valeu".to_vec()), Some(&b"value".to_vec()));
+        assert_eq!(table.suggest(&b"conuter".to_vec()), Some(&b"counter".to_vec()));
+        assert_eq!(table.suggest(&b"total".to_vec()), None);
+    }
+}
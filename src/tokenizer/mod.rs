@@ -3,7 +3,8 @@ use std::str::Chars;
 use anyhow::bail;
 use lazy_static::lazy_static;
 use regex::Regex;
-use crate::tokenizer::token::{Keyword, Literal, LiteralType, Operator, Token, TypeType};
+use crate::diagnostic::Span;
+use crate::tokenizer::token::{Keyword, Literal, literal_to_char, literal_to_string, LiteralType, Operator, split_string_literal, Token, TypeType};
 
 pub mod token;
 
@@ -15,13 +16,19 @@ lazy_static! {
 }
 
 pub struct Tokenizer<'a> {
-    input: Peekable<Chars<'a>>
+    input: Peekable<Chars<'a>>,
+    line: usize,
+    column: usize,
+    token_start: Span,
 }
 
 impl<'a> Tokenizer<'a> {
     pub fn new(input: Peekable<Chars<'a>>) -> Self {
         Self {
             input,
+            line: 1,
+            column: 1,
+            token_start: Span::default(),
         }
     }
 
@@ -45,9 +52,10 @@ impl<'a> Tokenizer<'a> {
     fn next_token(&mut self) -> anyhow::Result<Token> {
         self.skip_ignorables();
         if self.input.peek().is_none() { return Ok(Token::EOF); }
+        self.token_start = Span { line: self.line, column: self.column, len: 0 };
 
         match self.input.peek().expect("THIS WILL NEVER OCCUR!") {
-            &',' | &':' | &';' | &'+' | &'-' | &'*' | &'/' | &'&' | &'=' | &'(' | &')' | &'[' | &']' => self.tokenize_singe_symbol(),
+            &',' | &':' | &';' | &'+' | &'-' | &'*' | &'/' | &'&' | &'|' | &'=' | &'!' | &'<' | &'>' | &'(' | &')' | &'[' | &']' | &'.' => self.tokenize_singe_symbol(),
             character if LITERAL_START_REGEX.is_match(&character.to_string()) => self.tokenize_identifier(),
             character if NUMBER_REGEX.is_match(&character.to_string()) => self.tokenize_number(),
             &'\'' => self.tokenize_char(),
@@ -58,26 +66,41 @@ impl<'a> Tokenizer<'a> {
 
     fn tokenize_singe_symbol(&mut self) -> anyhow::Result<Token> {
         let char = self.consume_char()?;
+        let next = self.input.peek().copied();
         Ok(
             match char {
                 ',' => Token::Comma,
                 ':' => Token::Colon,
                 ';' => Token::Semicolon,
-                '+' | '-' | '*' | '/' | '&' | '=' => Token::Operation {
-                    operator: match char {
-                        '+' => Operator::Plus,
-                        '-' => Operator::Minus,
-                        '*' => Operator::Times,
-                        '/' => Operator::Divide,
-                        '&' => Operator::And,
-                        '=' => Operator::Assign,
-                        _ => unreachable!()
+                '+' | '-' | '*' | '/' | '&' | '|' | '=' | '!' | '<' | '>' => Token::Operation {
+                    operator: match (char, next) {
+                        ('&', Some('&')) => { self.consume_char()?; Operator::LogicalAnd }
+                        ('|', Some('|')) => { self.consume_char()?; Operator::LogicalOr }
+                        ('=', Some('=')) => { self.consume_char()?; Operator::Equal }
+                        ('!', Some('=')) => { self.consume_char()?; Operator::NotEqual }
+                        ('<', Some('=')) => { self.consume_char()?; Operator::LessEqual }
+                        ('>', Some('=')) => { self.consume_char()?; Operator::GreaterEqual }
+                        ('+', Some('=')) => { self.consume_char()?; Operator::PlusAssign }
+                        ('-', Some('=')) => { self.consume_char()?; Operator::MinusAssign }
+                        ('*', Some('=')) => { self.consume_char()?; Operator::TimesAssign }
+                        ('/', Some('=')) => { self.consume_char()?; Operator::DivideAssign }
+                        ('+', _) => Operator::Plus,
+                        ('-', _) => Operator::Minus,
+                        ('*', _) => Operator::Times,
+                        ('/', _) => Operator::Divide,
+                        ('&', _) => Operator::And,
+                        ('=', _) => Operator::Assign,
+                        ('<', _) => Operator::Less,
+                        ('>', _) => Operator::Greater,
+                        _ => bail!("Unknown char '{}' encountered!", char)
                     }
                 },
                 '(' => Token::OpenParent,
                 ')' => Token::ClosedParent,
                 '[' => Token::OpenBracket,
                 ']' => Token::ClosedBracket,
+                '.' if next == Some('.') => { self.consume_char()?; Token::DotDot }
+                '.' => Token::Dot,
                 _ => bail!("Unknown char encountered!")
             }
         )
@@ -85,35 +108,46 @@ impl<'a> Tokenizer<'a> {
 
     fn tokenize_identifier(&mut self) -> anyhow::Result<Token> {
         let literal: Literal = self.read_matching(|c| LITERAL_REGEX.is_match(&c.to_string())).iter()
-            .map(|c| *c as u8)
-            .collect();
+            .collect::<String>()
+            .into_bytes();
 
         Ok(
             match literal.as_slice() {
                 b"as" => Token::Operation {
                     operator: Operator::As
                 },
-                b"exit" | b"let" | b"print" => Token::Keyword {
+                b"exit" | b"let" | b"mut" | b"const" | b"print" => Token::Keyword {
                     keyword: match literal.as_slice() {
                         b"let" => Keyword::Let,
+                        b"mut" => Keyword::Mut,
+                        b"const" => Keyword::Const,
                         b"exit" => Keyword::Exit,
                         b"print" => Keyword::Print,
                         _ => unreachable!()
-                    }
+                    },
+                    span: self.token_span(),
                 },
-                b"u64" | b"u32" | b"u16" | b"u8" | b"char" => Token::Type {
+                b"true" | b"false" => Token::Literal {
+                    value: literal,
+                    type_: LiteralType::Boolean,
+                    span: self.token_span(),
+                },
+                b"u64" | b"u32" | b"u16" | b"u8" | b"char" | b"bool" | b"str" => Token::Type {
                     type_: match literal.as_slice() {
                         b"u64" => TypeType::U64,
                         b"u32" => TypeType::U32,
                         b"u16" => TypeType::U16,
                         b"u8" => TypeType::U8,
                         b"char" => TypeType::Char,
+                        b"bool" => TypeType::Bool,
+                        b"str" => TypeType::Str,
                         _ => unreachable!()
                     }
                 },
                 _ => Token::Literal {
                     value: literal,
                     type_: LiteralType::Identifier,
+                    span: self.token_span(),
                 }
             }
         )
@@ -121,13 +155,14 @@ impl<'a> Tokenizer<'a> {
 
     fn tokenize_number(&mut self) -> anyhow::Result<Token> {
         let literal: Literal = self.read_matching(|c| NUMBER_REGEX.is_match(&c.to_string())).iter()
-            .map(|c| *c as u8)
-            .collect();
+            .collect::<String>()
+            .into_bytes();
 
         Ok(
             Token::Literal {
                 value: literal,
                 type_: LiteralType::Number,
+                span: self.token_span(),
             }
         )
     }
@@ -137,15 +172,18 @@ impl<'a> Tokenizer<'a> {
         self.consume_char()?;
 
         let literal: Literal = self.read_matching(|c| c != &'\'').iter()
-            .map(|c| *c as u8)
-            .collect();
+            .collect::<String>()
+            .into_bytes();
 
         if self.consume_char()? != '\'' { bail!("Char didnt end with \"'\"!") }
+        if split_string_literal(&literal).len() != 1 { bail!("Char literal '{}' has to contain exactly one character!", literal_to_string(&literal)) }
+        literal_to_char(&literal)?;
 
         Ok(
             Token::Literal {
                 value: literal,
                 type_: LiteralType::Char,
+                span: self.token_span(),
             }
         )
     }
@@ -155,15 +193,19 @@ impl<'a> Tokenizer<'a> {
         self.consume_char()?;
 
         let literal: Literal = self.read_matching(|c| c != &'"').iter()
-            .map(|c| *c as u8)
-            .collect();
+            .collect::<String>()
+            .into_bytes();
 
         if self.consume_char()? != '"' { bail!("String didnt end with '\"'!") }
+        for char in split_string_literal(&literal) {
+            literal_to_char(&char)?;
+        }
 
         Ok(
             Token::Literal {
                 value: literal,
                 type_: LiteralType::String,
+                span: self.token_span(),
             }
         )
     }
@@ -173,7 +215,7 @@ impl<'a> Tokenizer<'a> {
         while self.input.peek().is_some() && predicate(self.input.peek().expect("THIS WILL NEVER OCCUR!")) {
             buffer.push(self.consume_char().expect("THIS WILL NEVER OCCUR!"));
         }
-        return buffer;
+        buffer
     }
 
     fn skip_ignorables(&mut self) {
@@ -182,8 +224,45 @@ impl<'a> Tokenizer<'a> {
         }
     }
 
+    fn token_span(&self) -> Span {
+        let len = if self.line == self.token_start.line { self.column - self.token_start.column } else { 1 };
+        Span { len, ..self.token_start }
+    }
+
     fn consume_char(&mut self) -> anyhow::Result<char> {
         if self.input.peek().is_none() { bail!("Tried to consume char but ran out of data!") }
-        return Ok(self.input.next().expect("THIS WILL NEVER OCCUR!"));
+        let char = self.input.next().expect("THIS WILL NEVER OCCUR!");
+        if char == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Ok(char)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn token(source: &str) -> anyhow::Result<Token> {
+        Tokenizer::new(source.chars().peekable()).next_token()
+    }
+
+    #[test]
+    fn escapes_stand_for_their_scalar_value() {
+        for (literal, value) in [("a", 'a'), ("é", 'é'), ("\\n", '\n'), ("\\0", '\0'), ("\\\\", '\\'), ("\\u{1F600}", '😀')] {
+            assert_eq!(literal_to_char(&literal.as_bytes().to_vec()).unwrap(), value as u64, "{}", literal);
+        }
+    }
+
+    #[test]
+    fn invalid_escapes_are_rejected() {
+        assert!(token("'\\x'").unwrap_err().to_string().contains("Unknown escape sequence"));
+        assert!(token("\"a\\qb\"").unwrap_err().to_string().contains("Unknown escape sequence"));
+        assert!(token("'\\u{d800}'").unwrap_err().to_string().contains("not a unicode scalar value"));
+        assert!(token("\"\\u{110000}\"").is_err());
+        assert!(token("\"tab\\t \\u{e9}\"").is_ok());
     }
 }
@@ -1,5 +1,7 @@
 use std::fmt::{Display, Formatter, Write};
+use anyhow::bail;
 use serde::{Deserialize, Serialize};
+use crate::diagnostic::Span;
 
 pub type Literal = Vec<u8>;
 
@@ -7,16 +9,73 @@ pub fn literal_to_string(literal: &Literal) -> String {
     String::from_utf8_lossy(literal.as_slice()).parse().unwrap()
 }
 
+/// The unicode scalar value of a char literal, fails for escape sequences that dont stand for one.
+pub fn literal_to_char(literal: &Literal) -> anyhow::Result<u64> {
+    Ok(match literal.as_slice() {
+        [b'\\', b'n'] => b'\n' as u64,
+        [b'\\', b't'] => b'\t' as u64,
+        [b'\\', b'r'] => b'\r' as u64,
+        [b'\\', b'0'] => 0,
+        [b'\\', escaped @ (b'\\' | b'\'' | b'"')] => *escaped as u64,
+        [b'\\', b'u', b'{', digits @ .., b'}'] => {
+            let scalar = std::str::from_utf8(digits).ok()
+                .and_then(|digits| u32::from_str_radix(digits, 16).ok())
+                .and_then(char::from_u32);
+            let Some(char) = scalar else { bail!("Escape sequence '{}' is not a unicode scalar value!", literal_to_string(literal)) };
+            char as u64
+        }
+        [b'\\', ..] => bail!("Unknown escape sequence '{}'!", literal_to_string(literal)),
+        _ => literal_to_string(literal).chars().next().map_or(0, |char| char as u64),
+    })
+}
+
+/// Splits a string literal into the literals of its chars, escape sequences stay together.
+pub fn split_string_literal(literal: &Literal) -> Vec<Literal> {
+    let mut chars = vec![];
+    let string = literal_to_string(literal);
+    let mut iter = string.chars().peekable();
+    while let Some(char) = iter.next() {
+        let mut current = String::from(char);
+        if char == '\\' {
+            match iter.next() {
+                Some('u') if iter.peek() == Some(&'{') => {
+                    current.push('u');
+                    for char in iter.by_ref() {
+                        current.push(char);
+                        if char == '}' { break; }
+                    }
+                }
+                Some(escaped) => current.push(escaped),
+                None => {}
+            }
+        }
+        chars.push(current.into_bytes());
+    }
+    chars
+}
+
+/// The UTF-8 bytes a string literal stands for once its escape sequences are replaced.
+pub fn string_literal_bytes(literal: &Literal) -> Vec<u8> {
+    split_string_literal(literal).iter()
+        .map(|char| literal_to_char(char).ok().and_then(|value| char::from_u32(value as u32)).expect("String literals are validated by the tokenizer!"))
+        .collect::<String>()
+        .into_bytes()
+}
+
 #[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
 pub enum Token {
     EOF,
     Ignored,
 
-    Keyword { keyword: Keyword },
+    Keyword {
+        keyword: Keyword,
+        span: Span,
+    },
 
     Literal {
         type_: LiteralType,
         value: Literal,
+        span: Span,
     },
 
     Type {
@@ -36,6 +95,9 @@ pub enum Token {
 
     OpenBracket,
     ClosedBracket,
+
+    Dot,
+    DotDot,
 }
 
 impl Display for Token {
@@ -43,11 +105,11 @@ impl Display for Token {
         match self {
             Token::EOF => f.write_str("eof"),
             Token::Ignored => f.write_str(""),
-            Token::Keyword { keyword } => {
+            Token::Keyword { keyword, .. } => {
                 f.write_str("keyword ").unwrap();
                 f.write_str(&keyword.to_string())
             },
-            Token::Literal { value, type_ } => {
+            Token::Literal { value, type_, .. } => {
                 f.write_str(&type_.to_string()).unwrap();
                 f.write_char(' ').unwrap();
                 f.write_str(&literal_to_string(value))
@@ -61,6 +123,8 @@ impl Display for Token {
             Token::ClosedParent => f.write_char(')'),
             Token::OpenBracket => f.write_char('['),
             Token::ClosedBracket => f.write_char(']'),
+            Token::Dot => f.write_char('.'),
+            Token::DotDot => f.write_str(".."),
         }
     }
 }
@@ -68,6 +132,8 @@ impl Display for Token {
 #[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
 pub enum Keyword {
     Let,
+    Mut,
+    Const,
     Exit,
     Print
 }
@@ -76,6 +142,8 @@ impl Display for Keyword {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
             Keyword::Let => f.write_str("let"),
+            Keyword::Mut => f.write_str("mut"),
+            Keyword::Const => f.write_str("const"),
             Keyword::Exit => f.write_str("exit"),
             Keyword::Print => f.write_str("print"),
         }
@@ -87,6 +155,7 @@ pub enum LiteralType {
     String,
     Char,
     Number,
+    Boolean,
     Identifier,
 }
 
@@ -96,6 +165,7 @@ impl Display for LiteralType {
             LiteralType::String => f.write_str("string"),
             LiteralType::Char => f.write_str("char"),
             LiteralType::Number => f.write_str("number"),
+            LiteralType::Boolean => f.write_str("bool"),
             LiteralType::Identifier => f.write_str("ident"),
         }
     }
@@ -108,6 +178,8 @@ pub enum TypeType {
     U16,
     U8,
     Char,
+    Bool,
+    Str,
 }
 
 impl Display for TypeType {
@@ -118,11 +190,13 @@ impl Display for TypeType {
             TypeType::U32 => f.write_str("u32"),
             TypeType::U16 => f.write_str("u16"),
             TypeType::U8 => f.write_str("u8"),
+            TypeType::Bool => f.write_str("bool"),
+            TypeType::Str => f.write_str("str"),
         }
     }
 }
 
-#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
 pub enum Operator {
     Plus,
     Minus,
@@ -131,6 +205,18 @@ pub enum Operator {
     And,
     Assign,
     As,
+    Equal,
+    NotEqual,
+    Less,
+    Greater,
+    LessEqual,
+    GreaterEqual,
+    LogicalAnd,
+    LogicalOr,
+    PlusAssign,
+    MinusAssign,
+    TimesAssign,
+    DivideAssign,
 }
 
 impl Display for Operator {
@@ -142,7 +228,19 @@ impl Display for Operator {
             Operator::Divide => f.write_char('/'),
             Operator::And => f.write_char('&'),
             Operator::Assign => f.write_char('='),
-            Operator::As => f.write_str("as")
+            Operator::As => f.write_str("as"),
+            Operator::Equal => f.write_str("=="),
+            Operator::NotEqual => f.write_str("!="),
+            Operator::Less => f.write_char('<'),
+            Operator::Greater => f.write_char('>'),
+            Operator::LessEqual => f.write_str("<="),
+            Operator::GreaterEqual => f.write_str(">="),
+            Operator::LogicalAnd => f.write_str("&&"),
+            Operator::LogicalOr => f.write_str("||"),
+            Operator::PlusAssign => f.write_str("+="),
+            Operator::MinusAssign => f.write_str("-="),
+            Operator::TimesAssign => f.write_str("*="),
+            Operator::DivideAssign => f.write_str("/="),
         }
     }
 }
\ No newline at end of file
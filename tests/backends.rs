@@ -0,0 +1,100 @@
+//! Runs scripts through every backend, each of them has to print the same and exit with the same code.
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+const LEVELS: [&str; 3] = ["-O0", "-O1", "-O2"];
+
+type Run = (String, Option<i32>);
+
+fn compile(arguments: &[&str]) -> Vec<u8> {
+    let output = Command::new(env!("CARGO_BIN_EXE_compiler_in_rust")).args(arguments).output().expect("The compiler can be started!");
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    output.stdout
+}
+
+/// A file in the scratch directory of the tests, named after the script and what it holds.
+fn scratch_path(script: &str, name: &str) -> PathBuf {
+    let stem = Path::new(script).file_stem().unwrap().to_str().unwrap();
+    Path::new(env!("CARGO_TARGET_TMPDIR")).join(format!("{}{}", stem, name))
+}
+
+fn result(output: Output) -> Run {
+    (String::from_utf8(output.stdout).expect("The program prints UTF-8!"), output.status.code())
+}
+
+/// Tools outside of Rust are skipped when they arent installed.
+fn available(tool: &str) -> bool {
+    let available = Command::new(tool).arg("--version").output().is_ok_and(|output| output.status.success());
+    if !available { eprintln!("skipping the backend needing `{}`, it isnt installed", tool); }
+    available
+}
+
+fn run_asm(script: &str, level: &str) -> Run {
+    let assembly = scratch_path(script, &format!("{}.s", level));
+    let object = scratch_path(script, &format!("{}.o", level));
+    let executable = scratch_path(script, &format!("{}_asm", level));
+    std::fs::write(&assembly, compile(&[script, level, "--emit=asm"])).unwrap();
+    assert!(Command::new("as").arg(&assembly).arg("-o").arg(&object).status().unwrap().success());
+    assert!(Command::new("ld").arg(&object).arg("-o").arg(&executable).status().unwrap().success());
+    result(Command::new(&executable).output().unwrap())
+}
+
+fn run_vm(script: &str, level: &str) -> Run {
+    let bytecode = scratch_path(script, &format!("{}.hbc", level));
+    std::fs::write(&bytecode, compile(&[script, level, "--emit=bytecode"])).unwrap();
+    result(Command::new(env!("CARGO_BIN_EXE_compiler_in_rust")).arg(&bytecode).output().unwrap())
+}
+
+fn run_c(script: &str) -> Run {
+    let source = scratch_path(script, ".c");
+    let executable = scratch_path(script, "_c");
+    std::fs::write(&source, compile(&[script, "--emit=c"])).unwrap();
+    assert!(Command::new("cc").arg(&source).arg("-o").arg(&executable).status().unwrap().success());
+    result(Command::new(&executable).output().unwrap())
+}
+
+fn run_wasm(script: &str, level: &str) -> Run {
+    let module = scratch_path(script, &format!("{}.wasm", level));
+    std::fs::write(&module, compile(&[script, level, "--emit=wasm"])).unwrap();
+    result(Command::new("node").arg("tests/res/wasi.cjs").arg(&module).output().unwrap())
+}
+
+fn run_natively(script: &str, level: &str) -> Run {
+    let output = Command::new(env!("CARGO_BIN_EXE_compiler_in_rust")).args([script, level, "--run"]).output().unwrap();
+    result(output)
+}
+
+fn check(script: &str, printed: &str, code: i32) {
+    let expected = (printed.to_string(), Some(code));
+    let native = cfg!(all(target_arch = "x86_64", target_os = "linux"));
+    for level in LEVELS {
+        if native && available("as") && available("ld") {
+            assert_eq!(run_asm(script, level), expected, "{} as assembly at {}", script, level);
+        }
+        assert_eq!(run_vm(script, level), expected, "{} as bytecode at {}", script, level);
+        if available("node") {
+            assert_eq!(run_wasm(script, level), expected, "{} as WebAssembly at {}", script, level);
+        }
+        if native {
+            assert_eq!(run_natively(script, level), expected, "{} with --run at {}", script, level);
+        }
+    }
+    if available("cc") {
+        assert_eq!(run_c(script), expected, "{} as C", script);
+    }
+}
+
+#[test]
+fn backends_agree() {
+    check("tests/res/backends.he", "4\n4294967294\n142\ntrue\nworld\n\n6\ntrue\né\n", 7);
+}
+
+#[test]
+fn stores_through_a_changed_pointer_arent_lost() {
+    check("tests/res/aliasing.he", "1\n2\n", 0);
+}
+
+#[test]
+fn division_by_zero_exits_with_its_code() {
+    check("tests/res/division_by_zero.he", "1\n1\n", 102);
+}
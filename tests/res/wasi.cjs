@@ -0,0 +1,7 @@
+// Runs a WASI module passed as the first argument and exits with its exit code.
+const { WASI } = require('node:wasi');
+const fs = require('fs');
+const wasi = new WASI({ version: 'preview1', returnOnExit: true });
+const compiled = new WebAssembly.Module(fs.readFileSync(process.argv[2]));
+const instance = new WebAssembly.Instance(compiled, wasi.getImportObject());
+process.exitCode = wasi.start(instance);